@@ -0,0 +1,1175 @@
+use std::cell::{Cell, OnceCell, RefCell};
+use std::fs;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use async_channel::{Receiver, Sender};
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use gtk4::{gdk, Application, ApplicationWindow, CssProvider, Image, Label, LevelBar, Spinner};
+use gtk4_layer_shell::LayerShell;
+use nix::sys::signal::Signal;
+use nix::unistd::Pid;
+use tracing::*;
+
+use crate::audio::{self, GainCheck, LevelMonitor};
+use crate::audit;
+use crate::clipboard;
+use crate::config::{Config, Preset, Setting, Theme, EMPTY_MARKER_VAR, PENDING_VAR};
+use crate::event::{self, AppEvent, Debounce, Effect, Ending, Inactivity, Session, SessionState};
+use crate::overlay::{self, Overlay};
+use crate::portal;
+use crate::ipc::ControlSocket;
+use crate::layout;
+use crate::locale::TimeFormat;
+use crate::notify::{self, Notifier};
+use crate::process::{self, ChildOutput, ChildProcess, LineSink};
+use crate::registry::{self, Instance, Registration, Registry};
+
+/// How long the panic combo stays armed when `--confirm-panic` is set
+const PANIC_CONFIRM_WINDOW: Duration = Duration::from_secs(1);
+
+/// How long `--preview` keeps the transcription on screen after the command exits
+const PREVIEW_DURATION: Duration = Duration::from_secs(2);
+
+/// How often a detached wait checks whether the command has exited
+const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the error state stays on screen after the command fails
+const ERROR_DURATION: Duration = Duration::from_secs(4);
+
+/// All state shared between the GTK handlers, behind a single `Rc`.
+///
+/// Handlers only [`post`](AppModel::post) [`AppEvent`]s; a single loop feeds them to the
+/// [`Session`] reducer and carries out the resulting [`Effect`]s.
+pub struct AppModel {
+    config: Config,
+    session: RefCell<Session>,
+    child: RefCell<Option<ChildProcess>>,
+    child_pid: Cell<Option<Pid>>,
+    sender: Sender<AppEvent>,
+    receiver: RefCell<Option<Receiver<AppEvent>>>,
+    window: glib::WeakRef<ApplicationWindow>,
+    icon: OnceCell<Image>,
+    spinner: OnceCell<Spinner>,
+    preview: OnceCell<Label>,
+    error: OnceCell<Label>,
+    status: OnceCell<Label>,
+    caption: OnceCell<Label>,
+    registration: RefCell<Option<Registration>>,
+    debounce: RefCell<Debounce<AppEvent>>,
+    control: RefCell<Option<ControlSocket>>,
+    /// Portal subscription for `--theme auto`
+    color_scheme_watch: RefCell<Option<gio::SignalSubscription>>,
+    /// The configuration as changed through the control socket since startup
+    live: RefCell<Config>,
+    live_css: OnceCell<CssProvider>,
+    output: RefCell<Option<ChildOutput>>,
+    started: Cell<Option<Instant>>,
+    /// Time spent recording (not paused) by the current child, for the timer
+    recorded: Cell<Duration>,
+    preview_sender: Sender<String>,
+    preview_lines: RefCell<Option<Receiver<String>>>,
+    /// Last state shown, so that the timer only counts while recording
+    state: Cell<SessionState>,
+    meter: RefCell<Option<LevelMonitor>>,
+    /// Copies of the overlay on the other outputs (`--all-outputs` only)
+    mirrors: RefCell<Vec<Overlay>>,
+    /// Translucent surfaces behind the overlay on every output (`--dim-screen` only)
+    dims: RefCell<Vec<ApplicationWindow>>,
+    /// Exit codes of stopped children, for waiting without the event loop once detached
+    exit_sender: mpsc::Sender<i32>,
+    exits: RefCell<Option<mpsc::Receiver<i32>>>,
+    /// Timers and tasks working for the session, cancelled by [`shutdown`](AppModel::shutdown)
+    sources: RefCell<Vec<glib::SourceId>>,
+    tasks: RefCell<Vec<glib::JoinHandle<()>>>,
+    /// Created by the command when it transcribed nothing (`--skip-empty` only)
+    empty_marker: RefCell<Option<PathBuf>>,
+    /// Holds the transcription until it may replace the clipboard (`--confirm-overwrite` only)
+    pending: RefCell<Option<PathBuf>>,
+    /// Desktop notifications (`--notify` only)
+    notifier: Option<Box<dyn Notifier>>,
+    /// When the overlay last saw speech or a key press (`--auto-hide` only)
+    inactivity: RefCell<Option<Inactivity>>,
+    /// The detached session's transcription went where the command sends it
+    delivered_detached: Cell<bool>,
+}
+
+impl AppModel {
+    pub fn new(config: Config) -> Self {
+        let (sender, receiver) = async_channel::unbounded();
+        let (exit_sender, exits) = mpsc::channel();
+        let (preview_sender, preview_lines) = async_channel::unbounded();
+        Self {
+            session: RefCell::new(Session::new(config.confirm_panic, config.on_close)),
+            live: RefCell::new(config.clone()),
+            debounce: RefCell::new(Debounce::new(Duration::from_millis(config.debounce))),
+            notifier: config.notify.map(notify::notifier),
+            inactivity: RefCell::new(None),
+            delivered_detached: Cell::new(false),
+            config,
+            child: RefCell::new(None),
+            child_pid: Cell::new(None),
+            sender,
+            receiver: RefCell::new(Some(receiver)),
+            window: glib::WeakRef::new(),
+            icon: OnceCell::new(),
+            spinner: OnceCell::new(),
+            preview: OnceCell::new(),
+            error: OnceCell::new(),
+            status: OnceCell::new(),
+            caption: OnceCell::new(),
+            registration: RefCell::new(None),
+            control: RefCell::new(None),
+            sources: RefCell::new(Vec::new()),
+            tasks: RefCell::new(Vec::new()),
+            empty_marker: RefCell::new(None),
+            pending: RefCell::new(None),
+            color_scheme_watch: RefCell::new(None),
+            live_css: OnceCell::new(),
+            output: RefCell::new(None),
+            started: Cell::new(None),
+            recorded: Cell::new(Duration::ZERO),
+            preview_sender,
+            preview_lines: RefCell::new(Some(preview_lines)),
+            state: Cell::new(SessionState::Recording),
+            meter: RefCell::new(None),
+            mirrors: RefCell::new(Vec::new()),
+            dims: RefCell::new(Vec::new()),
+            exit_sender,
+            exits: RefCell::new(Some(exits)),
+        }
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.session.borrow().exit_code()
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.session.borrow().is_finished()
+    }
+
+    pub fn ending(&self) -> Ending {
+        self.session.borrow().ending()
+    }
+
+    /// The state the overlay shows
+    pub fn state(&self) -> SessionState {
+        self.state.get()
+    }
+
+    /// Whether the `copy` preset put a transcription on the clipboard, with the session ending
+    /// with `code`: not with nothing transcribed, nor with the transcription thrown away instead
+    /// of replacing the clipboard
+    pub fn copied(&self, code: i32) -> bool {
+        if self.config.preset != Some(Preset::Copy) || code != 0 {
+            return false;
+        }
+        match self.ending() {
+            Ending::Stopped(_) => self.state.get() != SessionState::Empty,
+            Ending::Detached => self.delivered_detached.get(),
+            _ => false,
+        }
+    }
+
+    /// Time since the session started, if it did
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started.get().map(|started| started.elapsed())
+    }
+
+    /// Finish without a running session (e.g. the child failed to spawn)
+    pub fn abort(&self, code: i32) {
+        self.session.borrow_mut().abort(code);
+    }
+
+    /// Record this instance in the runtime registry and listen for settings next to it, until
+    /// the model is dropped or the wrapper exits, whichever comes first
+    pub fn register(&self) {
+        let registry = match Registry::open() {
+            Ok(registry) => registry,
+            Err(e) => {
+                warn!(error = %e, "Failed to register instance");
+                return;
+            }
+        };
+        let instance = Instance::current();
+        let sender = self.sender.clone();
+        let on_setting = move |setting| {
+            let _ = sender.try_send(AppEvent::SettingChanged(setting));
+        };
+        match ControlSocket::bind(registry.socket_path(instance.pid), on_setting) {
+            Ok(control) => *self.control.borrow_mut() = Some(control),
+            Err(e) => warn!(error = %e, "Failed to open the control socket"),
+        }
+        // Inherited by the command and whatever it pipes the transcription to. One left behind by
+        // an earlier process with the same pid would pass for this one's.
+        let export = |var: &str, path: PathBuf| {
+            let _ = fs::remove_file(&path);
+            std::env::set_var(var, &path);
+            registry::track_artifact(path.clone());
+            Some(path)
+        };
+        if self.config.skip_empty {
+            let marker = registry.empty_marker_path(instance.pid);
+            *self.empty_marker.borrow_mut() = export(EMPTY_MARKER_VAR, marker);
+        }
+        if self.config.confirm_overwrite {
+            let pending = registry.pending_path(instance.pid);
+            *self.pending.borrow_mut() = export(PENDING_VAR, pending);
+        }
+        match registry.register(instance) {
+            Ok(registration) => *self.registration.borrow_mut() = Some(registration),
+            Err(e) => warn!(error = %e, "Failed to register instance"),
+        }
+    }
+
+    /// Follow the desktop's dark/light preference with `--theme auto`, until the model is dropped
+    pub fn watch_color_scheme(&self) {
+        if self.config.theme != Theme::Auto {
+            return;
+        }
+        let sender = self.sender.clone();
+        *self.color_scheme_watch.borrow_mut() = portal::watch_color_scheme(move |scheme| {
+            let _ = sender.try_send(AppEvent::ColorSchemeChanged(scheme));
+        });
+    }
+
+    /// Wait for the child left stopping by a detach, now that the overlay is gone. Bounded by the
+    /// kill timeout, after which it is killed.
+    pub fn wait_detached(&self) -> i32 {
+        let Some(exits) = self.exits.borrow_mut().take() else {
+            return 1;
+        };
+        let exits = Rc::new(exits);
+        let timeout = Duration::from_secs(self.config.kill_timeout);
+        if let Some(code) = recv_on_main_loop(&exits, timeout) {
+            // Without the overlay to ask on, rather than lose it
+            let mut delivered = code == 0;
+            if let Some(pending) = self.pending.borrow().as_deref().filter(|path| path.exists()) {
+                warn!("The clipboard holds something, writing the transcription to stdout instead");
+                if let Ok(text) = fs::read(pending) {
+                    let _ = std::io::stdout().write_all(&text);
+                }
+                let _ = fs::remove_file(pending);
+                delivered = false;
+            }
+            if code == 0 && take_empty_marker(self.empty_marker.borrow().as_deref()) {
+                return self.config.empty_exit_code.unwrap_or(0);
+            }
+            self.delivered_detached.set(delivered);
+            return code;
+        }
+        warn!("Detached child did not exit after the kill timeout, killing it");
+        if let Some(pid) = self.child_pid.get() {
+            let _ = process::send_signal(pid, Signal::SIGKILL);
+        }
+        recv_on_main_loop(&exits, process::ESCALATION_GRACE).unwrap_or(1)
+    }
+
+    /// Take over the child's piped output, once the session is over
+    pub fn take_output(&self) -> Option<ChildOutput> {
+        self.output.borrow_mut().take()
+    }
+
+    /// Post an event to the event bus. Repeated stop, pause and detach requests are coalesced.
+    pub fn post(&self, event: AppEvent) {
+        let request = matches!(
+            event,
+            AppEvent::StopRequested
+                | AppEvent::Clicked
+                | AppEvent::PauseRequested
+                | AppEvent::DetachRequested
+        );
+        if request && !self.debounce.borrow_mut().accept(event.clone(), Instant::now()) {
+            debug!(?event, "Ignoring repeated request");
+            return;
+        }
+        debug!(?event, "Posting event");
+        // Unbounded, and the receiver lives as long as the model
+        let _ = self.sender.try_send(event);
+    }
+
+    /// Take over the spawned child and the overlay showing it, and start consuming events
+    pub fn attach(self: &Rc<Self>, mut child: ChildProcess, overlay: &Overlay) {
+        self.child_pid.set(Some(child.pid()));
+        self.started.set(Some(Instant::now()));
+        *self.output.borrow_mut() = child.take_output();
+        *self.child.borrow_mut() = Some(child);
+        self.window.set(Some(&overlay.window));
+        let _ = self.icon.set(overlay.icon.clone());
+        let _ = self.spinner.set(overlay.spinner.clone());
+        let _ = self.error.set(overlay.error.clone());
+        let _ = self.status.set(overlay.status.clone());
+        let _ = self.caption.set(overlay.caption.clone());
+        self.set_state(SessionState::Recording);
+        if self.config.show_timer || self.config.max_duration.is_some() {
+            self.start_timer(overlay.timer.clone());
+        }
+        // Also what tells --auto-hide that someone is speaking
+        if self.config.level_meter || self.config.auto_hide.is_some() {
+            self.start_meter(overlay.level.clone(), overlay.gain.clone());
+        }
+        if let Some(secs) = self.config.auto_hide {
+            self.start_auto_hide(Duration::from_secs(secs));
+        }
+
+        // Nothing is left to act on once the window is gone, however early that happens
+        let model = self.clone();
+        overlay.window.connect_destroy(move |_| model.shutdown());
+
+        let Some(receiver) = self.receiver.borrow_mut().take() else {
+            return;
+        };
+        let model = self.clone();
+        self.spawn(async move {
+            while let Ok(event) = receiver.recv().await {
+                model.dispatch(event);
+            }
+        });
+    }
+
+    /// Keep `source` until it is done or the model shuts down
+    pub fn guard(&self, source: glib::SourceId) {
+        let context = glib::MainContext::ref_thread_default();
+        let mut sources = self.sources.borrow_mut();
+        sources.retain(|id| context.find_source_by_id(id).is_some());
+        sources.push(source);
+    }
+
+    /// Run `future` on the main loop until it finishes or the model shuts down
+    pub fn spawn(&self, future: impl Future<Output = ()> + 'static) {
+        let mut tasks = self.tasks.borrow_mut();
+        tasks.retain(|task| !task.source().is_destroyed());
+        tasks.push(glib::spawn_future_local(future));
+    }
+
+    /// Cancel every pending timer and task, stop the level meter and close the mirrors and
+    /// dimming surfaces, so that no callback acts on the session after its window is gone. Safe
+    /// to call more than once.
+    pub fn shutdown(&self) {
+        debug!("Shutting down the session's timers and tasks");
+        for task in self.tasks.take() {
+            task.abort();
+        }
+        let context = glib::MainContext::ref_thread_default();
+        for id in self.sources.take() {
+            if let Some(source) = context.find_source_by_id(&id) {
+                source.destroy();
+            }
+        }
+        self.meter.borrow_mut().take();
+        self.close_mirrors();
+        self.close_dims();
+    }
+
+    /// Keep a mirror of the overlay on each of `monitors` other than the main overlay's, closing
+    /// the mirrors of monitors that are gone
+    pub fn sync_mirrors(&self, app: &Application, monitors: &[gdk::Monitor]) {
+        let Some(window) = self.window.upgrade() else {
+            return;
+        };
+        let mut mirrors = self.mirrors.borrow_mut();
+        mirrors.retain(|mirror| {
+            let kept = LayerShell::monitor(&mirror.window).is_some_and(|m| monitors.contains(&m));
+            if !kept {
+                mirror.window.close();
+            }
+            kept
+        });
+
+        let mut covered: Vec<gdk::Monitor> = mirrors
+            .iter()
+            .filter_map(|mirror| LayerShell::monitor(&mirror.window))
+            .collect();
+        covered.extend(LayerShell::monitor(&window));
+        for monitor in monitors.iter().filter(|monitor| !covered.contains(monitor)) {
+            let mirror = overlay::create_mirror_window(app, &self.config, monitor);
+            // Without keyboard input, clicking is the only way to stop from a mirror
+            let sender = self.sender.clone();
+            overlay::connect_click(&mirror.window, move || {
+                let _ = sender.try_send(AppEvent::Clicked);
+            });
+            mirror.show_state(&self.live.borrow(), self.state.get());
+            mirror.window.present();
+            mirrors.push(mirror);
+        }
+    }
+
+    /// Keep a dimming surface on each of `monitors`, closing those of monitors that are gone. They
+    /// are only shown while recording.
+    pub fn sync_dims(&self, app: &Application, monitors: &[gdk::Monitor]) {
+        let mut dims = self.dims.borrow_mut();
+        dims.retain(|dim| {
+            let kept = LayerShell::monitor(dim).is_some_and(|m| monitors.contains(&m));
+            if !kept {
+                dim.close();
+            }
+            kept
+        });
+
+        let covered: Vec<gdk::Monitor> = dims.iter().filter_map(LayerShell::monitor).collect();
+        for monitor in monitors.iter().filter(|monitor| !covered.contains(monitor)) {
+            let Some(dim) = overlay::create_dim_window(app, &self.config, monitor) else {
+                warn!("Nothing can be dimmed behind an overlay on the background layer");
+                return;
+            };
+            dim.set_visible(self.state.get() == SessionState::Recording);
+            dims.push(dim);
+        }
+    }
+
+    /// Close the dimming surfaces, which like the mirrors would keep the application running
+    pub fn close_dims(&self) {
+        for dim in self.dims.take() {
+            dim.close();
+        }
+    }
+
+    fn animation_duration(&self) -> Duration {
+        Duration::from_millis(self.config.animation_duration)
+    }
+
+    /// Fade the overlay out with `--animation-duration`, then close it
+    fn fade_out(&self, window: &ApplicationWindow) {
+        overlay::fade_out(window, self.animation_duration());
+    }
+
+    /// Close the mirrors along with the main overlay, which would otherwise keep the application
+    /// running
+    pub fn close_mirrors(&self) {
+        for mirror in self.mirrors.take() {
+            mirror.window.close();
+        }
+    }
+
+    /// Update `label` every second with the recording time (skipping time spent paused) and the
+    /// countdown to `--max-duration`, until the recording stops
+    fn start_timer(self: &Rc<Self>, label: Label) {
+        let time = TimeFormat::from_env();
+        let model = self.clone();
+        let update = move |recorded: Duration| {
+            let elapsed = model.elapsed().unwrap_or_default();
+            let remaining = model
+                .config
+                .max_duration
+                .map(|max| Duration::from_secs(max).saturating_sub(elapsed));
+            let recorded = model.config.show_timer.then_some(recorded);
+            label.set_text(&event::timer_text(recorded, remaining, time));
+        };
+        update(self.recorded.get());
+
+        let model = self.clone();
+        self.guard(glib::timeout_add_seconds_local(1, move || match model.state.get() {
+            SessionState::Recording => {
+                model.recorded.set(model.recorded.get() + Duration::from_secs(1));
+                update(model.recorded.get());
+                glib::ControlFlow::Continue
+            }
+            SessionState::Paused | SessionState::PanicWarning => {
+                update(model.recorded.get());
+                glib::ControlFlow::Continue
+            }
+            SessionState::Processing
+            | SessionState::Error
+            | SessionState::Empty
+            | SessionState::ConfirmOverwrite => glib::ControlFlow::Break,
+        }));
+    }
+
+    /// Fade the overlay out of the way after `timeout` of recording without activity
+    fn start_auto_hide(self: &Rc<Self>, timeout: Duration) {
+        *self.inactivity.borrow_mut() = Some(Inactivity::new(timeout, Instant::now()));
+        let model = self.clone();
+        self.guard(glib::timeout_add_seconds_local(1, move || {
+            if model.is_finished() {
+                model.note_activity();
+                return glib::ControlFlow::Break;
+            }
+            // Anything but recording is worth seeing
+            if model.state.get() != SessionState::Recording {
+                model.note_activity();
+                return glib::ControlFlow::Continue;
+            }
+            let mut inactivity = model.inactivity.borrow_mut();
+            if inactivity.as_mut().is_some_and(|inactivity| inactivity.check(Instant::now())) {
+                info!("No activity, fading the overlay out");
+                model.set_idle(true);
+            }
+            glib::ControlFlow::Continue
+        }));
+    }
+
+    /// Bring back an overlay faded out by `--auto-hide`, and start its timeout over
+    pub fn note_activity(&self) {
+        let woke = self
+            .inactivity
+            .borrow_mut()
+            .as_mut()
+            .is_some_and(|inactivity| inactivity.active(Instant::now()));
+        if woke {
+            self.set_idle(false);
+        }
+    }
+
+    fn set_idle(&self, idle: bool) {
+        let mut windows: Vec<ApplicationWindow> = self.window.upgrade().into_iter().collect();
+        windows.extend(self.mirrors.borrow().iter().map(|mirror| mirror.window.clone()));
+        for window in windows {
+            if idle {
+                window.add_css_class(layout::IDLE_CLASS);
+            } else {
+                window.remove_css_class(layout::IDLE_CLASS);
+            }
+        }
+    }
+
+    /// Spawn the command, forwarding its stdout lines to the preview with `--preview`
+    pub fn spawn_child(&self) -> process::Result<ChildProcess> {
+        let on_line: Option<LineSink> = self.config.preview.then(|| {
+            let sender = self.preview_sender.clone();
+            Box::new(move |line| {
+                let _ = sender.try_send(line);
+            }) as LineSink
+        });
+        ChildProcess::spawn(
+            &self.config.command,
+            self.config.child_stdout,
+            self.config.child_stderr,
+            on_line,
+            self.config.clean_env.then(|| process::clean_env(&self.config.keep_env)),
+            // Recording before GTK is up, which can exit the process on its own
+            self.config.spawn_first,
+        )
+    }
+
+    /// Show the lines the command prints to stdout on the overlay's preview label
+    pub fn show_preview(&self, label: Label) {
+        let Some(lines) = self.preview_lines.borrow_mut().take() else {
+            return;
+        };
+        let _ = self.preview.set(label.clone());
+        self.spawn(async move {
+            let mut text = String::new();
+            while let Ok(line) = lines.recv().await {
+                if !text.is_empty() {
+                    text.push('\n');
+                }
+                text.push_str(&line);
+                label.set_text(text.trim());
+                label.set_visible(!text.trim().is_empty());
+            }
+        });
+    }
+
+    /// Show the microphone input level on `bar` until the recording stops, and warn on
+    /// `warning` about a misconfigured input gain
+    fn start_meter(self: &Rc<Self>, bar: LevelBar, warning: Label) {
+        // Small, so that a stalled main loop drops stale levels rather than queueing them
+        let (sender, levels) = async_channel::bounded(4);
+        match LevelMonitor::spawn(move |level| {
+            let _ = sender.try_send(level);
+        }) {
+            Ok(meter) => *self.meter.borrow_mut() = Some(meter),
+            Err(e) => {
+                warn!(error = %e, "Failed to start the level meter, is pw-record installed?");
+                bar.set_visible(false);
+                return;
+            }
+        }
+        let model = self.clone();
+        self.spawn(async move {
+            let mut gain = GainCheck::default();
+            while let Ok(level) = levels.recv().await {
+                bar.set_value(level);
+                if audio::is_sound(level) {
+                    model.note_activity();
+                }
+                // Silence is expected while paused
+                if !model.config.level_meter || model.state.get() != SessionState::Recording {
+                    continue;
+                }
+                if let Some(change) = gain.update(level) {
+                    if let Some(change) = change {
+                        warn!(?change, "Input gain looks misconfigured");
+                    }
+                    warning.set_text(change.map(|change| change.text()).unwrap_or_default());
+                    warning.set_visible(change.is_some());
+                }
+            }
+            bar.set_value(0.0);
+            warning.set_visible(false);
+        });
+    }
+
+    /// Run `event` through the reducer and carry out the resulting effects
+    fn dispatch(&self, event: AppEvent) {
+        let effects = self.session.borrow_mut().reduce(event.clone());
+        debug!(?event, ?effects, "Dispatched event");
+        for effect in effects {
+            self.apply(effect);
+        }
+    }
+
+    fn apply(&self, effect: Effect) {
+        match effect {
+            Effect::StopChild => self.stop_child(),
+            Effect::SignalChild(signal) => self.signal_child(signal),
+            Effect::TogglePause => self.toggle_pause(),
+            Effect::CancelChild => self.cancel_child(),
+            Effect::CopyPending => {
+                if let Some(pending) = self.pending.borrow().as_deref() {
+                    if let Err(e) = clipboard::copy_from(pending) {
+                        error!(error = %e, "Failed to copy the transcription");
+                    }
+                    let _ = fs::remove_file(pending);
+                }
+            }
+            Effect::DiscardPending => {
+                info!("Keeping the clipboard, discarding the transcription");
+                if let Some(pending) = self.pending.borrow().as_deref() {
+                    let _ = fs::remove_file(pending);
+                }
+            }
+            Effect::RestartChild => self.restart_child(),
+            Effect::Detach => {
+                info!("Detaching, the transcription finishes in the background");
+                if let Some(registration) = self.registration.borrow_mut().as_mut() {
+                    registration.set_detached();
+                }
+                if let Some(window) = self.window.upgrade() {
+                    self.fade_out(&window);
+                }
+            }
+            Effect::ApplySetting(setting) => self.apply_setting(&setting),
+            Effect::FollowColorScheme(scheme) => {
+                info!(?scheme, "Desktop color scheme changed");
+                self.live.borrow_mut().follow_color_scheme(scheme);
+                self.restyle();
+            }
+            Effect::KillAll => self.panic_exit(),
+            Effect::ArmKillTimer => {
+                self.arm_kill_timer(Duration::from_secs(self.config.kill_timeout))
+            }
+            Effect::ArmEscalationTimer => self.arm_kill_timer(process::ESCALATION_GRACE),
+            Effect::ArmPanicConfirmTimer => {
+                let sender = self.sender.clone();
+                self.guard(glib::timeout_add_local_once(PANIC_CONFIRM_WINDOW, move || {
+                    let _ = sender.try_send(AppEvent::PanicConfirmExpired);
+                }));
+            }
+            Effect::SetState(state) => self.set_state(state),
+            Effect::CloseWindow => {
+                self.notify_completion();
+                let Some(window) = self.window.upgrade() else {
+                    return;
+                };
+                let previewing = self.exit_code() == 0
+                    && self.preview.get().is_some_and(|preview| !preview.text().is_empty());
+                let linger = match self.state.get() {
+                    SessionState::Error | SessionState::Empty => ERROR_DURATION,
+                    _ if previewing => PREVIEW_DURATION,
+                    _ => Duration::ZERO,
+                };
+                if linger.is_zero() {
+                    self.fade_out(&window);
+                } else {
+                    let duration = self.animation_duration();
+                    self.guard(glib::timeout_add_local_once(linger, move || {
+                        overlay::fade_out(&window, duration)
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Show `state` on the overlay and its mirrors
+    fn show_state(&self, state: SessionState) {
+        let live = self.live.borrow();
+        if let (Some(icon), Some(spinner), Some(status)) =
+            (self.icon.get(), self.spinner.get(), self.status.get())
+        {
+            overlay::show_state(&live, icon, spinner, status, state);
+        }
+        for mirror in self.mirrors.borrow().iter() {
+            mirror.show_state(&live, state);
+        }
+        // Dictation mode only lasts while recording; the dimming fades in again on resume
+        let recording = state == SessionState::Recording;
+        for dim in self.dims.borrow().iter().filter(|dim| dim.is_visible() != recording) {
+            dim.remove_css_class(layout::SHOWN_CLASS);
+            dim.set_visible(recording);
+        }
+    }
+
+    /// Change a setting on the live overlay: the icon, caption, placement or colors
+    fn apply_setting(&self, setting: &Setting) {
+        self.live.borrow_mut().apply(setting);
+        self.restyle();
+    }
+
+    /// Redraw the overlay and its mirrors with the live configuration
+    fn restyle(&self) {
+        let Some(window) = self.window.upgrade() else {
+            return;
+        };
+        let provider = self.live_css.get_or_init(|| {
+            let provider = CssProvider::new();
+            // Over the stylesheet generated at startup, but under the user's
+            gtk4::style_context_add_provider_for_display(
+                &WidgetExt::display(&window),
+                &provider,
+                gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION + 1,
+            );
+            provider
+        });
+        let live = self.live.borrow();
+        overlay::restyle(&window, &live, provider);
+        if let Some(caption) = self.caption.get() {
+            overlay::show_caption(caption, live.label.as_deref());
+        }
+        for mirror in self.mirrors.borrow().iter() {
+            overlay::restyle(&mirror.window, &live, provider);
+            overlay::show_caption(&mirror.caption, live.label.as_deref());
+        }
+        drop(live);
+        self.show_state(self.state.get());
+    }
+
+    /// Reflect the session state in the overlay and the registry
+    fn set_state(&self, state: SessionState) {
+        self.state.set(state);
+        if matches!(
+            state,
+            SessionState::Processing
+                | SessionState::Error
+                | SessionState::Empty
+                | SessionState::ConfirmOverwrite
+        ) {
+            self.meter.borrow_mut().take();
+        }
+        if let Some(registration) = self.registration.borrow_mut().as_mut() {
+            registration.set_state(state);
+        }
+        self.show_state(state);
+        if state == SessionState::Error {
+            self.show_error();
+        }
+        if self.config.notify_progress {
+            if let (Some(notifier), Some(notice)) = (&self.notifier, notify::progress(state)) {
+                notifier.show(&notice);
+            }
+        }
+        if self.config.bell {
+            if let Some(window) = self.window.upgrade() {
+                WidgetExt::display(&window).beep();
+            }
+        }
+    }
+
+    /// Explain the failure on the overlay
+    fn show_error(&self) {
+        let Some(label) = self.error.get() else {
+            return;
+        };
+        label.set_text(&self.error_text());
+        label.set_visible(true);
+    }
+
+    /// What went wrong: the exit code and the end of the captured stderr
+    fn error_text(&self) -> String {
+        let program = self.config.command.first().map(String::as_str).unwrap_or_default();
+        let mut lines = vec![format!("{program} exited with code {}", self.exit_code())];
+        if let Some(output) = self.output.borrow().as_ref() {
+            lines.extend(output.stderr_tail(self.config.error_lines));
+        }
+        lines.join("\n")
+    }
+
+    /// Tell how the session ended with `--notify`, with the transcription if it was previewed
+    fn notify_completion(&self) {
+        let Some(notifier) = &self.notifier else {
+            return;
+        };
+        let state = self.state.get();
+        let transcript = self.preview.get().map(|preview| preview.text()).unwrap_or_default();
+        let error = if state == SessionState::Error { self.error_text() } else { String::new() };
+        let notice =
+            notify::completion(state, self.exit_code(), self.config.preset, &transcript, &error);
+        match notice {
+            Some(notice) => notifier.show(&notice),
+            // Nothing to tell, but the progress notice shouldn't stay up
+            None if self.config.notify_progress => notifier.withdraw(),
+            None => {}
+        }
+    }
+
+    /// Ask the child to stop and wait for it in the background
+    fn stop_child(&self) {
+        let Some(child) = self.child.borrow_mut().take() else {
+            return;
+        };
+        if let Err(e) = child.send_sigusr1() {
+            warn!(error = %e, "Failed to send SIGUSR1");
+        }
+
+        let sender = self.sender.clone();
+        let exit_sender = self.exit_sender.clone();
+        let empty_marker = self.empty_marker.borrow().clone();
+        let empty_exit_code = self.config.empty_exit_code.unwrap_or(0);
+        let pending = self.pending.borrow().clone();
+        self.spawn(async move {
+            let result = gio::spawn_blocking(move || {
+                let code = match child.wait() {
+                    Ok(status) => {
+                        let code = status.code().unwrap_or(1);
+                        info!(exit_code = code, "Child process exited");
+                        code
+                    }
+                    Err(e) => {
+                        error!(error = %e, "Failed waiting for child");
+                        1
+                    }
+                };
+                let held = code == 0 && pending.as_deref().is_some_and(still_pending);
+                // Also reported from the blocking thread, which keeps going after a detach
+                let _ = exit_sender.send(code);
+                (code, held)
+            })
+            .await;
+            let (code, held) = result.unwrap_or_else(|e| {
+                error!(error = ?e, "spawn_blocking failed");
+                (1, false)
+            });
+            let event = if code == 0 && take_empty_marker(empty_marker.as_deref()) {
+                info!("Nothing was transcribed");
+                AppEvent::NothingTranscribed(empty_exit_code)
+            } else if held {
+                info!("The clipboard holds something, waiting for confirmation to replace it");
+                AppEvent::OverwritePending
+            } else {
+                AppEvent::ChildExited(code)
+            };
+            let _ = sender.send(event).await;
+        });
+    }
+
+    /// Post [`AppEvent::KillTimeoutElapsed`] after `timeout`
+    fn arm_kill_timer(&self, timeout: Duration) {
+        let sender = self.sender.clone();
+        self.guard(glib::timeout_add_local_once(timeout, move || {
+            let _ = sender.try_send(AppEvent::KillTimeoutElapsed);
+        }));
+    }
+
+    fn signal_child(&self, signal: Signal) {
+        let Some(pid) = self.child_pid.get() else {
+            return;
+        };
+        warn!(?signal, "Stop requested while processing, escalating");
+        if let Err(e) = process::send_signal(pid, signal) {
+            warn!(error = %e, "Failed to escalate");
+        }
+    }
+
+    /// Send the pause signal, which waystt treats as a pause/resume toggle
+    fn toggle_pause(&self) {
+        let Some(pid) = self.child_pid.get() else {
+            return;
+        };
+        if let Err(e) = process::send_signal(pid, self.config.pause_signal) {
+            warn!(error = %e, "Failed to pause or resume child");
+        }
+    }
+
+    /// Kill a still-recording child outright, so that it delivers nothing
+    fn cancel_child(&self) {
+        let Some(mut child) = self.child.borrow_mut().take() else {
+            return;
+        };
+        warn!("Cancelling the recording");
+        child.force_kill();
+    }
+
+    /// Replace the child with a new one running the same command, discarding the recording
+    fn restart_child(&self) {
+        if let Some(mut old) = self.child.borrow_mut().take() {
+            info!("Restarting the recording");
+            audit::report("restart");
+            old.force_kill();
+            // Reap it without holding up the overlay
+            std::thread::spawn(move || old.wait());
+        }
+        match self.spawn_child() {
+            Ok(mut child) => {
+                self.child_pid.set(Some(child.pid()));
+                *self.output.borrow_mut() = child.take_output();
+                *self.child.borrow_mut() = Some(child);
+                self.recorded.set(Duration::ZERO);
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to restart the child process");
+                self.post(AppEvent::ChildExitedUnexpectedly(1));
+            }
+        }
+    }
+
+    /// Close every running instance of the wrapper in this session, including this one
+    fn panic_exit(&self) {
+        warn!("user pressed the panic exit hotkey, closing all windows");
+        let mut pids = match Registry::open() {
+            Ok(registry) => registry
+                .peers()
+                .into_iter()
+                .map(|instance| Pid::from_raw(instance.pid as i32))
+                .collect(),
+            Err(e) => {
+                error!("error reading the instance registry, other windows may still exist: {e}");
+                Vec::new()
+            }
+        };
+        let own_pid = Pid::this();
+        pids.retain(|pid| *pid != own_pid);
+        // Last, so that a failure to register still closes this instance
+        pids.push(own_pid);
+
+        for pid in pids {
+            if let Err(e) = process::send_signal(pid, self.config.panic_signal) {
+                error!(?pid, "error killing instance, it may still exist: {e}");
+            }
+        }
+    }
+
+    /// Check whether a recording child exited on its own, returning whether to keep polling
+    pub fn poll_child(&self) -> glib::ControlFlow {
+        let mut child_ref = self.child.borrow_mut();
+        let Some(child) = child_ref.as_mut() else {
+            return glib::ControlFlow::Break;
+        };
+
+        match child.try_wait() {
+            Ok(Some(status)) => {
+                child_ref.take();
+                let code = status.code().unwrap_or(1);
+                warn!(exit_code = code, "Child process exited unexpectedly");
+                self.post(AppEvent::ChildExitedUnexpectedly(code));
+                glib::ControlFlow::Break
+            }
+            _ => glib::ControlFlow::Continue,
+        }
+    }
+}
+
+/// Receive from `exits` within `timeout`, running the main loop meanwhile so that the termination
+/// signal handlers still act while the wrapper waits without its overlay
+fn recv_on_main_loop(exits: &Rc<mpsc::Receiver<i32>>, timeout: Duration) -> Option<i32> {
+    let main_loop = glib::MainLoop::new(None, false);
+    let received = Rc::new(Cell::new(None));
+    let deadline = Instant::now() + timeout;
+    let poll = glib::clone!(
+        #[strong]
+        exits,
+        #[strong]
+        received,
+        #[strong]
+        main_loop,
+        move || {
+            let done = match exits.try_recv() {
+                Ok(code) => {
+                    received.set(Some(code));
+                    true
+                }
+                Err(mpsc::TryRecvError::Disconnected) => true,
+                Err(mpsc::TryRecvError::Empty) => Instant::now() >= deadline,
+            };
+            if !done {
+                return glib::ControlFlow::Continue;
+            }
+            main_loop.quit();
+            glib::ControlFlow::Break
+        }
+    );
+    glib::timeout_add_local(EXIT_POLL_INTERVAL, poll);
+    main_loop.run();
+    received.get()
+}
+
+/// Whether the transcription held back at `pending` by `--confirm-overwrite` still needs
+/// confirmation: it is copied right away if the clipboard holds nothing to lose
+fn still_pending(pending: &Path) -> bool {
+    if !pending.exists() {
+        return false;
+    }
+    if clipboard::has_content() {
+        return true;
+    }
+    if let Err(e) = clipboard::copy_from(pending) {
+        error!(error = %e, "Failed to copy the transcription");
+    }
+    let _ = fs::remove_file(pending);
+    false
+}
+
+/// Whether the command created the `--skip-empty` marker at `marker`, which is removed
+fn take_empty_marker(marker: Option<&Path>) -> bool {
+    marker.is_some_and(|path| fs::remove_file(path).is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> Config {
+        Config {
+            icon: "test-icon".to_string(),
+            icon_processing: Some("test-processing".to_string()),
+            icon_error: "test-error".to_string(),
+            icon_paused: "test-paused".to_string(),
+            label: None,
+            icon_size: crate::config::IconSize::Pixels(64),
+            a11y_large: false,
+            bell: false,
+            notify: None,
+            notify_progress: false,
+            auto_hide: None,
+            show_timer: false,
+            show_hints: false,
+            rec_dot: false,
+            level_meter: false,
+            position: crate::config::Position::Center,
+            appearance: crate::config::Appearance::Floating,
+            theme: crate::config::Theme::Dark,
+            state_palette: None,
+            color_scheme: crate::config::ColorScheme::NoPreference,
+            icon_color: None,
+            margin: 10,
+            units: crate::config::Units::Logical,
+            background_color: "black".to_string(),
+            opacity: 0.8,
+            enforce_contrast: false,
+            border_width: None,
+            border_color: None,
+            corner_radius: None,
+            padding: 10,
+            shadow: false,
+            dim_screen: false,
+            animation_duration: 150,
+            css: None,
+            layout: None,
+            layer: None,
+            keyboard_mode: None,
+            exclusive_zone: None,
+            output: None,
+            all_outputs: false,
+            renderer: None,
+            gdk_backend: None,
+            clipboard_ttl: None,
+            kill_timeout: 30,
+            debounce: 200,
+            spawn_first: false,
+            confirm_panic: false,
+            panic_key: "Escape".to_string(),
+            panic_modifiers: vec![],
+            panic_signal: Signal::SIGHUP,
+            no_panic: false,
+            max_duration: None,
+            pause_key: "space".to_string(),
+            detach_key: "d".to_string(),
+            pause_signal: Signal::SIGUSR2,
+            child_stdout: crate::config::OutputMode::Inherit,
+            child_stderr: crate::config::OutputMode::Inherit,
+            error_lines: 5,
+            on_close: crate::config::OnClose::Detach,
+            preview: false,
+            preset: None,
+            translate_to: None,
+            translate_command: "trans".to_string(),
+            skip_empty: false,
+            empty_exit_code: None,
+            confirm_overwrite: false,
+            clean_env: false,
+            keep_env: Vec::new(),
+            login_shell: false,
+            command: vec!["echo".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_app_model_creation() {
+        let model = AppModel::new(config());
+
+        assert_eq!(model.exit_code(), 0);
+        assert_eq!(model.config().icon, "test-icon");
+    }
+
+    #[test]
+    fn test_only_a_copied_transcription_is_cleared() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let copied = |preset, events: &[AppEvent]| {
+                    let model = AppModel::new(Config { preset, ..config() });
+                    for event in events {
+                        model.dispatch(event.clone());
+                    }
+                    model.shutdown();
+                    model.copied(model.exit_code())
+                };
+                let stopped = [AppEvent::StopRequested, AppEvent::ChildExited(0)];
+                assert!(copied(Some(Preset::Copy), &stopped));
+                assert!(!copied(Some(Preset::Type), &stopped));
+                assert!(!copied(None, &stopped));
+
+                let empty = [AppEvent::StopRequested, AppEvent::NothingTranscribed(0)];
+                assert!(!copied(Some(Preset::Copy), &empty));
+                let kept = [
+                    AppEvent::StopRequested,
+                    AppEvent::OverwritePending,
+                    AppEvent::CancelRequested,
+                ];
+                assert!(!copied(Some(Preset::Copy), &kept));
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_detached_wait_receives_the_exit_code() {
+        let (sender, exits) = mpsc::channel();
+        let exits = Rc::new(exits);
+        sender.send(3).unwrap();
+        assert_eq!(recv_on_main_loop(&exits, Duration::from_secs(5)), Some(3));
+        assert_eq!(recv_on_main_loop(&exits, Duration::ZERO), None);
+        drop(sender);
+        assert_eq!(recv_on_main_loop(&exits, Duration::from_secs(5)), None);
+    }
+
+    #[test]
+    fn test_shutdown_cancels_pending_work() {
+        let context = glib::MainContext::new();
+        context
+            .with_thread_default(|| {
+                let model = AppModel::new(config());
+                let fired = Rc::new(Cell::new(0));
+                let timer_fired = fired.clone();
+                model.guard(glib::timeout_add_local_once(Duration::ZERO, move || {
+                    timer_fired.set(timer_fired.get() + 1)
+                }));
+                let task_fired = fired.clone();
+                model.spawn(async move { task_fired.set(task_fired.get() + 1) });
+
+                model.shutdown();
+                model.shutdown();
+                while context.iteration(false) {}
+                assert_eq!(fired.get(), 0);
+            })
+            .unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::cell::Cell;
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::time::Duration;
+
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use gtk4::{gdk, Application, EventControllerKey};
+use tracing::{error, info};
+
+use crate::audit;
+use crate::config::Config;
+use crate::event;
+use crate::locale::TimeFormat;
+use crate::overlay::{self, create_overlay_window, Overlay};
+use crate::portal;
+use crate::registry::{self, Instance, Registry};
+
+/// How often the attached session's registry entry is re-read
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Show the session's state and elapsed time on the overlay
+fn show(overlay: &Overlay, config: &Config, instance: &Instance, time: TimeFormat) {
+    overlay.show_state(config, instance.state);
+    overlay.timer.set_text(&event::format_elapsed(instance.elapsed(), time));
+}
+
+/// Present the overlay for a detached session, following its state until it finishes. Escape
+/// only closes this overlay; the session carries on either way.
+pub fn run(mut config: Config, pid: Option<u32>) -> ExitCode {
+    let registry = match Registry::open() {
+        Ok(registry) => registry,
+        Err(e) => {
+            error!(error = %e, "Failed to open the instance registry");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Only within this graphical session, where the overlay can be shown
+    let Some(instance) = registry::find_detached(registry.peers(), pid) else {
+        error!("No detached session to attach to");
+        return ExitCode::FAILURE;
+    };
+    info!(pid = instance.pid, "Attaching to detached session");
+
+    overlay::apply_render_env(&config);
+    portal::follow_color_scheme(&mut config);
+
+    // Not unique, so that it never hands over to (or is taken for) a recording instance
+    let app = Application::builder()
+        .application_id("com.github.mcoffin.waystt-wrapper.attach")
+        .flags(gio::ApplicationFlags::NON_UNIQUE)
+        .build();
+    overlay::init_adwaita(&app);
+
+    let config = Rc::new(config);
+    let failed = Rc::new(Cell::new(false));
+    app.connect_activate(glib::clone!(
+        #[strong]
+        failed,
+        move |app| {
+            let overlay = match create_overlay_window(app, &config) {
+                Ok(overlay) => overlay,
+                Err(e) => {
+                    error!(error = %e, "Failed to create overlay window");
+                    failed.set(true);
+                    return;
+                }
+            };
+            overlay.timer.set_visible(true);
+            let time = TimeFormat::from_env();
+            show(&overlay, &config, &instance, time);
+
+            let fade = Duration::from_millis(config.animation_duration);
+            let controller = EventControllerKey::new();
+            controller.connect_key_pressed(glib::clone!(
+                #[weak(rename_to = window)]
+                overlay.window,
+                #[upgrade_or]
+                glib::Propagation::Proceed,
+                move |_, keyval, _, _| {
+                    if keyval != gdk::Key::Escape {
+                        return glib::Propagation::Proceed;
+                    }
+                    info!("Escape pressed, leaving the session in the background");
+                    overlay::fade_out(&window, fade);
+                    glib::Propagation::Stop
+                }
+            ));
+            overlay::track(&controller, audit::Kind::Controller);
+            overlay.window.add_controller(controller);
+            overlay.window.present();
+
+            let Ok(registry) = Registry::open() else {
+                return;
+            };
+            let config = config.clone();
+            let pid = instance.pid;
+            glib::timeout_add_local(POLL_INTERVAL, move || {
+                let current = registry.instances().into_iter().find(|i| i.pid == pid);
+                let Some(current) = current else {
+                    info!(pid, "Detached session finished");
+                    overlay::fade_out(&overlay.window, fade);
+                    return glib::ControlFlow::Break;
+                };
+                show(&overlay, &config, &current, time);
+                glib::ControlFlow::Continue
+            });
+        }
+    ));
+
+    app.run_with_args::<&str>(&[]);
+    if failed.get() {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,337 @@
+use std::collections::VecDeque;
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+
+use serde_json::Value;
+use tracing::{debug, info, warn};
+
+/// Records the default PipeWire source as raw 16 kHz mono samples on stdout
+const CAPTURE_COMMAND: &[&str] = &[
+    "pw-record",
+    "--rate",
+    "16000",
+    "--channels",
+    "1",
+    "--format",
+    "s16",
+    "-",
+];
+
+/// Bytes per level update: 50 ms of 16 kHz, 16 bit mono audio
+const CHUNK_SIZE: usize = 1600;
+
+/// Quietest level the meter shows, in dBFS; anything below reads as silence
+const FLOOR_DB: f64 = -60.0;
+
+/// Meter level from 0.0 (silence) to 1.0 (full scale) for a chunk of signed 16 bit little-endian
+/// samples. Logarithmic, so that normal speech lands mid-scale rather than near the bottom.
+pub fn level(samples: &[u8]) -> f64 {
+    let peak = samples
+        .chunks_exact(2)
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]).unsigned_abs())
+        .max()
+        .unwrap_or(0);
+    if peak == 0 {
+        return 0.0;
+    }
+    let db = 20.0 * (f64::from(peak) / f64::from(i16::MAX)).log10();
+    ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0)
+}
+
+/// Levels below this (about -54 dBFS) count as silence for [`GainCheck`]
+const QUIET_LEVEL: f64 = 0.1;
+
+/// Levels at or above this (about -30 dBFS) are someone speaking rather than room noise, which
+/// keeps `--auto-hide` from hiding the overlay
+const SOUND_LEVEL: f64 = 0.5;
+
+/// Whether `level` is loud enough to count as activity
+pub fn is_sound(level: f64) -> bool {
+    level >= SOUND_LEVEL
+}
+
+/// Levels at or above this are clipping (within a fraction of a dB of full scale)
+const CLIPPING_LEVEL: f64 = 0.999;
+
+/// How many chunks in a row have to be silent before the mic is considered too quiet (5 s)
+const QUIET_CHUNKS: usize = 100;
+
+/// Sliding window of chunks checked for clipping (2 s), and how many of them have to clip
+const CLIPPING_WINDOW: usize = 40;
+const CLIPPING_CHUNKS: usize = 8;
+
+/// A likely misconfigured input gain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GainWarning {
+    TooQuiet,
+    Clipping,
+}
+
+impl GainWarning {
+    pub fn text(self) -> &'static str {
+        match self {
+            GainWarning::TooQuiet => "Mic is very quiet, check the input gain",
+            GainWarning::Clipping => "Mic is clipping, lower the input gain",
+        }
+    }
+}
+
+/// Watches the levels from a [`LevelMonitor`] for sustained silence or clipping
+#[derive(Debug, Default)]
+pub struct GainCheck {
+    quiet_chunks: usize,
+    recent_clipping: VecDeque<bool>,
+    warning: Option<GainWarning>,
+}
+
+impl GainCheck {
+    /// Take the next level, returning the new warning state if it changed
+    pub fn update(&mut self, level: f64) -> Option<Option<GainWarning>> {
+        self.quiet_chunks = if level < QUIET_LEVEL {
+            self.quiet_chunks + 1
+        } else {
+            0
+        };
+        if self.recent_clipping.len() == CLIPPING_WINDOW {
+            self.recent_clipping.pop_front();
+        }
+        self.recent_clipping.push_back(level >= CLIPPING_LEVEL);
+        let clipping = self.recent_clipping.iter().filter(|clipped| **clipped).count();
+
+        let warning = if clipping >= CLIPPING_CHUNKS {
+            Some(GainWarning::Clipping)
+        } else if self.quiet_chunks >= QUIET_CHUNKS {
+            Some(GainWarning::TooQuiet)
+        } else if self.warning == Some(GainWarning::Clipping) && clipping > 0 {
+            // Only clear once the clipping has fully stopped, rather than flicker around the limit
+            self.warning
+        } else {
+            None
+        };
+        (warning != self.warning).then(|| {
+            self.warning = warning;
+            warning
+        })
+    }
+}
+
+/// Our own capture streams, which are not worth a warning
+const OWN_STREAMS: &[&str] = &["waystt", "pw-record"];
+
+/// Names of the applications with an active audio capture stream in a `pw-dump` of the graph
+fn capture_apps(dump: &Value) -> Vec<String> {
+    let mut apps: Vec<String> = dump
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|object| object["type"] == "PipeWire:Interface:Node")
+        .map(|node| &node["info"])
+        .filter(|info| info["state"] == "running")
+        .map(|info| &info["props"])
+        .filter(|props| props["media.class"] == "Stream/Input/Audio")
+        .filter_map(|props| {
+            ["application.name", "application.process.binary", "node.name"]
+                .iter()
+                .find_map(|key| props[key].as_str())
+        })
+        .filter(|name| !OWN_STREAMS.contains(name))
+        .map(str::to_string)
+        .collect();
+    apps.sort();
+    apps.dedup();
+    apps
+}
+
+/// Other applications currently capturing audio, which tends to explain poor transcriptions.
+/// Empty if PipeWire can't be queried.
+pub fn other_capture_apps() -> Vec<String> {
+    let output = Command::new("pw-dump")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    let output = match output {
+        Ok(output) if output.status.success() => output.stdout,
+        Ok(output) => {
+            debug!(status = ?output.status, "pw-dump failed");
+            return Vec::new();
+        }
+        Err(e) => {
+            debug!(error = %e, "Could not run pw-dump");
+            return Vec::new();
+        }
+    };
+    match serde_json::from_slice(&output) {
+        Ok(dump) => capture_apps(&dump),
+        Err(e) => {
+            warn!(error = %e, "Unexpected pw-dump output");
+            Vec::new()
+        }
+    }
+}
+
+/// Warning naming the other applications capturing audio, e.g. `Zoom is also using the mic`
+pub fn capture_warning(apps: &[String]) -> Option<String> {
+    let (last, rest) = apps.split_last()?;
+    let names = if rest.is_empty() {
+        last.clone()
+    } else {
+        format!("{} and {last}", rest.join(", "))
+    };
+    let verb = if rest.is_empty() { "is" } else { "are" };
+    Some(format!("{names} {verb} also using the mic"))
+}
+
+/// A capture stream on the default PipeWire source, reporting input levels until dropped
+pub struct LevelMonitor {
+    child: Child,
+}
+
+impl LevelMonitor {
+    /// Start capturing, calling `on_level` from a background thread about every 50 ms
+    pub fn spawn(on_level: impl Fn(f64) + Send + 'static) -> io::Result<Self> {
+        let mut child = Command::new(CAPTURE_COMMAND[0])
+            .args(&CAPTURE_COMMAND[1..])
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        info!(pid = child.id(), "Started level monitor");
+
+        let mut stdout = child.stdout.take().expect("stdout is piped");
+        thread::spawn(move || {
+            let mut chunk = [0; CHUNK_SIZE];
+            while stdout.read_exact(&mut chunk).is_ok() {
+                on_level(level(&chunk));
+            }
+            debug!("Level monitor stream ended");
+        });
+
+        Ok(Self { child })
+    }
+}
+
+impl Drop for LevelMonitor {
+    fn drop(&mut self) {
+        if let Err(e) = self.child.kill() {
+            warn!(error = %e, "Failed to stop level monitor");
+        }
+        let _ = self.child.wait();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(values: &[i16]) -> Vec<u8> {
+        values.iter().flat_map(|value| value.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn test_capture_apps() {
+        let dump = serde_json::json!([
+            { "type": "PipeWire:Interface:Client", "info": {} },
+            {
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                    "state": "running",
+                    "props": { "media.class": "Stream/Input/Audio", "application.name": "ZOOM" }
+                }
+            },
+            {
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                    "state": "running",
+                    "props": { "media.class": "Stream/Input/Audio", "node.name": "waystt" }
+                }
+            },
+            {
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                    "state": "suspended",
+                    "props": { "media.class": "Stream/Input/Audio", "application.name": "Firefox" }
+                }
+            },
+            {
+                "type": "PipeWire:Interface:Node",
+                "info": {
+                    "state": "running",
+                    "props": { "media.class": "Stream/Output/Audio", "application.name": "mpv" }
+                }
+            }
+        ]);
+        assert_eq!(capture_apps(&dump), vec!["ZOOM"]);
+        assert!(capture_apps(&Value::Null).is_empty());
+    }
+
+    #[test]
+    fn test_capture_warning() {
+        let apps = |names: &[&str]| names.iter().map(|name| name.to_string()).collect::<Vec<_>>();
+        assert_eq!(capture_warning(&[]), None);
+        assert_eq!(
+            capture_warning(&apps(&["Zoom"])).as_deref(),
+            Some("Zoom is also using the mic")
+        );
+        assert_eq!(
+            capture_warning(&apps(&["Discord", "OBS", "Zoom"])).as_deref(),
+            Some("Discord, OBS and Zoom are also using the mic")
+        );
+    }
+
+    #[test]
+    fn test_gain_check_too_quiet() {
+        let mut check = GainCheck::default();
+        for _ in 1..QUIET_CHUNKS {
+            assert_eq!(check.update(0.05), None);
+        }
+        assert_eq!(check.update(0.05), Some(Some(GainWarning::TooQuiet)));
+        assert_eq!(check.update(0.05), None);
+        assert_eq!(check.update(0.5), Some(None));
+    }
+
+    #[test]
+    fn test_gain_check_clipping() {
+        let mut check = GainCheck::default();
+        // Occasional clipping is fine
+        for _ in 0..CLIPPING_WINDOW {
+            check.update(1.0);
+            for _ in 0..5 {
+                assert_eq!(check.update(0.5), None);
+            }
+        }
+        for _ in 0..CLIPPING_WINDOW {
+            check.update(0.5);
+        }
+
+        for _ in 1..CLIPPING_CHUNKS {
+            assert_eq!(check.update(1.0), None);
+        }
+        assert_eq!(check.update(1.0), Some(Some(GainWarning::Clipping)));
+        for _ in 0..CLIPPING_WINDOW - 1 {
+            assert_eq!(check.update(0.5), None);
+        }
+        assert_eq!(check.update(0.5), Some(None));
+    }
+
+    #[test]
+    fn test_silence_is_zero() {
+        assert_eq!(level(&samples(&[0, 0, 0])), 0.0);
+        assert_eq!(level(&[]), 0.0);
+    }
+
+    #[test]
+    fn test_full_scale_is_one() {
+        assert_eq!(level(&samples(&[0, i16::MAX, 5])), 1.0);
+        assert_eq!(level(&samples(&[i16::MIN])), 1.0);
+    }
+
+    #[test]
+    fn test_level_is_logarithmic() {
+        // -20 dBFS is a third of the way down from full scale
+        let level = level(&samples(&[i16::MAX / 10]));
+        assert!((level - 2.0 / 3.0).abs() < 0.01, "{level}");
+        // Below the floor reads as silence
+        assert_eq!(super::level(&samples(&[1])), 0.0);
+    }
+}
@@ -0,0 +1,134 @@
+use std::fmt;
+use std::fs;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use tracing::info;
+
+/// Something whose lifetime `--audit-lifecycle` keeps count of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Window,
+    Controller,
+    Child,
+}
+
+impl Kind {
+    const ALL: [Kind; 3] = [Kind::Window, Kind::Controller, Kind::Child];
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Window => "windows",
+            Kind::Controller => "controllers",
+            Kind::Child => "children",
+        }
+    }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static CREATED: [AtomicUsize; 3] =
+    [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+static DESTROYED: [AtomicUsize; 3] =
+    [AtomicUsize::new(0), AtomicUsize::new(0), AtomicUsize::new(0)];
+
+/// Log every creation and destruction, and a [`Report`] at milestones
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+pub fn created(kind: Kind) {
+    let count = CREATED[kind as usize].fetch_add(1, Ordering::Relaxed) + 1;
+    if is_enabled() {
+        info!(kind = kind.name(), count, "Created");
+    }
+}
+
+pub fn destroyed(kind: Kind) {
+    let count = DESTROYED[kind as usize].fetch_add(1, Ordering::Relaxed) + 1;
+    if is_enabled() {
+        info!(kind = kind.name(), count, "Destroyed");
+    }
+}
+
+/// Number of file descriptors this process has open, where `/proc` is available
+pub fn open_fds() -> Option<usize> {
+    // Reading the directory takes one more
+    fs::read_dir("/proc/self/fd").ok().map(|fds| fds.count().saturating_sub(1))
+}
+
+/// Created and destroyed counts for each [`Kind`], and the open file descriptors
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    counts: [(usize, usize); 3],
+    open_fds: Option<usize>,
+}
+
+impl Report {
+    pub fn current() -> Self {
+        Self {
+            counts: Kind::ALL.map(|kind| {
+                (
+                    CREATED[kind as usize].load(Ordering::Relaxed),
+                    DESTROYED[kind as usize].load(Ordering::Relaxed),
+                )
+            }),
+            open_fds: open_fds(),
+        }
+    }
+
+    /// How many of `kind` are still alive
+    pub fn live(&self, kind: Kind) -> usize {
+        let (created, destroyed) = self.counts[kind as usize];
+        created.saturating_sub(destroyed)
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for kind in Kind::ALL {
+            let (created, _) = self.counts[kind as usize];
+            write!(f, "{}: {} live of {created}; ", kind.name(), self.live(kind))?;
+        }
+        match self.open_fds {
+            Some(fds) => write!(f, "{fds} open fds"),
+            None => write!(f, "open fds unknown"),
+        }
+    }
+}
+
+/// Log the current [`Report`] if `--audit-lifecycle` is on
+pub fn report(milestone: &str) {
+    if is_enabled() {
+        info!(milestone, "Lifecycle: {}", Report::current());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let report = Report {
+            counts: [(2, 1), (8, 8), (1, 0)],
+            open_fds: Some(12),
+        };
+        assert_eq!(report.live(Kind::Window), 1);
+        assert_eq!(report.live(Kind::Controller), 0);
+        assert_eq!(report.live(Kind::Child), 1);
+        assert_eq!(
+            report.to_string(),
+            "windows: 1 live of 2; controllers: 0 live of 8; children: 1 live of 1; 12 open fds"
+        );
+    }
+
+    #[test]
+    fn test_open_fds() {
+        if std::path::Path::new("/proc/self/fd").is_dir() {
+            assert!(open_fds().is_some_and(|fds| fds >= 3));
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use std::fs::File;
+use std::io;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use tracing::info;
+
+/// Script run by the detached clearer. It snapshots a hash of the current clipboard contents,
+/// sleeps, and only clears the selection if the contents are unchanged (i.e. nothing else has
+/// taken ownership of the clipboard in the meantime).
+const CLEAR_SCRIPT: &str = r#"snapshot() { wl-paste --no-newline 2>/dev/null | sha256sum; }
+before=$(snapshot)
+sleep "$1"
+[ "$(snapshot)" = "$before" ] && wl-copy --clear"#;
+
+/// Whether the clipboard holds anything, in any format
+pub fn has_content() -> bool {
+    Command::new("wl-paste")
+        .arg("--list-types")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .is_ok_and(|output| output.status.success() && !output.stdout.trim_ascii().is_empty())
+}
+
+/// Put the contents of `path` on the clipboard
+pub fn copy_from(path: &Path) -> io::Result<()> {
+    // wl-copy keeps serving the clipboard from a forked child, which must not hold our pipes
+    let status = Command::new("wl-copy")
+        .stdin(File::open(path)?)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+    if !status.success() {
+        return Err(io::Error::other(format!("wl-copy failed: {status}")));
+    }
+    info!("Copied the transcription to the clipboard");
+    Ok(())
+}
+
+/// Spawn a detached process that clears the clipboard after `ttl`, so it outlives the wrapper
+pub fn schedule_clear(ttl: Duration) -> io::Result<()> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(CLEAR_SCRIPT)
+        .arg("sh")
+        .arg(ttl.as_secs().to_string())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()?;
+
+    info!(pid = child.id(), ttl = ?ttl, "Scheduled clipboard clear");
+
+    Ok(())
+}
@@ -1,13 +1,710 @@
-use clap::{Parser, ValueEnum};
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::str::FromStr;
 
-#[derive(Debug, Clone, Copy, ValueEnum, Default)]
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use nix::sys::signal::Signal;
+use tracing::warn;
+use serde::{Serialize, Serializer};
+
+/// Error type for rendering a resolved [`Config`]
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("failed to serialize config as TOML: {0}")]
+    Toml(#[from] toml::ser::Error),
+    #[error("failed to serialize config as JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum Position {
     TopLeft,
+    Top,
     TopRight,
+    Left,
+    Right,
     BottomLeft,
+    Bottom,
     BottomRight,
     #[default]
     Center,
+    /// Just above the focused window (Hyprland only, centered elsewhere)
+    AboveWindow,
+    /// Just above the pointer (Hyprland only, centered elsewhere)
+    Cursor,
+}
+
+/// Overall shape of the overlay
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Appearance {
+    /// A floating square holding the icon
+    #[default]
+    Floating,
+    /// A full-width strip along the top or bottom edge, with the icon and a status label
+    Bar,
+    /// Just a small colored circle, as a subtle recording indicator
+    Dot,
+}
+
+/// Built-in color scheme, selected with `--theme`
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Translucent grey with a red icon
+    #[default]
+    Dark,
+    /// Light grey with dark text
+    Light,
+    /// Opaque black with a yellow icon, white text and a thick white border, for low vision
+    HighContrast,
+    /// Barely-there background with a white icon
+    Minimal,
+    /// Dark or light, following the desktop's dark/light preference
+    Auto,
+}
+
+/// State colors for color vision deficiencies, selected with `--palette`. From the Okabe-Ito
+/// set, so that recording, paused, processing and failed stay apart without telling red
+/// from green (or blue from yellow)
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StatePalette {
+    /// Red-green, weak green: orange, sky blue, blue and purple
+    Deuteranopia,
+    /// Red-green, weak red: yellow, sky blue, blue and purple
+    Protanopia,
+    /// Blue-yellow: vermillion, grey, bluish green and purple
+    Tritanopia,
+}
+
+/// Icon colors for each phase of the session
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateColors {
+    pub recording: &'static str,
+    pub paused: &'static str,
+    pub processing: &'static str,
+    pub error: &'static str,
+}
+
+impl StatePalette {
+    pub fn colors(self) -> StateColors {
+        match self {
+            StatePalette::Deuteranopia => StateColors {
+                recording: "#e69f00",
+                paused: "#56b4e9",
+                processing: "#0072b2",
+                error: "#cc79a7",
+            },
+            StatePalette::Protanopia => StateColors {
+                recording: "#f0e442",
+                paused: "#56b4e9",
+                processing: "#0072b2",
+                error: "#cc79a7",
+            },
+            StatePalette::Tritanopia => StateColors {
+                recording: "#d55e00",
+                paused: "#999999",
+                processing: "#009e73",
+                error: "#cc79a7",
+            },
+        }
+    }
+}
+
+/// The desktop's dark/light preference, as reported by the settings portal
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorScheme {
+    #[default]
+    NoPreference,
+    PreferDark,
+    PreferLight,
+}
+
+impl ColorScheme {
+    /// The portal's `org.freedesktop.appearance` `color-scheme` value
+    pub fn from_portal(value: u32) -> Self {
+        match value {
+            1 => ColorScheme::PreferDark,
+            2 => ColorScheme::PreferLight,
+            _ => ColorScheme::NoPreference,
+        }
+    }
+}
+
+/// The colors of a [`Theme`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub background: &'static str,
+    pub opacity: f64,
+    pub icon: &'static str,
+    pub text: &'static str,
+    /// CSS `border` of the overlay
+    pub border: &'static str,
+}
+
+impl Theme {
+    /// The theme to draw [`Theme::Auto`] with under `scheme`: light only if asked for
+    pub fn resolve(self, scheme: ColorScheme) -> Theme {
+        match (self, scheme) {
+            (Theme::Auto, ColorScheme::PreferLight) => Theme::Light,
+            (Theme::Auto, _) => Theme::Dark,
+            (theme, _) => theme,
+        }
+    }
+
+    pub fn palette(self) -> Palette {
+        match self {
+            // Until the desktop's preference is known
+            Theme::Dark | Theme::Auto => Palette {
+                background: "rgb(50, 50, 50)",
+                opacity: 0.8,
+                icon: "#ff5555",
+                text: "#eeeeee",
+                border: "none",
+            },
+            Theme::Light => Palette {
+                background: "#f6f5f4",
+                opacity: 0.9,
+                icon: "#c01c28",
+                text: "#241f31",
+                border: "1px solid alpha(black, 0.15)",
+            },
+            // Yellow and white on black are both well above the WCAG AAA contrast ratio
+            Theme::HighContrast => Palette {
+                background: "black",
+                opacity: 1.0,
+                icon: "#ffff00",
+                text: "white",
+                border: "3px solid white",
+            },
+            Theme::Minimal => Palette {
+                background: "black",
+                opacity: 0.3,
+                icon: "white",
+                text: "white",
+                border: "none",
+            },
+        }
+    }
+}
+
+/// Icon color, selected with `--icon-color`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IconColor {
+    /// Follow the GTK theme: accent-colored icon on the theme's background and foreground
+    Auto,
+    /// Any CSS color
+    Css(String),
+}
+
+impl Serialize for IconColor {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IconColor::Auto => serializer.serialize_str("auto"),
+            IconColor::Css(color) => serializer.serialize_str(color),
+        }
+    }
+}
+
+fn parse_icon_color(value: &str) -> Result<IconColor, String> {
+    if value.trim().eq_ignore_ascii_case("auto") {
+        return Ok(IconColor::Auto);
+    }
+    parse_css_color(value).map(IconColor::Css)
+}
+
+/// Named colors of the GTK theme, which GTK resolves at style time
+#[cfg(not(feature = "adwaita"))]
+pub mod theme_colors {
+    pub const BACKGROUND: &str = "@theme_bg_color";
+    pub const ACCENT: &str = "@theme_selected_bg_color";
+    pub const TEXT: &str = "@theme_fg_color";
+}
+
+/// libadwaita's named colors, which also follow the GNOME accent color and dark style
+#[cfg(feature = "adwaita")]
+pub mod theme_colors {
+    pub const BACKGROUND: &str = "@window_bg_color";
+    pub const ACCENT: &str = "@accent_color";
+    pub const TEXT: &str = "@window_fg_color";
+}
+
+/// The colors of `theme`, taken from the GTK theme for `--icon-color auto`
+fn palette(theme: Theme, icon_color: Option<&IconColor>) -> Palette {
+    let palette = theme.palette();
+    match icon_color {
+        Some(IconColor::Auto) => Palette {
+            background: theme_colors::BACKGROUND,
+            icon: theme_colors::ACCENT,
+            text: theme_colors::TEXT,
+            ..palette
+        },
+        _ => palette,
+    }
+}
+
+/// Layer-shell layer to place the overlay on
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layer {
+    Background,
+    Bottom,
+    Top,
+    Overlay,
+}
+
+/// Layer-shell keyboard interactivity
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum KeyboardMode {
+    /// Grab all keyboard input while the overlay is shown
+    Exclusive,
+    /// Receive keyboard input only when focused (e.g. by clicking the overlay)
+    OnDemand,
+    /// Never receive keyboard input
+    None,
+}
+
+/// GTK renderer, passed to GTK as `GSK_RENDERER`
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Renderer {
+    /// Software rendering, which avoids flicker from the GL renderers on some GPUs
+    Cairo,
+    /// The old GL renderer
+    Gl,
+    /// The new GL renderer
+    Ngl,
+    Vulkan,
+}
+
+impl Renderer {
+    fn env_value(self) -> &'static str {
+        match self {
+            Renderer::Cairo => "cairo",
+            Renderer::Gl => "gl",
+            Renderer::Ngl => "ngl",
+            Renderer::Vulkan => "vulkan",
+        }
+    }
+}
+
+/// Well-known waystt pipelines, selected with `--preset`
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Preset {
+    /// Copy the transcription to the clipboard
+    #[default]
+    Copy,
+    /// Type the transcription into the focused window
+    Type,
+    /// Print the transcription to stdout
+    Stdout,
+}
+
+/// The command line for each preset, and the programs it needs
+const PRESETS: &[(Preset, &[&str], &[&str])] = &[
+    (Preset::Copy, &["waystt", "--pipe-to", "wl-copy"], &["waystt", "wl-copy"]),
+    (Preset::Type, &["waystt", "--pipe-to", "wtype", "-"], &["waystt", "wtype"]),
+    (Preset::Stdout, &["waystt"], &["waystt"]),
+];
+
+impl Preset {
+    fn entry(self) -> Option<&'static (Preset, &'static [&'static str], &'static [&'static str])> {
+        PRESETS.iter().find(|(preset, _, _)| *preset == self)
+    }
+
+    pub fn command(self) -> Vec<String> {
+        self.entry()
+            .map(|(_, command, _)| command.iter().map(|arg| arg.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Where the preset sends the transcription
+    pub fn destination(self) -> &'static str {
+        match self {
+            Preset::Copy => "clipboard",
+            Preset::Type => "focused window",
+            Preset::Stdout => "stdout",
+        }
+    }
+
+    /// Where the preset pipes the transcription to, as a shell command, if anywhere
+    fn sink(self) -> Option<String> {
+        let command = self.command();
+        let pipe_to = command.iter().position(|arg| arg == "--pipe-to")?;
+        Some(command[pipe_to + 1..].join(" "))
+    }
+
+    /// The preset's command as the options adjust it: with `filter`, a shell pipeline stage,
+    /// between waystt and where the transcription goes; for `skip_empty`, a blank transcription
+    /// going nowhere and the file named by [`EMPTY_MARKER_VAR`] created instead; and to `hold`
+    /// the transcription back in the file named by [`PENDING_VAR`] rather than send it on
+    pub fn command_with(self, filter: Option<&str>, skip_empty: bool, hold: bool) -> Vec<String> {
+        if filter.is_none() && !skip_empty && !hold {
+            return self.command();
+        }
+        let sink = if hold {
+            Some(format!("cat > \"${PENDING_VAR}\""))
+        } else {
+            self.sink()
+        };
+        let stage = match (filter, sink) {
+            (Some(filter), Some(sink)) => format!("{filter} | {sink}"),
+            (Some(filter), None) => filter.to_string(),
+            (None, Some(sink)) => sink,
+            (None, None) => "cat".to_string(),
+        };
+        if !skip_empty {
+            return shell_stage(&stage);
+        }
+        shell_stage(&format!(
+            "text=$(cat); case \"$text\" in *[![:space:]]*) printf '%s' \"$text\" | {stage} ;; \
+             *) [ -z \"${EMPTY_MARKER_VAR}\" ] || : > \"${EMPTY_MARKER_VAR}\" ;; esac"
+        ))
+    }
+
+    /// Programs that have to be installed for the preset to work
+    pub fn programs(self) -> &'static [&'static str] {
+        self.entry().map(|(_, _, programs)| *programs).unwrap_or_default()
+    }
+}
+
+/// Environment variable naming the file a command creates to tell the wrapper that nothing was
+/// transcribed, for `--skip-empty`
+pub const EMPTY_MARKER_VAR: &str = "WAYSTT_WRAPPER_EMPTY";
+
+/// Environment variable naming the file the transcription is held back in until the wrapper
+/// copies it, for `--confirm-overwrite`
+pub const PENDING_VAR: &str = "WAYSTT_WRAPPER_PENDING";
+
+/// `command` run by a login shell, which sets up the user's PATH from ~/.profile first
+fn through_login_shell(command: Vec<String>) -> Vec<String> {
+    let shell = ["sh", "-lc", "exec \"$0\" \"$@\""].map(String::from);
+    shell.into_iter().chain(command).collect()
+}
+
+/// waystt piping the transcription to the shell pipeline `stage`
+fn shell_stage(stage: &str) -> Vec<String> {
+    ["waystt", "--pipe-to", "sh", "-c", stage].map(String::from).to_vec()
+}
+
+/// Translation filter used by `--translate-to` (translate-shell, reading stdin); `{lang}` is
+/// replaced with the target language
+const DEFAULT_TRANSLATE_COMMAND: &str = "trans -brief :{lang}";
+
+/// Accept a language code (e.g. `de`, `pt-BR`), which ends up in a shell command line
+fn parse_language(value: &str) -> Result<String, String> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || c == '-' || c == '_';
+    if !value.is_empty() && value.chars().all(valid) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("invalid language code: {value}"))
+    }
+}
+
+/// What happens to a recording when the overlay is closed from outside (e.g. by the compositor)
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OnClose {
+    /// Stop recording and wait for the transcription, as if Escape was pressed
+    Graceful,
+    /// Kill the command, so nothing is transcribed or delivered
+    Cancel,
+    /// Stop recording and close the overlay right away, leaving the command to deliver in the
+    /// background as with the detach key
+    #[default]
+    Detach,
+}
+
+/// Where an output stream of the command goes
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum OutputMode {
+    /// Pass it through to the wrapper's own stream
+    #[default]
+    Inherit,
+    /// Discard it
+    Null,
+    /// Log each line through the wrapper's log
+    Log,
+    /// Hold it back and write it to the wrapper's own stream once the command exits
+    Capture,
+}
+
+/// Where `--notify` sends desktop notifications
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NotifyBackend {
+    /// The XDG desktop portal (org.freedesktop.portal.Notification)
+    Portal,
+    /// The `notify-send` command from libnotify
+    NotifySend,
+    /// The notification daemon over D-Bus, with the hints mako and swaync use to replace notices
+    Dbus,
+}
+
+/// Keyboard modifier, for key combos
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    Shift,
+    Super,
+}
+
+/// Output format for `--print-config`
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq)]
+pub enum ConfigFormat {
+    #[default]
+    Toml,
+    Json,
+}
+
+/// A setting that can be changed on a running overlay with `waystt-wrapper set`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Setting {
+    Icon(String),
+    /// Pango markup of the caption; empty hides it
+    Label(String),
+    Position(Position),
+    Theme(Theme),
+}
+
+/// How a GDK key name is written on a keyboard, e.g. `Escape` as "Esc" and `page_down` as
+/// "Page Down"
+fn key_label(name: &str) -> String {
+    match name {
+        "Escape" => "Esc".to_string(),
+        "Return" => "Enter".to_string(),
+        _ => name
+            .split('_')
+            .map(|word| {
+                let mut chars = word.chars();
+                chars.next().map_or_else(String::new, |first| {
+                    first.to_uppercase().chain(chars).collect()
+                })
+            })
+            .collect::<Vec<_>>()
+            .join(" "),
+    }
+}
+
+/// Parse a `key=value` setting, e.g. `theme=light`
+pub fn parse_setting(value: &str) -> Result<Setting, String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected KEY=VALUE, got `{value}`"))?;
+    match key {
+        "icon" if !value.is_empty() => Ok(Setting::Icon(value.to_string())),
+        "icon" => Err("icon can't be empty".to_string()),
+        "label" => Ok(Setting::Label(value.to_string())),
+        "position" => Position::from_str(value, false).map(Setting::Position),
+        "theme" => Theme::from_str(value, false).map(Setting::Theme),
+        _ => Err(format!("unknown setting `{key}` (expected icon, label, position or theme)")),
+    }
+}
+
+impl fmt::Display for Setting {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |value: Option<clap::builder::PossibleValue>| {
+            value.map(|value| value.get_name().to_string()).unwrap_or_default()
+        };
+        match self {
+            Setting::Icon(icon) => write!(f, "icon={icon}"),
+            Setting::Label(label) => write!(f, "label={label}"),
+            Setting::Position(position) => {
+                write!(f, "position={}", name(position.to_possible_value()))
+            }
+            Setting::Theme(theme) => write!(f, "theme={}", name(theme.to_possible_value())),
+        }
+    }
+}
+
+/// Auxiliary subcommands that run instead of the overlay
+#[derive(Subcommand, Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Print compositor and layer-shell capabilities for bug reports
+    Doctor,
+    /// List running instances: pid, state and Wayland display
+    Status,
+    /// Show the overlay again for a session detached with the detach key, until it finishes
+    Attach {
+        /// Pid of the detached wrapper, if there are several
+        pid: Option<u32>,
+    },
+    /// Change the appearance of running overlays, e.g. `set theme=light icon=face-smile`
+    Set {
+        /// Only change the overlay of this wrapper, rather than every one on this display
+        #[arg(long)]
+        pid: Option<u32>,
+        /// `icon=NAME|PATH`, `label=MARKUP`, `position=POS` or `theme=THEME`
+        #[arg(required = true, value_name = "KEY=VALUE", value_parser = parse_setting)]
+        settings: Vec<Setting>,
+    },
+    /// Print shell completions
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Stand-in for waystt used by tests: waits for SIGUSR1, prints canned output and exits
+    #[command(hide = true)]
+    FakeChild(FakeChildArgs),
+}
+
+/// Options for the hidden `fake-child` subcommand
+#[derive(clap::Args, Debug, Clone, PartialEq, Eq)]
+pub struct FakeChildArgs {
+    /// Text printed to stdout when stopped
+    #[arg(long, default_value = "this is a fake transcription")]
+    pub text: String,
+
+    /// Exit code to exit with when stopped
+    #[arg(long, default_value = "0")]
+    pub exit_code: i32,
+
+    /// Exit on its own after this many milliseconds, as if it crashed
+    #[arg(long, value_name = "MS")]
+    pub exit_after_ms: Option<u64>,
+
+    /// Ignore SIGUSR1 and SIGTERM, so that only SIGKILL stops it
+    #[arg(long)]
+    pub ignore_stop: bool,
+}
+
+/// Accept a CSS color value, rejecting anything that could break out of the declaration
+fn parse_css_color(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() {
+        return Err("color must not be empty".to_string());
+    }
+    if value.contains([';', '{', '}']) {
+        return Err(format!("invalid CSS color: {value}"));
+    }
+    Ok(value.to_string())
+}
+
+/// Accept a signal name with or without the `SIG` prefix, in any case (e.g. `usr2`, `SIGUSR2`)
+fn parse_signal(value: &str) -> Result<Signal, String> {
+    let name = value.trim().to_ascii_uppercase();
+    let name = if name.starts_with("SIG") {
+        name
+    } else {
+        format!("SIG{name}")
+    };
+    Signal::from_str(&name).map_err(|_| format!("unknown signal: {value}"))
+}
+
+fn serialize_signal<S: Serializer>(signal: &Signal, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(signal.as_str())
+}
+
+/// Space to reserve along the anchored edge, like a panel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExclusiveZone {
+    /// Reserve the surface's own size
+    Auto,
+    Pixels(i32),
+}
+
+impl Serialize for ExclusiveZone {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ExclusiveZone::Auto => serializer.serialize_str("auto"),
+            ExclusiveZone::Pixels(pixels) => serializer.serialize_i32(*pixels),
+        }
+    }
+}
+
+fn parse_exclusive_zone(value: &str) -> Result<ExclusiveZone, String> {
+    if value.trim().eq_ignore_ascii_case("auto") {
+        return Ok(ExclusiveZone::Auto);
+    }
+    let pixels: i32 = value.trim().parse().map_err(|e| format!("{e}"))?;
+    if pixels >= 0 {
+        Ok(ExclusiveZone::Pixels(pixels))
+    } else {
+        Err("exclusive zone must be `auto` or at least 0".to_string())
+    }
+}
+
+/// Unit of the sizes and margins given in pixels, selected with `--units`
+#[derive(Debug, Clone, Copy, ValueEnum, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Units {
+    /// Scaled with the output, like the rest of the desktop
+    #[default]
+    Logical,
+    /// Device pixels, as many on a scaled output as on an unscaled one
+    Physical,
+}
+
+/// Icon size, absolute or relative to the monitor the overlay is shown on
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IconSize {
+    Pixels(i32),
+    /// Percentage of the monitor's smaller dimension
+    Percent(f64),
+}
+
+impl IconSize {
+    /// Size in pixels on a monitor of `width` x `height`
+    pub fn resolve(self, width: i32, height: i32) -> i32 {
+        match self {
+            IconSize::Pixels(pixels) => pixels,
+            IconSize::Percent(percent) => {
+                ((width.min(height) as f64 * percent / 100.0).round() as i32).max(1)
+            }
+        }
+    }
+}
+
+impl PartialEq<i32> for IconSize {
+    fn eq(&self, other: &i32) -> bool {
+        *self == IconSize::Pixels(*other)
+    }
+}
+
+impl Serialize for IconSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            IconSize::Pixels(pixels) => serializer.serialize_i32(*pixels),
+            IconSize::Percent(percent) => serializer.serialize_str(&format!("{percent}%")),
+        }
+    }
+}
+
+/// Accept pixels (`96`) or a percentage of the monitor (`10%`)
+fn parse_icon_size(value: &str) -> Result<IconSize, String> {
+    if let Some(percent) = value.strip_suffix('%') {
+        let percent: f64 = percent.trim().parse().map_err(|e| format!("{e}"))?;
+        if percent > 0.0 && percent <= 100.0 {
+            Ok(IconSize::Percent(percent))
+        } else {
+            Err("percentage must be greater than 0 and at most 100".to_string())
+        }
+    } else {
+        let pixels: i32 = value.trim().parse().map_err(|e| format!("{e}"))?;
+        if pixels >= 1 {
+            Ok(IconSize::Pixels(pixels))
+        } else {
+            Err("size must be at least 1 pixel".to_string())
+        }
+    }
+}
+
+fn parse_opacity(value: &str) -> Result<f64, String> {
+    let opacity: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if (0.0..=1.0).contains(&opacity) {
+        Ok(opacity)
+    } else {
+        Err("opacity must be between 0.0 and 1.0".to_string())
+    }
 }
 
 #[derive(Parser, Debug)]
@@ -15,58 +712,661 @@ pub enum Position {
 #[command(about = "GTK4 overlay wrapper for waystt speech-to-text")]
 #[command(version)]
 pub struct Args {
-    /// Icon name from the system theme
-    #[arg(long, default_value = "audio-input-microphone-symbolic")]
+    /// Icon name from the system theme, or path to an image file
+    #[arg(long, visible_alias = "icon-recording", default_value = "audio-input-microphone-symbolic")]
     pub icon: String,
 
-    /// Icon size in pixels
-    #[arg(long, default_value = "96", value_parser = clap::value_parser!(i32).range(1..))]
-    pub icon_size: i32,
+    /// Icon shown while waiting for the command to finish after stopping (default: a spinner)
+    #[arg(long)]
+    pub icon_processing: Option<String>,
+
+    /// Icon shown when the command fails
+    #[arg(long, default_value = "dialog-error-symbolic")]
+    pub icon_error: String,
+
+    /// Icon shown while recording is paused
+    #[arg(long, default_value = "media-playback-pause-symbolic")]
+    pub icon_paused: String,
+
+    /// Text shown with the icon, e.g. which profile this hotkey runs (Pango markup)
+    #[arg(long, value_name = "TEXT")]
+    pub label: Option<String>,
+
+    /// Icon size in pixels, or as a percentage of the monitor's smaller dimension (e.g. 10%)
+    #[arg(long, value_name = "SIZE", default_value = "96", value_parser = parse_icon_size)]
+    pub icon_size: IconSize,
+
+    /// Large-print mode: icon of at least 48 px, larger labels, padding and spacing
+    #[arg(long)]
+    pub a11y_large: bool,
+
+    /// Ring the system bell on every state change (recording, paused, processing, error)
+    #[arg(long)]
+    pub bell: bool,
+
+    /// Fade the overlay out of the way after this many seconds of recording without speech or key
+    /// presses, until either comes back
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub auto_hide: Option<u64>,
+
+    /// Show a desktop notification when the transcription is done, failed or came out empty,
+    /// through this backend
+    #[arg(long, value_enum, value_name = "BACKEND")]
+    pub notify: Option<NotifyBackend>,
+
+    /// Also keep a notification up while recording, updated as the session goes on (paused,
+    /// transcribing) and replaced by the one for how it ended
+    #[arg(long, requires = "notify")]
+    pub notify_progress: bool,
+
+    /// Show the elapsed recording time under the icon
+    #[arg(long)]
+    pub show_timer: bool,
+
+    /// Show the keys that stop, pause and detach under the icon, as configured
+    #[arg(long)]
+    pub show_hints: bool,
+
+    /// Show a red "REC" dot next to the icon that blinks once a second while recording
+    #[arg(long)]
+    pub rec_dot: bool,
+
+    /// Show the microphone input level under the icon (needs `pw-record`)
+    #[arg(long)]
+    pub level_meter: bool,
 
     /// Position of the overlay on screen
     #[arg(long, value_enum, default_value = "center")]
     pub position: Position,
 
+    /// Overall shape: a floating square, or a full-width bar (at the top for top-* positions,
+    /// otherwise at the bottom)
+    #[arg(long, value_enum, default_value = "floating")]
+    pub appearance: Appearance,
+
     /// Margin from screen edges in pixels
     #[arg(long, default_value = "20", value_parser = clap::value_parser!(i32).range(0..))]
     pub margin: i32,
 
-    /// Command to execute (defaults to "waystt --pipe-to wl-copy")
+    /// Whether the pixel sizes and margins are logical (scaled with the output) or physical
+    #[arg(long, value_enum, default_value = "logical")]
+    pub units: Units,
+
+    /// Built-in color scheme
+    #[arg(long, value_enum, default_value = "dark")]
+    pub theme: Theme,
+
+    /// Icon colors for each state that stay apart with a color vision deficiency, over the
+    /// theme's single icon color
+    #[arg(long = "palette", value_enum, value_name = "PALETTE")]
+    pub state_palette: Option<StatePalette>,
+
+    /// Icon color (any CSS color), or `auto` to take the icon, background and text colors from
+    /// the GTK theme (defaults to the theme's)
+    #[arg(long, value_name = "COLOR|auto", value_parser = parse_icon_color)]
+    pub icon_color: Option<IconColor>,
+
+    /// Background color of the overlay (any CSS color; defaults to the theme's)
+    #[arg(long, value_parser = parse_css_color)]
+    pub background_color: Option<String>,
+
+    /// Background opacity, from 0.0 (transparent) to 1.0 (opaque; defaults to the theme's)
+    #[arg(long, value_parser = parse_opacity)]
+    pub opacity: Option<f64>,
+
+    /// Swap custom icon and text colors that fall short of the WCAG minimum contrast against the
+    /// background for black or white, instead of only warning about them
+    #[arg(long)]
+    pub enforce_contrast: bool,
+
+    /// Border width in pixels (defaults to the theme's border)
+    #[arg(long, value_name = "PX")]
+    pub border_width: Option<u32>,
+
+    /// Border color (any CSS color; defaults to the theme's text color)
+    #[arg(long, value_parser = parse_css_color)]
+    pub border_color: Option<String>,
+
+    /// Corner radius in pixels (defaults to 10, or square corners for the bar)
+    #[arg(long, value_name = "PX")]
+    pub corner_radius: Option<u32>,
+
+    /// Space between the icon and the overlay's edges, in pixels
+    #[arg(long, value_name = "PX", default_value = "10")]
+    pub padding: u32,
+
+    /// Draw a drop shadow around the overlay, so it stands out against light wallpapers
+    #[arg(long)]
+    pub shadow: bool,
+
+    /// Dim every output behind the overlay while recording, to mark dictation mode
+    #[arg(long)]
+    pub dim_screen: bool,
+
+    /// Fade the overlay in and out over this many milliseconds; 0 makes it pop
+    #[arg(long, value_name = "MS", default_value = "150")]
+    pub animation_duration: u64,
+
+    /// GTK CSS file applied after the built-in styles
+    /// (default: $XDG_CONFIG_HOME/waystt-wrapper/style.css, if it exists)
+    #[arg(long, value_name = "FILE")]
+    pub css: Option<PathBuf>,
+
+    /// GTK Builder (.ui) file to lay the overlay out with instead of the built-in layout. Its
+    /// root widget has the id `overlay`; the widgets it gives the ids `icon`, `timer`,
+    /// `transcript` (or `rec`, `spinner`, `level`, `status`, `caption`, `hint`, `keys`,
+    /// `error`, `warning`, `gain`, `onboarding`) are the ones the overlay drives
+    #[arg(long, value_name = "FILE")]
+    pub layout: Option<PathBuf>,
+
+    /// Layer-shell layer (defaults to overlay, or the compositor's quirk profile)
+    #[arg(long, value_enum)]
+    pub layer: Option<Layer>,
+
+    /// Keyboard interactivity (defaults to exclusive, or the compositor's quirk profile)
+    #[arg(long, value_enum)]
+    pub keyboard_mode: Option<KeyboardMode>,
+
+    /// Reserve this many pixels along the anchored edge (or `auto` for the overlay's own size),
+    /// so that windows are laid out around the overlay instead of under it
+    #[arg(long, value_name = "PX|auto", value_parser = parse_exclusive_zone)]
+    pub exclusive_zone: Option<ExclusiveZone>,
+
+    /// Output (monitor connector name, e.g. DP-1) to show the overlay on, or `focused` for the
+    /// one the user is working on (asked of sway, Hyprland or niri)
+    #[arg(long, value_name = "NAME")]
+    pub output: Option<String>,
+
+    /// Also show a copy of the overlay on every other output, following outputs as they are
+    /// connected and disconnected
+    #[arg(long)]
+    pub all_outputs: bool,
+
+    /// GTK renderer to use (sets `GSK_RENDERER`), e.g. `cairo` where the GL renderer flickers on
+    /// layer surfaces
+    #[arg(long, value_enum)]
+    pub renderer: Option<Renderer>,
+
+    /// GDK backends to try, in order (sets `GDK_BACKEND`, e.g. `wayland`)
+    #[arg(long, value_name = "BACKENDS")]
+    pub gdk_backend: Option<String>,
+
+    /// Clear the clipboard this many seconds after the copy preset put a transcription on it,
+    /// unless it changed since
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub clipboard_ttl: Option<u64>,
+
+    /// Seconds to wait for the child to exit after stopping it before escalating to SIGTERM and
+    /// then SIGKILL
+    #[arg(long, value_name = "SECS", default_value = "30", value_parser = clap::value_parser!(u64).range(1..))]
+    pub kill_timeout: u64,
+
+    /// Ignore stop, pause and detach requests repeated within this many milliseconds, and
+    /// launches this soon after another overlay started (hotkey bounce); 0 turns it off
+    #[arg(long, value_name = "MS", default_value = "200")]
+    pub debounce: u64,
+
+    /// Start the command before initializing GTK, so recording starts as soon as possible; skips
+    /// the check for other apps capturing the microphone
+    #[arg(long)]
+    pub spawn_first: bool,
+
+    /// Require the panic combo (Ctrl+Alt+Escape) to be pressed twice within a second before
+    /// closing every instance
+    #[arg(long)]
+    pub confirm_panic: bool,
+
+    /// Key (GDK key name) of the panic combo, which closes every instance
+    #[arg(long, value_name = "KEY", default_value = "Escape")]
+    pub panic_key: String,
+
+    /// Modifiers that have to be held with the panic key, joined with `+`
+    #[arg(long, value_enum, value_name = "MODS", value_delimiter = '+', default_value = "ctrl+alt")]
+    pub panic_modifiers: Vec<Modifier>,
+
+    /// Signal sent to every instance by the panic combo
+    #[arg(long, value_name = "SIGNAL", default_value = "SIGHUP", value_parser = parse_signal)]
+    pub panic_signal: Signal,
+
+    /// Disable the panic combo entirely
+    #[arg(long)]
+    pub no_panic: bool,
+
+    /// Stop recording automatically after this many seconds
+    #[arg(long, value_name = "SECS", value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_duration: Option<u64>,
+
+    /// Key (GDK key name) that pauses and resumes recording
+    #[arg(long, value_name = "KEY", default_value = "space")]
+    pub pause_key: String,
+
+    /// Key (GDK key name) that hides the overlay and lets the transcription finish in the
+    /// background
+    #[arg(long, value_name = "KEY", default_value = "d")]
+    pub detach_key: String,
+
+    /// Signal sent to the command to pause or resume recording
+    #[arg(long, value_name = "SIGNAL", default_value = "SIGUSR2", value_parser = parse_signal)]
+    pub pause_signal: Signal,
+
+    /// Where the command's stdout goes: inherit, null, log or capture
+    #[arg(long, value_enum, value_name = "MODE", default_value = "inherit")]
+    pub child_stdout: OutputMode,
+
+    /// Where the command's stderr goes: inherit, null, log or capture
+    #[arg(long, value_enum, value_name = "MODE", default_value = "inherit")]
+    pub child_stderr: OutputMode,
+
+    /// How many of the last lines of the command's stderr to show when it fails (needs
+    /// --child-stderr capture; 0 shows only the exit code)
+    #[arg(long, value_name = "N", default_value = "5")]
+    pub error_lines: usize,
+
+    /// What to do with the recording when the overlay is closed from outside: graceful, cancel
+    /// or detach
+    #[arg(long, value_enum, value_name = "MODE", default_value = "detach")]
+    pub on_close: OnClose,
+
+    /// Show the transcription (the command's stdout) in the overlay for a moment before it closes;
+    /// captures stdout unless --child-stdout is log or capture already
+    #[arg(long)]
+    pub preview: bool,
+
+    /// Print the command and overlay geometry that would be used, then exit without spawning
+    /// anything or opening a window
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the fully resolved configuration and exit
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "toml"
+    )]
+    pub print_config: Option<ConfigFormat>,
+
+    /// Print the stylesheet the overlay would apply (built-in styles, then the user stylesheet)
+    /// and exit
+    #[arg(long)]
+    pub dump_css: bool,
+
+    /// Log every overlay window, event controller and child process created and destroyed, and
+    /// their counts and the open file descriptors at restarts and exit, to spot leaks
+    #[arg(long)]
+    pub audit_lifecycle: bool,
+
+    /// Forget where the overlay was dragged to and place it by `--position` again
+    #[arg(long)]
+    pub reset_position: bool,
+
+    #[command(subcommand)]
+    pub action: Option<Action>,
+
+    /// Run a well-known waystt pipeline instead of a custom command
+    #[arg(long, value_enum, conflicts_with = "command")]
+    pub preset: Option<Preset>,
+
+    /// Translate the transcription into this language (e.g. `de`) before the preset delivers it
+    #[arg(long, value_name = "LANG", value_parser = parse_language, conflicts_with = "command")]
+    pub translate_to: Option<String>,
+
+    /// Shell command translating stdin to stdout for --translate-to; `{lang}` is replaced with the
+    /// language
+    #[arg(long, value_name = "CMD", default_value = DEFAULT_TRANSLATE_COMMAND)]
+    pub translate_command: String,
+
+    /// Leave the clipboard (or focused window) alone when nothing was transcribed, and show
+    /// "nothing transcribed" instead; a custom command has to create the file named by
+    /// $WAYSTT_WRAPPER_EMPTY itself
+    #[arg(long)]
+    pub skip_empty: bool,
+
+    /// If the clipboard already holds something, only replace it with the transcription once
+    /// Enter is pressed or the overlay clicked (copy preset only)
+    #[arg(long, conflicts_with = "command")]
+    pub confirm_overwrite: bool,
+
+    /// Exit with this code when nothing was transcribed (needs --skip-empty; default 0)
+    #[arg(
+        long,
+        value_name = "CODE",
+        requires = "skip_empty",
+        value_parser = clap::value_parser!(i32).range(1..=255)
+    )]
+    pub empty_exit_code: Option<i32>,
+
+    /// Run the command with only the variables it needs from the environment (PATH, HOME, the
+    /// locale, the Wayland and D-Bus sessions and the ones the wrapper sets for it), rather
+    /// than everything the wrapper was started with
+    #[arg(long)]
+    pub clean_env: bool,
+
+    /// Also pass this variable to the command with --clean-env (e.g. an API key); repeatable
+    #[arg(long, value_name = "VAR", requires = "clean_env")]
+    pub keep_env: Vec<String>,
+
+    /// Run the command through a login shell (`sh -l`), so that the PATH set up in ~/.profile
+    /// (e.g. ~/.local/bin for waystt) applies even when the compositor starts the wrapper
+    #[arg(long)]
+    pub login_shell: bool,
+
+    /// Command to execute (defaults to the `copy` preset, "waystt --pipe-to wl-copy")
     #[arg(trailing_var_arg = true, num_args = 0..)]
     pub command: Vec<String>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Config {
     pub icon: String,
-    pub icon_size: i32,
+    pub icon_processing: Option<String>,
+    pub icon_error: String,
+    pub icon_paused: String,
+    pub label: Option<String>,
+    pub icon_size: IconSize,
+    pub a11y_large: bool,
+    pub bell: bool,
+    pub notify: Option<NotifyBackend>,
+    pub notify_progress: bool,
+    pub auto_hide: Option<u64>,
+    pub show_timer: bool,
+    pub show_hints: bool,
+    pub rec_dot: bool,
+    pub level_meter: bool,
     pub position: Position,
+    pub appearance: Appearance,
     pub margin: i32,
+    pub units: Units,
+    pub theme: Theme,
+    pub state_palette: Option<StatePalette>,
+    /// The desktop's preference that `--theme auto` follows, once known
+    #[serde(skip)]
+    pub color_scheme: ColorScheme,
+    pub icon_color: Option<IconColor>,
+    pub background_color: String,
+    pub opacity: f64,
+    pub enforce_contrast: bool,
+    pub border_width: Option<u32>,
+    pub border_color: Option<String>,
+    pub corner_radius: Option<u32>,
+    pub padding: u32,
+    pub shadow: bool,
+    pub dim_screen: bool,
+    pub animation_duration: u64,
+    pub css: Option<PathBuf>,
+    pub layout: Option<PathBuf>,
+    pub layer: Option<Layer>,
+    pub keyboard_mode: Option<KeyboardMode>,
+    pub exclusive_zone: Option<ExclusiveZone>,
+    pub output: Option<String>,
+    pub all_outputs: bool,
+    pub renderer: Option<Renderer>,
+    pub gdk_backend: Option<String>,
+    pub clipboard_ttl: Option<u64>,
+    pub kill_timeout: u64,
+    pub debounce: u64,
+    pub spawn_first: bool,
+    pub confirm_panic: bool,
+    pub panic_key: String,
+    pub panic_modifiers: Vec<Modifier>,
+    #[serde(serialize_with = "serialize_signal")]
+    pub panic_signal: Signal,
+    pub no_panic: bool,
+    pub max_duration: Option<u64>,
+    pub pause_key: String,
+    pub detach_key: String,
+    #[serde(serialize_with = "serialize_signal")]
+    pub pause_signal: Signal,
+    pub child_stdout: OutputMode,
+    pub child_stderr: OutputMode,
+    pub error_lines: usize,
+    pub on_close: OnClose,
+    pub preview: bool,
+    /// The preset the command comes from, unless a custom command was given
+    pub preset: Option<Preset>,
+    pub translate_to: Option<String>,
+    pub translate_command: String,
+    pub skip_empty: bool,
+    pub empty_exit_code: Option<i32>,
+    pub confirm_overwrite: bool,
+    pub clean_env: bool,
+    pub keep_env: Vec<String>,
+    pub login_shell: bool,
     pub command: Vec<String>,
 }
 
+/// Write completions for `shell`, generated from the [`Args`] definition
+pub fn write_completions(shell: Shell, out: &mut impl Write) {
+    clap_complete::generate(shell, &mut Args::command(), env!("CARGO_PKG_NAME"), out);
+}
+
 impl From<Args> for Config {
     fn from(args: Args) -> Self {
-        let command = if args.command.is_empty() {
-            vec![
-                "waystt".to_string(),
-                "--pipe-to".to_string(),
-                "wl-copy".to_string(),
-            ]
+        let palette = palette(args.theme, args.icon_color.as_ref());
+        // Only the copy preset has a clipboard to overwrite
+        let copies = args.command.is_empty() && args.preset.unwrap_or_default() == Preset::Copy;
+        let hold = args.confirm_overwrite && copies;
+        if args.confirm_overwrite && !hold {
+            warn!("--confirm-overwrite only applies to the copy preset, ignoring it");
+        }
+        let (preset, command) = if args.command.is_empty() {
+            let preset = args.preset.unwrap_or_default();
+            let filter = args
+                .translate_to
+                .as_ref()
+                .map(|lang| args.translate_command.replace("{lang}", lang));
+            let command = preset.command_with(filter.as_deref(), args.skip_empty, hold);
+            (Some(preset), command)
         } else {
-            args.command
+            (None, args.command)
         };
+        let command = if args.login_shell { through_login_shell(command) } else { command };
 
         Self {
             icon: args.icon,
+            icon_processing: args.icon_processing,
+            icon_error: args.icon_error,
+            icon_paused: args.icon_paused,
+            label: args.label,
             icon_size: args.icon_size,
+            a11y_large: args.a11y_large,
+            bell: args.bell,
+            notify: args.notify,
+            notify_progress: args.notify_progress,
+            auto_hide: args.auto_hide,
+            show_timer: args.show_timer,
+            show_hints: args.show_hints,
+            rec_dot: args.rec_dot,
+            level_meter: args.level_meter,
             position: args.position,
+            appearance: args.appearance,
             margin: args.margin,
+            units: args.units,
+            theme: args.theme,
+            state_palette: args.state_palette,
+            color_scheme: ColorScheme::NoPreference,
+            icon_color: args.icon_color,
+            background_color: args
+                .background_color
+                .unwrap_or_else(|| palette.background.to_string()),
+            opacity: args.opacity.unwrap_or(palette.opacity),
+            enforce_contrast: args.enforce_contrast,
+            border_width: args.border_width,
+            border_color: args.border_color,
+            corner_radius: args.corner_radius,
+            padding: args.padding,
+            shadow: args.shadow,
+            dim_screen: args.dim_screen,
+            animation_duration: args.animation_duration,
+            css: args.css,
+            layout: args.layout,
+            layer: args.layer,
+            keyboard_mode: args.keyboard_mode,
+            exclusive_zone: args.exclusive_zone,
+            output: args.output,
+            all_outputs: args.all_outputs,
+            renderer: args.renderer,
+            gdk_backend: args.gdk_backend,
+            clipboard_ttl: args.clipboard_ttl,
+            kill_timeout: args.kill_timeout,
+            debounce: args.debounce,
+            spawn_first: args.spawn_first,
+            confirm_panic: args.confirm_panic,
+            panic_key: args.panic_key,
+            panic_modifiers: args.panic_modifiers,
+            panic_signal: args.panic_signal,
+            no_panic: args.no_panic,
+            max_duration: args.max_duration,
+            pause_key: args.pause_key,
+            detach_key: args.detach_key,
+            pause_signal: args.pause_signal,
+            // The preview reads stdout, which is still written out at exit when captured
+            child_stdout: match args.child_stdout {
+                OutputMode::Inherit | OutputMode::Null if args.preview => OutputMode::Capture,
+                mode => mode,
+            },
+            child_stderr: args.child_stderr,
+            error_lines: args.error_lines,
+            on_close: args.on_close,
+            preview: args.preview,
+            preset,
+            translate_to: args.translate_to,
+            translate_command: args.translate_command,
+            skip_empty: args.skip_empty,
+            empty_exit_code: args.empty_exit_code,
+            confirm_overwrite: hold,
+            clean_env: args.clean_env,
+            keep_env: args.keep_env,
+            login_shell: args.login_shell,
             command,
         }
     }
 }
 
+/// Directory holding the user's waystt-wrapper configuration
+pub fn config_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("waystt-wrapper"))
+}
+
+impl Config {
+    /// Programs the preset (and its translation step) needs, or none for a custom command or
+    /// with `--login-shell`, since only the login shell knows the PATH to look for them in
+    pub fn required_programs(&self) -> Vec<&str> {
+        let Some(preset) = self.preset.filter(|_| !self.login_shell) else {
+            return Vec::new();
+        };
+        let mut programs = preset.programs().to_vec();
+        if self.translate_to.is_some() {
+            programs.extend(self.translate_command.split_whitespace().next());
+        }
+        programs
+    }
+
+    /// The colors to draw with: the theme's, or the GTK theme's for `--icon-color auto`
+    pub fn palette(&self) -> Palette {
+        palette(self.theme.resolve(self.color_scheme), self.icon_color.as_ref())
+    }
+
+    /// Follow a change of the desktop's dark/light preference with `--theme auto`. The
+    /// background and opacity change with the palette, unless they were set explicitly.
+    pub fn follow_color_scheme(&mut self, scheme: ColorScheme) {
+        let old = self.palette();
+        self.color_scheme = scheme;
+        let new = self.palette();
+        if self.background_color == old.background {
+            self.background_color = new.background.to_string();
+        }
+        if self.opacity == old.opacity {
+            self.opacity = new.opacity;
+        }
+    }
+
+    /// Apply a setting changed at runtime. A new theme brings its own background and opacity,
+    /// replacing `--background-color` and `--opacity`.
+    pub fn apply(&mut self, setting: &Setting) {
+        match setting {
+            Setting::Icon(icon) => self.icon = icon.clone(),
+            Setting::Label(label) => {
+                self.label = Some(label.clone()).filter(|label| !label.is_empty());
+            }
+            Setting::Position(position) => self.position = *position,
+            Setting::Theme(theme) => {
+                self.theme = *theme;
+                let palette = self.palette();
+                self.background_color = palette.background.to_string();
+                self.opacity = palette.opacity;
+            }
+        }
+    }
+
+    /// Environment variables GTK reads at startup for `--renderer` and `--gdk-backend`
+    pub fn render_env(&self) -> Vec<(&'static str, String)> {
+        let mut env = Vec::new();
+        if let Some(renderer) = self.renderer {
+            env.push(("GSK_RENDERER", renderer.env_value().to_string()));
+        }
+        if let Some(backend) = &self.gdk_backend {
+            env.push(("GDK_BACKEND", backend.clone()));
+        }
+        env
+    }
+
+    /// Caption for `--show-hints`, naming the configured keys, e.g. "Esc to stop · Space to
+    /// pause · D to detach"
+    pub fn key_hints(&self) -> String {
+        [
+            ("Escape", "stop"),
+            (self.pause_key.as_str(), "pause"),
+            (self.detach_key.as_str(), "detach"),
+        ]
+        .map(|(key, action)| format!("{} to {action}", key_label(key)))
+        .join(" · ")
+    }
+
+    /// The configuration with its pixel sizes and margins in logical pixels on an output of
+    /// `scale`, which GTK and the compositor work in. Only `--units physical` changes anything.
+    pub fn in_logical_pixels(&self, scale: i32) -> Config {
+        let mut config = self.clone();
+        if self.units == Units::Logical || scale <= 1 {
+            return config;
+        }
+        let logical = |pixels: i32| (pixels as f64 / scale as f64).round() as i32;
+        let logical_u32 = |pixels: u32| logical(pixels as i32) as u32;
+        config.margin = logical(self.margin);
+        if let IconSize::Pixels(pixels) = self.icon_size {
+            config.icon_size = IconSize::Pixels(logical(pixels).max(1));
+        }
+        config.padding = logical_u32(self.padding);
+        // A hairline border stays visible
+        config.border_width = self.border_width.map(|width| logical_u32(width).max(width.min(1)));
+        config.corner_radius = self.corner_radius.map(logical_u32);
+        config
+    }
+
+    /// The user stylesheet to load: `--css` if given, otherwise the default one if it exists
+    pub fn user_css(&self) -> Option<PathBuf> {
+        self.css.clone().or_else(|| {
+            config_dir()
+                .map(|dir| dir.join("style.css"))
+                .filter(|path| path.is_file())
+        })
+    }
+
+    /// Render the resolved configuration in the requested format
+    pub fn render(&self, format: ConfigFormat) -> Result<String, ConfigError> {
+        match format {
+            ConfigFormat::Toml => Ok(toml::to_string_pretty(self)?),
+            ConfigFormat::Json => Ok(serde_json::to_string_pretty(self)?),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -89,34 +1389,170 @@ mod tests {
         let args = Args::try_parse_from(&["waystt-wrapper", "--position", "top-left"]).unwrap();
         assert!(matches!(args.position, Position::TopLeft));
 
-        // Test TopRight
-        let args = Args::try_parse_from(&["waystt-wrapper", "--position", "top-right"]).unwrap();
-        assert!(matches!(args.position, Position::TopRight));
+        // Test TopRight
+        let args = Args::try_parse_from(&["waystt-wrapper", "--position", "top-right"]).unwrap();
+        assert!(matches!(args.position, Position::TopRight));
+
+        // Test BottomLeft
+        let args =
+            Args::try_parse_from(&["waystt-wrapper", "--position", "bottom-left"]).unwrap();
+        assert!(matches!(args.position, Position::BottomLeft));
+
+        // Test BottomRight
+        let args =
+            Args::try_parse_from(&["waystt-wrapper", "--position", "bottom-right"]).unwrap();
+        assert!(matches!(args.position, Position::BottomRight));
+
+        // Test Center
+        let args = Args::try_parse_from(&["waystt-wrapper", "--position", "center"]).unwrap();
+        assert!(matches!(args.position, Position::Center));
+
+        // Test edge-centered positions
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "top"]).unwrap();
+        assert!(matches!(args.position, Position::Top));
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "bottom"]).unwrap();
+        assert!(matches!(args.position, Position::Bottom));
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "left"]).unwrap();
+        assert!(matches!(args.position, Position::Left));
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "right"]).unwrap();
+        assert!(matches!(args.position, Position::Right));
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "above-window"]).unwrap();
+        assert!(matches!(args.position, Position::AboveWindow));
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "cursor"]).unwrap();
+        assert!(matches!(args.position, Position::Cursor));
+    }
+
+    #[test]
+    fn test_config_default_command() {
+        let args = Args::try_parse_from(&["waystt-wrapper"]).unwrap();
+        let config = Config::from(args);
+
+        assert_eq!(config.command.len(), 3);
+        assert_eq!(config.command[0], "waystt");
+        assert_eq!(config.command[1], "--pipe-to");
+        assert_eq!(config.command[2], "wl-copy");
+    }
+
+    #[test]
+    fn test_presets() {
+        let command = |preset| {
+            let args = Args::try_parse_from(["waystt-wrapper", "--preset", preset]).unwrap();
+            Config::from(args).command
+        };
+        assert_eq!(command("copy"), vec!["waystt", "--pipe-to", "wl-copy"]);
+        assert_eq!(command("type"), vec!["waystt", "--pipe-to", "wtype", "-"]);
+        assert_eq!(command("stdout"), vec!["waystt"]);
+    }
+
+    #[test]
+    fn test_every_preset_has_a_command() {
+        for preset in Preset::value_variants() {
+            assert!(!preset.command().is_empty(), "{preset:?}");
+            assert!(preset.programs().contains(&preset.command()[0].as_str()));
+        }
+    }
+
+    #[test]
+    fn test_default_command_comes_from_copy_preset() {
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper"]).unwrap());
+        assert_eq!(config.preset, Some(Preset::Copy));
+        assert_eq!(Preset::Copy.programs(), ["waystt", "wl-copy"]);
+    }
+
+    #[test]
+    fn test_translation_goes_between_waystt_and_the_sink() {
+        let config = |args: &[&str]| {
+            Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+        };
+        let copy = config(&["--translate-to", "de"]);
+        assert_eq!(
+            copy.command,
+            vec!["waystt", "--pipe-to", "sh", "-c", "trans -brief :de | wl-copy"]
+        );
+        assert_eq!(copy.required_programs(), vec!["waystt", "wl-copy", "trans"]);
+
+        let stdout = config(&[
+            "--preset",
+            "stdout",
+            "--translate-to",
+            "pt-BR",
+            "--translate-command",
+            "my-translator --to {lang}",
+        ]);
+        assert_eq!(
+            stdout.command,
+            vec!["waystt", "--pipe-to", "sh", "-c", "my-translator --to pt-BR"]
+        );
+        assert_eq!(stdout.required_programs(), vec!["waystt", "my-translator"]);
+    }
+
+    #[test]
+    fn test_skip_empty_guards_the_sink() {
+        let config = |args: &[&str]| {
+            Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+        };
+        let copy = config(&["--skip-empty"]);
+        assert_eq!(copy.command[..4], ["waystt", "--pipe-to", "sh", "-c"]);
+        assert!(copy.command[4].contains("printf '%s' \"$text\" | wl-copy ;;"));
+        assert!(copy.command[4].contains(": > \"$WAYSTT_WRAPPER_EMPTY\""));
+        assert_eq!(copy.required_programs(), vec!["waystt", "wl-copy"]);
+
+        let translated = config(&["--skip-empty", "--translate-to", "de"]);
+        assert!(translated.command[4].contains("| trans -brief :de | wl-copy ;;"));
 
-        // Test BottomLeft
-        let args =
-            Args::try_parse_from(&["waystt-wrapper", "--position", "bottom-left"]).unwrap();
-        assert!(matches!(args.position, Position::BottomLeft));
+        let stdout = config(&["--skip-empty", "--preset", "stdout"]);
+        assert!(stdout.command[4].contains("| cat ;;"));
 
-        // Test BottomRight
-        let args =
-            Args::try_parse_from(&["waystt-wrapper", "--position", "bottom-right"]).unwrap();
-        assert!(matches!(args.position, Position::BottomRight));
+        assert!(Args::try_parse_from(["waystt-wrapper", "--empty-exit-code", "3"]).is_err());
+        let args = ["waystt-wrapper", "--skip-empty", "--empty-exit-code", "0"];
+        assert!(Args::try_parse_from(args).is_err());
+    }
 
-        // Test Center
-        let args = Args::try_parse_from(&["waystt-wrapper", "--position", "center"]).unwrap();
-        assert!(matches!(args.position, Position::Center));
+    #[test]
+    fn test_login_shell_wraps_the_command() {
+        let config = |args: &[&str]| {
+            Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+        };
+        let login = config(&["--login-shell", "--", "waystt", "--pipe-to", "cat"]);
+        assert_eq!(
+            login.command,
+            vec!["sh", "-lc", "exec \"$0\" \"$@\"", "waystt", "--pipe-to", "cat"]
+        );
+        let preset = config(&["--login-shell"]);
+        assert_eq!(preset.command[3..], ["waystt", "--pipe-to", "wl-copy"]);
+        assert!(preset.required_programs().is_empty());
     }
 
     #[test]
-    fn test_config_default_command() {
-        let args = Args::try_parse_from(&["waystt-wrapper"]).unwrap();
-        let config = Config::from(args);
+    fn test_confirm_overwrite_holds_the_transcription_back() {
+        let config = |args: &[&str]| {
+            Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+        };
+        assert_eq!(
+            config(&["--confirm-overwrite"]).command,
+            vec!["waystt", "--pipe-to", "sh", "-c", "cat > \"$WAYSTT_WRAPPER_PENDING\""]
+        );
+        let translated = config(&["--confirm-overwrite", "--translate-to", "de"]);
+        assert_eq!(translated.command[4], "trans -brief :de | cat > \"$WAYSTT_WRAPPER_PENDING\"");
+        // Nothing to overwrite
+        let typed = config(&["--confirm-overwrite", "--preset", "type"]);
+        assert_eq!(typed.command, vec!["waystt", "--pipe-to", "wtype", "-"]);
+        assert!(!typed.confirm_overwrite);
+    }
 
-        assert_eq!(config.command.len(), 3);
-        assert_eq!(config.command[0], "waystt");
-        assert_eq!(config.command[1], "--pipe-to");
-        assert_eq!(config.command[2], "wl-copy");
+    #[test]
+    fn test_translate_to_rejects_shell_syntax() {
+        let result = Args::try_parse_from(["waystt-wrapper", "--translate-to", "de; rm -rf ~"]);
+        assert!(result.is_err());
+        let result = Args::try_parse_from(["waystt-wrapper", "--translate-to", "de", "--", "echo"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_preset_conflicts_with_command() {
+        let result = Args::try_parse_from(["waystt-wrapper", "--preset", "type", "--", "echo"]);
+        assert!(result.is_err());
     }
 
     #[test]
@@ -189,19 +1625,151 @@ mod tests {
     fn test_config_conversion_preserves_fields() {
         let args = Args {
             icon: "test-icon".to_string(),
-            icon_size: 150,
+            icon_processing: Some("test-processing".to_string()),
+            icon_error: "test-error".to_string(),
+            icon_paused: "test-paused".to_string(),
+            label: Some("<b>Work</b>".to_string()),
+            icon_size: IconSize::Pixels(150),
+            a11y_large: true,
+            bell: true,
+            notify: Some(NotifyBackend::Dbus),
+            notify_progress: true,
+            auto_hide: Some(30),
+            show_timer: true,
+            show_hints: true,
+            rec_dot: true,
+            level_meter: true,
             position: Position::BottomRight,
+            appearance: Appearance::Bar,
             margin: 40,
+            units: Units::Physical,
+            theme: Theme::HighContrast,
+            state_palette: Some(StatePalette::Tritanopia),
+            icon_color: Some(IconColor::Css("lime".to_string())),
+            background_color: Some("black".to_string()),
+            opacity: Some(1.0),
+            enforce_contrast: true,
+            border_width: Some(4),
+            border_color: Some("red".to_string()),
+            corner_radius: Some(4),
+            padding: 6,
+            shadow: true,
+            dim_screen: true,
+            animation_duration: 0,
+            css: Some(PathBuf::from("/tmp/style.css")),
+            layout: Some(PathBuf::from("/tmp/overlay.ui")),
+            layer: Some(Layer::Bottom),
+            keyboard_mode: Some(KeyboardMode::OnDemand),
+            exclusive_zone: Some(ExclusiveZone::Auto),
+            output: Some("DP-1".to_string()),
+            all_outputs: true,
+            renderer: Some(Renderer::Cairo),
+            gdk_backend: Some("wayland".to_string()),
+            clipboard_ttl: Some(30),
+            kill_timeout: 5,
+            debounce: 0,
+            spawn_first: true,
+            confirm_panic: true,
+            panic_key: "q".to_string(),
+            panic_modifiers: vec![Modifier::Super],
+            panic_signal: Signal::SIGTERM,
+            no_panic: true,
+            max_duration: Some(120),
+            pause_key: "p".to_string(),
+            detach_key: "h".to_string(),
+            pause_signal: Signal::SIGUSR1,
+            child_stdout: OutputMode::Capture,
+            child_stderr: OutputMode::Null,
+            error_lines: 3,
+            on_close: OnClose::Graceful,
+            preview: true,
+            print_config: None,
+            dump_css: false,
+            reset_position: false,
+            audit_lifecycle: false,
+            action: None,
+            preset: None,
+            translate_to: None,
+            translate_command: "translate".to_string(),
+            skip_empty: true,
+            empty_exit_code: Some(3),
+            confirm_overwrite: true,
+            clean_env: true,
+            keep_env: vec!["OPENAI_API_KEY".to_string()],
+            login_shell: false,
+            dry_run: false,
             command: vec!["test".to_string()],
         };
 
         let config = Config::from(args);
 
         assert_eq!(config.icon, "test-icon");
+        assert_eq!(config.icon_processing.as_deref(), Some("test-processing"));
+        assert_eq!(config.icon_error, "test-error");
         assert_eq!(config.icon_size, 150);
+        assert!(config.a11y_large);
+        assert!(config.bell);
+        assert_eq!(config.notify, Some(NotifyBackend::Dbus));
+        assert!(config.notify_progress);
+        assert_eq!(config.auto_hide, Some(30));
+        assert!(config.show_timer);
+        assert!(config.show_hints);
+        assert!(config.rec_dot);
+        assert!(config.level_meter);
+        assert_eq!(config.appearance, Appearance::Bar);
         assert!(matches!(config.position, Position::BottomRight));
         assert_eq!(config.margin, 40);
+        assert_eq!(config.units, Units::Physical);
+        assert_eq!(config.theme, Theme::HighContrast);
+        assert_eq!(config.state_palette, Some(StatePalette::Tritanopia));
+        assert_eq!(config.icon_color, Some(IconColor::Css("lime".to_string())));
+        assert_eq!(config.border_width, Some(4));
+        assert_eq!(config.border_color.as_deref(), Some("red"));
+        assert_eq!(config.corner_radius, Some(4));
+        assert_eq!(config.padding, 6);
+        assert!(config.shadow);
+        assert!(config.dim_screen);
+        assert_eq!(config.animation_duration, 0);
+        assert_eq!(config.background_color, "black");
+        assert_eq!(config.opacity, 1.0);
+        assert!(config.enforce_contrast);
+        assert_eq!(config.css, Some(PathBuf::from("/tmp/style.css")));
+        assert_eq!(config.layout, Some(PathBuf::from("/tmp/overlay.ui")));
+        assert_eq!(config.layer, Some(Layer::Bottom));
+        assert_eq!(config.keyboard_mode, Some(KeyboardMode::OnDemand));
+        assert_eq!(config.exclusive_zone, Some(ExclusiveZone::Auto));
+        assert_eq!(config.output.as_deref(), Some("DP-1"));
+        assert!(config.all_outputs);
+        assert_eq!(config.renderer, Some(Renderer::Cairo));
+        assert_eq!(config.gdk_backend.as_deref(), Some("wayland"));
+        assert_eq!(config.clipboard_ttl, Some(30));
+        assert_eq!(config.kill_timeout, 5);
+        assert_eq!(config.debounce, 0);
+        assert!(config.spawn_first);
+        assert!(config.confirm_panic);
+        assert_eq!(config.panic_key, "q");
+        assert_eq!(config.panic_modifiers, vec![Modifier::Super]);
+        assert_eq!(config.panic_signal, Signal::SIGTERM);
+        assert!(config.no_panic);
+        assert_eq!(config.max_duration, Some(120));
+        assert_eq!(config.icon_paused, "test-paused");
+        assert_eq!(config.label.as_deref(), Some("<b>Work</b>"));
+        assert_eq!(config.pause_key, "p");
+        assert_eq!(config.detach_key, "h");
+        assert_eq!(config.pause_signal, Signal::SIGUSR1);
+        assert_eq!(config.child_stdout, OutputMode::Capture);
+        assert_eq!(config.child_stderr, OutputMode::Null);
+        assert_eq!(config.error_lines, 3);
+        assert_eq!(config.on_close, OnClose::Graceful);
+        assert!(config.preview);
+        assert_eq!(config.preset, None);
         assert_eq!(config.command, vec!["test"]);
+        assert!(config.skip_empty);
+        assert_eq!(config.empty_exit_code, Some(3));
+        // Only the copy preset holds the transcription back
+        assert!(!config.confirm_overwrite);
+        assert!(config.clean_env);
+        assert_eq!(config.keep_env, ["OPENAI_API_KEY"]);
     }
 
     #[test]
@@ -227,4 +1795,587 @@ mod tests {
         let args = Args::try_parse_from(&["waystt-wrapper", "--margin", "0"]).unwrap();
         assert_eq!(args.margin, 0);
     }
+
+    #[test]
+    fn test_print_config_absent_by_default() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.print_config, None);
+    }
+
+    #[test]
+    fn test_print_config_defaults_to_toml() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--print-config"]).unwrap();
+        assert_eq!(args.print_config, Some(ConfigFormat::Toml));
+    }
+
+    #[test]
+    fn test_render_env() {
+        let args = ["waystt-wrapper", "--renderer", "cairo", "--gdk-backend", "wayland"];
+        let config = Config::from(Args::try_parse_from(args).unwrap());
+        assert_eq!(
+            config.render_env(),
+            vec![
+                ("GSK_RENDERER", "cairo".to_string()),
+                ("GDK_BACKEND", "wayland".to_string())
+            ]
+        );
+
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper"]).unwrap());
+        assert!(config.render_env().is_empty());
+    }
+
+    #[test]
+    fn test_key_hints() {
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper"]).unwrap());
+        assert_eq!(config.key_hints(), "Esc to stop · Space to pause · D to detach");
+
+        let args = ["waystt-wrapper", "--pause-key", "Pause", "--detach-key", "Return"];
+        let config = Config::from(Args::try_parse_from(args).unwrap());
+        assert_eq!(config.key_hints(), "Esc to stop · Pause to pause · Enter to detach");
+
+        assert_eq!(key_label("page_down"), "Page Down");
+        assert_eq!(key_label("F5"), "F5");
+    }
+
+    #[test]
+    fn test_in_logical_pixels() {
+        let args = [
+            "waystt-wrapper",
+            "--units",
+            "physical",
+            "--margin",
+            "40",
+            "--icon-size",
+            "96",
+            "--padding",
+            "5",
+            "--border-width",
+            "1",
+        ];
+        let config = Config::from(Args::try_parse_from(args).unwrap());
+        let logical = config.in_logical_pixels(2);
+        assert_eq!(logical.margin, 20);
+        assert_eq!(logical.icon_size, 48);
+        assert_eq!(logical.padding, 3);
+        assert_eq!(logical.border_width, Some(1));
+        assert_eq!(config.in_logical_pixels(1).margin, 40);
+
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper"]).unwrap());
+        assert_eq!(config.in_logical_pixels(2).margin, 20);
+        assert_eq!(config.in_logical_pixels(2).icon_size, 96);
+    }
+
+    #[test]
+    fn test_audit_lifecycle() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--audit-lifecycle"]).unwrap();
+        assert!(args.audit_lifecycle);
+    }
+
+    #[test]
+    fn test_reset_position() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--reset-position"]).unwrap();
+        assert!(args.reset_position);
+    }
+
+    #[test]
+    fn test_dump_css() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--dump-css"]).unwrap();
+        assert!(args.dump_css);
+    }
+
+    #[test]
+    fn test_print_config_json() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--print-config=json"]).unwrap();
+        assert_eq!(args.print_config, Some(ConfigFormat::Json));
+    }
+
+    #[test]
+    fn test_print_config_does_not_consume_command() {
+        let args =
+            Args::try_parse_from(["waystt-wrapper", "--print-config", "--", "echo"]).unwrap();
+        assert_eq!(args.print_config, Some(ConfigFormat::Toml));
+        assert_eq!(args.command, vec!["echo"]);
+    }
+
+    #[test]
+    fn test_render_toml() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--position", "top-left"]).unwrap();
+        let rendered = Config::from(args).render(ConfigFormat::Toml).unwrap();
+        assert!(rendered.contains("position = \"top-left\""));
+        assert!(rendered.contains("\"wl-copy\""));
+    }
+
+    #[test]
+    fn test_render_json() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--margin", "5"]).unwrap();
+        let rendered = Config::from(args).render(ConfigFormat::Json).unwrap();
+        assert!(rendered.contains("\"margin\": 5"));
+    }
+
+    #[test]
+    fn test_clipboard_ttl() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.clipboard_ttl, None);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--clipboard-ttl", "15"]).unwrap();
+        assert_eq!(args.clipboard_ttl, Some(15));
+    }
+
+    #[test]
+    fn test_kill_timeout() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.kill_timeout, 30);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--kill-timeout", "3"]).unwrap();
+        assert_eq!(args.kill_timeout, 3);
+
+        assert!(Args::try_parse_from(["waystt-wrapper", "--kill-timeout", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_max_duration() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.max_duration, None);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--max-duration", "60"]).unwrap();
+        assert_eq!(args.max_duration, Some(60));
+
+        assert!(Args::try_parse_from(["waystt-wrapper", "--max-duration", "0"]).is_err());
+    }
+
+    #[test]
+    fn test_clipboard_ttl_rejects_zero() {
+        let result = Args::try_parse_from(["waystt-wrapper", "--clipboard-ttl", "0"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_doctor_subcommand() {
+        let args = Args::try_parse_from(["waystt-wrapper", "doctor"]).unwrap();
+        assert_eq!(args.action, Some(Action::Doctor));
+        assert!(args.command.is_empty());
+    }
+
+    #[test]
+    fn test_doctor_as_command_after_separator() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--", "doctor"]).unwrap();
+        assert_eq!(args.action, None);
+        assert_eq!(args.command, vec!["doctor"]);
+    }
+
+    #[test]
+    fn test_output() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.output, None);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--output", "HDMI-A-1"]).unwrap();
+        assert_eq!(args.output.as_deref(), Some("HDMI-A-1"));
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--output", "focused"]).unwrap();
+        assert_eq!(args.output.as_deref(), Some(crate::focus::FOCUSED));
+    }
+
+    #[test]
+    fn test_layer_parsing() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.layer, None);
+
+        for (value, layer) in [
+            ("background", Layer::Background),
+            ("bottom", Layer::Bottom),
+            ("top", Layer::Top),
+            ("overlay", Layer::Overlay),
+        ] {
+            let args = Args::try_parse_from(["waystt-wrapper", "--layer", value]).unwrap();
+            assert_eq!(args.layer, Some(layer));
+        }
+    }
+
+    #[test]
+    fn test_invalid_layer() {
+        let result = Args::try_parse_from(["waystt-wrapper", "--layer", "fullscreen"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_keyboard_mode_parsing() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.keyboard_mode, None);
+
+        for (value, mode) in [
+            ("exclusive", KeyboardMode::Exclusive),
+            ("on-demand", KeyboardMode::OnDemand),
+            ("none", KeyboardMode::None),
+        ] {
+            let args = Args::try_parse_from(["waystt-wrapper", "--keyboard-mode", value]).unwrap();
+            assert_eq!(args.keyboard_mode, Some(mode));
+        }
+    }
+
+    #[test]
+    fn test_state_icons() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.icon_processing, None);
+        assert_eq!(args.icon_error, "dialog-error-symbolic");
+
+        let args = Args::try_parse_from([
+            "waystt-wrapper",
+            "--icon-recording",
+            "rec",
+            "--icon-processing",
+            "busy",
+            "--icon-error",
+            "oops",
+        ])
+        .unwrap();
+        assert_eq!(args.icon, "rec");
+        assert_eq!(args.icon_processing.as_deref(), Some("busy"));
+        assert_eq!(args.icon_error, "oops");
+    }
+
+    #[test]
+    fn test_background_defaults() {
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper"]).unwrap());
+        assert_eq!(config.theme, Theme::Dark);
+        assert_eq!(config.background_color, "rgb(50, 50, 50)");
+        assert_eq!(config.opacity, 0.8);
+    }
+
+    #[test]
+    fn test_background_follows_theme() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--theme", "high-contrast"]).unwrap();
+        let config = Config::from(args);
+        assert_eq!(config.background_color, "black");
+        assert_eq!(config.opacity, 1.0);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--theme", "light", "--opacity", "0.5"])
+            .unwrap();
+        let config = Config::from(args);
+        assert_eq!(config.background_color, Theme::Light.palette().background);
+        assert_eq!(config.opacity, 0.5);
+    }
+
+    #[test]
+    fn test_icon_color_auto_follows_gtk_theme() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--icon-color", "auto"]).unwrap();
+        let config = Config::from(args);
+        assert_eq!(config.icon_color, Some(IconColor::Auto));
+        assert_eq!(config.background_color, theme_colors::BACKGROUND);
+        assert_eq!(config.palette().icon, theme_colors::ACCENT);
+        // The theme still decides the opacity
+        assert_eq!(config.opacity, 0.8);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--icon-color", "#00ff00"]).unwrap();
+        assert_eq!(args.icon_color, Some(IconColor::Css("#00ff00".to_string())));
+        assert!(Args::try_parse_from(["waystt-wrapper", "--icon-color", "red; }"]).is_err());
+    }
+
+    #[test]
+    fn test_background_custom() {
+        let args = Args::try_parse_from([
+            "waystt-wrapper",
+            "--background-color",
+            "#202020",
+            "--opacity",
+            "1",
+        ])
+        .unwrap();
+        assert_eq!(args.background_color.as_deref(), Some("#202020"));
+        assert_eq!(args.opacity, Some(1.0));
+    }
+
+    #[test]
+    fn test_background_color_rejects_css_injection() {
+        let result =
+            Args::try_parse_from(["waystt-wrapper", "--background-color", "red; } * {"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_opacity_rejects_out_of_range() {
+        assert!(Args::try_parse_from(["waystt-wrapper", "--opacity", "1.5"]).is_err());
+        assert!(Args::try_parse_from(["waystt-wrapper", "--opacity", "-0.1"]).is_err());
+        assert!(Args::try_parse_from(["waystt-wrapper", "--opacity", "abc"]).is_err());
+    }
+
+    #[test]
+    fn test_css_path() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.css, None);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--css", "theme.css"]).unwrap();
+        let config = Config::from(args);
+        assert_eq!(config.user_css(), Some(PathBuf::from("theme.css")));
+    }
+
+    #[test]
+    fn test_pause_defaults() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.pause_key, "space");
+        assert_eq!(args.pause_signal, Signal::SIGUSR2);
+        assert_eq!(args.icon_paused, "media-playback-pause-symbolic");
+    }
+
+    #[test]
+    fn test_signal_parsing() {
+        for name in ["usr1", "SIGUSR1", "USR1", "sigusr1"] {
+            let args = Args::try_parse_from(["waystt-wrapper", "--pause-signal", name]).unwrap();
+            assert_eq!(args.pause_signal, Signal::SIGUSR1);
+        }
+        assert!(Args::try_parse_from(["waystt-wrapper", "--pause-signal", "nope"]).is_err());
+    }
+
+    #[test]
+    fn test_pause_signal_renders_by_name() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        let rendered = Config::from(args).render(ConfigFormat::Toml).unwrap();
+        assert!(rendered.contains("pause_signal = \"SIGUSR2\""));
+    }
+
+    #[test]
+    fn test_panic_combo_defaults() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.panic_key, "Escape");
+        assert_eq!(args.panic_modifiers, vec![Modifier::Ctrl, Modifier::Alt]);
+        assert_eq!(args.panic_signal, Signal::SIGHUP);
+        assert!(!args.no_panic);
+    }
+
+    #[test]
+    fn test_panic_modifiers_parsing() {
+        let args =
+            Args::try_parse_from(["waystt-wrapper", "--panic-modifiers", "super+shift"]).unwrap();
+        assert_eq!(args.panic_modifiers, vec![Modifier::Super, Modifier::Shift]);
+
+        assert!(Args::try_parse_from(["waystt-wrapper", "--panic-modifiers", "hyper"]).is_err());
+    }
+
+    #[test]
+    fn test_child_output_modes() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.child_stdout, OutputMode::Inherit);
+        assert_eq!(args.child_stderr, OutputMode::Inherit);
+
+        let args = Args::try_parse_from([
+            "waystt-wrapper",
+            "--child-stdout",
+            "null",
+            "--child-stderr",
+            "log",
+        ])
+        .unwrap();
+        assert_eq!(args.child_stdout, OutputMode::Null);
+        assert_eq!(args.child_stderr, OutputMode::Log);
+    }
+
+    #[test]
+    fn test_preview_reads_stdout() {
+        let config = Config::from(Args::try_parse_from(["waystt-wrapper", "--preview"]).unwrap());
+        assert_eq!(config.child_stdout, OutputMode::Capture);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--preview", "--child-stdout", "log"]);
+        assert_eq!(Config::from(args.unwrap()).child_stdout, OutputMode::Log);
+    }
+
+    #[test]
+    fn test_icon_size_percent() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--icon-size", "10%"]).unwrap();
+        assert_eq!(args.icon_size, IconSize::Percent(10.0));
+        assert_eq!(args.icon_size.resolve(1920, 1080), 108);
+        assert_eq!(args.icon_size.resolve(3840, 2160), 216);
+
+        for invalid in ["0%", "101%", "%", "abc%"] {
+            assert!(
+                Args::try_parse_from(["waystt-wrapper", "--icon-size", invalid]).is_err(),
+                "{invalid}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_icon_size_renders() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--icon-size", "12.5%"]).unwrap();
+        let rendered = Config::from(args).render(ConfigFormat::Toml).unwrap();
+        assert!(rendered.contains("icon_size = \"12.5%\""));
+    }
+
+    #[test]
+    fn test_exclusive_zone() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert_eq!(args.exclusive_zone, None);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--exclusive-zone", "auto"]).unwrap();
+        assert_eq!(args.exclusive_zone, Some(ExclusiveZone::Auto));
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--exclusive-zone", "32"]).unwrap();
+        assert_eq!(args.exclusive_zone, Some(ExclusiveZone::Pixels(32)));
+
+        assert!(Args::try_parse_from(["waystt-wrapper", "--exclusive-zone", "-5"]).is_err());
+        assert!(Args::try_parse_from(["waystt-wrapper", "--exclusive-zone", "big"]).is_err());
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let args = Args::try_parse_from(["waystt-wrapper"]).unwrap();
+        assert!(!args.dry_run);
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--dry-run", "--", "echo"]).unwrap();
+        assert!(args.dry_run);
+        assert_eq!(args.command, vec!["echo"]);
+    }
+
+    #[test]
+    fn test_completions_subcommand() {
+        let args = Args::try_parse_from(["waystt-wrapper", "completions", "zsh"]).unwrap();
+        assert_eq!(args.action, Some(Action::Completions { shell: Shell::Zsh }));
+        assert!(Args::try_parse_from(["waystt-wrapper", "completions", "cmd"]).is_err());
+    }
+
+    #[test]
+    fn test_completions_include_enum_values() {
+        let mut out = Vec::new();
+        write_completions(Shell::Fish, &mut out);
+        let completions = String::from_utf8(out).unwrap();
+        assert!(completions.contains("top-left"));
+        assert!(completions.contains("overlay"));
+    }
+
+    #[test]
+    fn test_status_subcommand() {
+        let args = Args::try_parse_from(["waystt-wrapper", "status"]).unwrap();
+        assert_eq!(args.action, Some(Action::Status));
+
+        let args = Args::try_parse_from(["waystt-wrapper", "--theme", "light", "attach"]).unwrap();
+        assert_eq!(args.action, Some(Action::Attach { pid: None }));
+        assert_eq!(args.theme, Theme::Light);
+        let args = Args::try_parse_from(["waystt-wrapper", "attach", "1234"]).unwrap();
+        assert_eq!(args.action, Some(Action::Attach { pid: Some(1234) }));
+    }
+
+    #[test]
+    fn test_fake_child_subcommand() {
+        let args = Args::try_parse_from(["waystt-wrapper", "fake-child"]).unwrap();
+        let Some(Action::FakeChild(fake)) = args.action else {
+            panic!("expected fake-child");
+        };
+        assert_eq!(fake.exit_code, 0);
+        assert_eq!(fake.exit_after_ms, None);
+        assert!(!fake.ignore_stop);
+
+        let args = Args::try_parse_from([
+            "waystt-wrapper",
+            "fake-child",
+            "--text",
+            "hello",
+            "--exit-code",
+            "4",
+            "--exit-after-ms",
+            "100",
+            "--ignore-stop",
+        ])
+        .unwrap();
+        let Some(Action::FakeChild(fake)) = args.action else {
+            panic!("expected fake-child");
+        };
+        assert_eq!(fake.text, "hello");
+        assert_eq!(fake.exit_code, 4);
+        assert_eq!(fake.exit_after_ms, Some(100));
+        assert!(fake.ignore_stop);
+    }
+
+    #[test]
+    fn test_parse_setting() {
+        assert_eq!(parse_setting("icon=face-smile"), Ok(Setting::Icon("face-smile".to_string())));
+        assert_eq!(
+            parse_setting("position=bottom-left"),
+            Ok(Setting::Position(Position::BottomLeft))
+        );
+        assert_eq!(parse_setting("theme=high-contrast"), Ok(Setting::Theme(Theme::HighContrast)));
+        // Markup may hold `=` itself, and an empty label hides the caption
+        assert_eq!(
+            parse_setting("label=<span weight=\"bold\">Work</span>"),
+            Ok(Setting::Label("<span weight=\"bold\">Work</span>".to_string()))
+        );
+        assert_eq!(parse_setting("label="), Ok(Setting::Label(String::new())));
+        assert!(parse_setting("theme").is_err());
+        assert!(parse_setting("theme=sepia").is_err());
+        assert!(parse_setting("icon=").is_err());
+        assert!(parse_setting("margin=4").is_err());
+    }
+
+    #[test]
+    fn test_setting_round_trips() {
+        let values = [
+            "icon=/tmp/mic.svg",
+            "label=<b>Work</b>",
+            "position=above-window",
+            "theme=light",
+        ];
+        for value in values {
+            assert_eq!(parse_setting(value).unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn test_set_action() {
+        let args =
+            Args::try_parse_from(["waystt-wrapper", "set", "--pid", "7", "theme=light"]).unwrap();
+        assert_eq!(
+            args.action,
+            Some(Action::Set {
+                pid: Some(7),
+                settings: vec![Setting::Theme(Theme::Light)],
+            })
+        );
+        assert!(Args::try_parse_from(["waystt-wrapper", "set"]).is_err());
+        assert!(Args::try_parse_from(["waystt-wrapper", "set", "theme=sepia"]).is_err());
+    }
+
+    #[test]
+    fn test_auto_theme_follows_color_scheme() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--theme", "auto"]).unwrap();
+        let mut config = Config::from(args);
+        assert_eq!(config.palette(), Theme::Dark.palette());
+
+        config.follow_color_scheme(ColorScheme::from_portal(2));
+        assert_eq!(config.palette(), Theme::Light.palette());
+        assert_eq!(config.background_color, Theme::Light.palette().background);
+        assert_eq!(config.opacity, Theme::Light.palette().opacity);
+
+        config.follow_color_scheme(ColorScheme::from_portal(0));
+        assert_eq!(config.palette(), Theme::Dark.palette());
+    }
+
+    #[test]
+    fn test_color_scheme_keeps_explicit_colors() {
+        let args = ["waystt-wrapper", "--theme", "auto", "--background-color", "navy"];
+        let mut config = Config::from(Args::try_parse_from(args).unwrap());
+        config.follow_color_scheme(ColorScheme::PreferLight);
+        assert_eq!(config.background_color, "navy");
+        assert_eq!(config.palette().text, Theme::Light.palette().text);
+    }
+
+    #[test]
+    fn test_other_themes_ignore_color_scheme() {
+        assert_eq!(Theme::HighContrast.resolve(ColorScheme::PreferLight), Theme::HighContrast);
+        assert_eq!(Theme::Dark.resolve(ColorScheme::PreferLight), Theme::Dark);
+    }
+
+    #[test]
+    fn test_apply_theme_replaces_colors() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--background-color", "black"]).unwrap();
+        let mut config = Config::from(args);
+        config.apply(&Setting::Theme(Theme::Light));
+        assert_eq!(config.theme, Theme::Light);
+        assert_eq!(config.background_color, Theme::Light.palette().background);
+        assert_eq!(config.opacity, Theme::Light.palette().opacity);
+
+        config.apply(&Setting::Icon("face-smile".to_string()));
+        assert_eq!(config.icon, "face-smile");
+
+        config.apply(&Setting::Label("<b>Work</b>".to_string()));
+        assert_eq!(config.label.as_deref(), Some("<b>Work</b>"));
+        config.apply(&Setting::Label(String::new()));
+        assert_eq!(config.label, None);
+    }
 }
@@ -0,0 +1,275 @@
+use std::fmt;
+
+use tracing::{info, warn};
+
+use crate::config::{Config, IconColor};
+
+/// Minimum contrast for the icon, as for graphical objects in WCAG 2.1 (1.4.11)
+pub const ICON_MIN_CONTRAST: f64 = 3.0;
+
+/// Minimum contrast for the labels, as for normal text in WCAG 2.1 (1.4.3)
+pub const TEXT_MIN_CONTRAST: f64 = 4.5;
+
+/// The basic CSS color keywords, which is what people type on a command line
+const NAMED_COLORS: &[(&str, [u8; 3])] = &[
+    ("black", [0, 0, 0]),
+    ("white", [255, 255, 255]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("silver", [192, 192, 192]),
+    ("red", [255, 0, 0]),
+    ("maroon", [128, 0, 0]),
+    ("orange", [255, 165, 0]),
+    ("yellow", [255, 255, 0]),
+    ("olive", [128, 128, 0]),
+    ("lime", [0, 255, 0]),
+    ("green", [0, 128, 0]),
+    ("aqua", [0, 255, 255]),
+    ("cyan", [0, 255, 255]),
+    ("teal", [0, 128, 128]),
+    ("blue", [0, 0, 255]),
+    ("navy", [0, 0, 128]),
+    ("fuchsia", [255, 0, 255]),
+    ("magenta", [255, 0, 255]),
+    ("purple", [128, 0, 128]),
+];
+
+/// An sRGB color with channels from 0 to 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rgb([f64; 3]);
+
+impl Rgb {
+    const BLACK: Rgb = Rgb([0.0; 3]);
+    const WHITE: Rgb = Rgb([1.0; 3]);
+
+    fn from_bytes(bytes: [u8; 3]) -> Self {
+        Rgb(bytes.map(|byte| f64::from(byte) / 255.0))
+    }
+
+    /// Parse a hex, `rgb()`/`rgba()` or basic named CSS color, with its alpha. Anything GTK
+    /// resolves itself (e.g. `@theme_bg_color`) gives `None`.
+    pub fn parse(color: &str) -> Option<(Rgb, f64)> {
+        let color = color.trim().to_ascii_lowercase();
+        if let Some(hex) = color.strip_prefix('#') {
+            return parse_hex(hex);
+        }
+        if let Some(args) = color
+            .strip_prefix("rgba(")
+            .or_else(|| color.strip_prefix("rgb("))
+            .and_then(|rest| rest.strip_suffix(')'))
+        {
+            let parts: Vec<&str> = args.split(',').map(str::trim).collect();
+            let channel = |part: &str| match part.strip_suffix('%') {
+                Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0),
+                None => part.parse::<f64>().ok().map(|value| value / 255.0),
+            };
+            let rgb = [
+                channel(parts.first()?)?,
+                channel(parts.get(1)?)?,
+                channel(parts.get(2)?)?,
+            ];
+            let alpha = match parts.get(3) {
+                Some(alpha) => alpha.parse().ok()?,
+                None => 1.0,
+            };
+            return Some((Rgb(rgb.map(|c| c.clamp(0.0, 1.0))), alpha));
+        }
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| *name == color)
+            .map(|(_, bytes)| (Rgb::from_bytes(*bytes), 1.0))
+    }
+
+    /// Relative luminance, as defined by WCAG 2.1
+    fn luminance(self) -> f64 {
+        let [r, g, b] = self.0.map(|c| {
+            if c <= 0.03928 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        });
+        0.2126 * r + 0.7152 * g + 0.0722 * b
+    }
+
+    /// This color drawn at `alpha` over `behind`
+    fn over(self, alpha: f64, behind: Rgb) -> Rgb {
+        let alpha = alpha.clamp(0.0, 1.0);
+        Rgb([0, 1, 2].map(|i| self.0[i] * alpha + behind.0[i] * (1.0 - alpha)))
+    }
+}
+
+fn parse_hex(hex: &str) -> Option<(Rgb, f64)> {
+    let digits: Vec<u8> = match hex.len() {
+        3 | 4 => hex
+            .chars()
+            .map(|c| u8::from_str_radix(&c.to_string(), 16).map(|d| d * 17))
+            .collect::<Result<_, _>>()
+            .ok()?,
+        6 | 8 => (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect::<Option<_>>()?,
+        _ => return None,
+    };
+    let alpha = digits.get(3).map_or(1.0, |alpha| f64::from(*alpha) / 255.0);
+    Some((Rgb::from_bytes([digits[0], digits[1], digits[2]]), alpha))
+}
+
+/// Contrast ratio between two colors, from 1 (none) to 21 (black on white)
+pub fn ratio(a: Rgb, b: Rgb) -> f64 {
+    let (a, b) = (a.luminance(), b.luminance());
+    (a.max(b) + 0.05) / (a.min(b) + 0.05)
+}
+
+/// The lowest contrast of `foreground` on `background` drawn at `opacity`, whatever is behind the
+/// overlay, or `None` if either color can't be worked out
+pub fn worst_ratio(foreground: &str, background: &str, opacity: f64) -> Option<f64> {
+    let (foreground, foreground_alpha) = Rgb::parse(foreground)?;
+    let (background, background_alpha) = Rgb::parse(background)?;
+    let alpha = opacity * background_alpha;
+    [Rgb::BLACK, Rgb::WHITE]
+        .into_iter()
+        .map(|behind| {
+            let background = background.over(alpha, behind);
+            ratio(foreground.over(foreground_alpha, background), background)
+        })
+        .reduce(f64::min)
+}
+
+/// `foreground`, or black or white (whichever stands out more) if it falls short of `min`
+/// against `background`
+pub fn ensure<'a>(foreground: &'a str, background: &str, opacity: f64, min: f64) -> &'a str {
+    match worst_ratio(foreground, background, opacity) {
+        Some(ratio) if ratio < min => {
+            let black = worst_ratio("black", background, opacity).unwrap_or_default();
+            let white = worst_ratio("white", background, opacity).unwrap_or_default();
+            if black > white {
+                "black"
+            } else {
+                "white"
+            }
+        }
+        _ => foreground,
+    }
+}
+
+/// A color that doesn't stand out enough from the background
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub what: &'static str,
+    pub color: String,
+    pub ratio: f64,
+    pub min: f64,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "the {} color {} has a contrast of {:.1}:1 against the background, below {}:1",
+            self.what, self.color, self.ratio, self.min
+        )
+    }
+}
+
+/// Icon and label colors that fall short of the minimum contrast against a custom background or
+/// with a custom icon color. The built-in themes are left alone.
+pub fn problems(config: &Config) -> Vec<Problem> {
+    let palette = config.palette();
+    let custom_icon = match &config.icon_color {
+        Some(IconColor::Css(color)) => Some(color.as_str()),
+        _ => None,
+    };
+    if custom_icon.is_none() && config.background_color == palette.background {
+        return vec![];
+    }
+    [
+        ("icon", custom_icon.unwrap_or(palette.icon), ICON_MIN_CONTRAST),
+        ("text", palette.text, TEXT_MIN_CONTRAST),
+    ]
+    .into_iter()
+    .filter_map(|(what, color, min)| {
+        let ratio = worst_ratio(color, &config.background_color, config.opacity)?;
+        (ratio < min).then(|| Problem {
+            what,
+            color: color.to_string(),
+            ratio,
+            min,
+        })
+    })
+    .collect()
+}
+
+/// Log the colors that fall short, and whether `--enforce-contrast` replaces them
+pub fn report(config: &Config) {
+    for problem in problems(config) {
+        if config.enforce_contrast {
+            info!("{problem}; using black or white instead");
+        } else {
+            warn!("{problem}; pass --enforce-contrast to use black or white instead");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::config::Args;
+
+    fn config(args: &[&str]) -> Config {
+        Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+    }
+
+    #[test]
+    fn test_parse() {
+        let white = Rgb::WHITE;
+        assert_eq!(Rgb::parse("#fff"), Some((white, 1.0)));
+        assert_eq!(Rgb::parse("#FFFFFF"), Some((white, 1.0)));
+        assert_eq!(Rgb::parse("#ffffff00"), Some((white, 0.0)));
+        assert_eq!(Rgb::parse("rgb(255, 255, 255)"), Some((white, 1.0)));
+        assert_eq!(Rgb::parse("rgba(100%, 100%, 100%, 0.5)"), Some((white, 0.5)));
+        assert_eq!(Rgb::parse(" White "), Some((white, 1.0)));
+        assert_eq!(Rgb::parse("@theme_bg_color"), None);
+        assert_eq!(Rgb::parse("#ggg"), None);
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert!((ratio(Rgb::BLACK, Rgb::WHITE) - 21.0).abs() < 0.01);
+        assert!((ratio(Rgb::WHITE, Rgb::WHITE) - 1.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_translucent_background_takes_the_worst_case() {
+        let opaque = worst_ratio("white", "black", 1.0).unwrap();
+        let translucent = worst_ratio("white", "black", 0.3).unwrap();
+        assert!(opaque > 20.0);
+        assert!(translucent < ICON_MIN_CONTRAST);
+    }
+
+    #[test]
+    fn test_ensure() {
+        assert_eq!(ensure("#ff5555", "black", 1.0, ICON_MIN_CONTRAST), "#ff5555");
+        assert_eq!(ensure("#777", "#666", 1.0, ICON_MIN_CONTRAST), "white");
+        assert_eq!(ensure("#999", "#aaa", 1.0, ICON_MIN_CONTRAST), "black");
+        assert_eq!(ensure("@theme_fg_color", "black", 1.0, TEXT_MIN_CONTRAST), "@theme_fg_color");
+    }
+
+    #[test]
+    fn test_problems_only_for_custom_colors() {
+        assert!(problems(&config(&["--theme", "minimal"])).is_empty());
+
+        let found = problems(&config(&["--background-color", "#ddd", "--opacity", "1"]));
+        let what: Vec<&str> = found.iter().map(|problem| problem.what).collect();
+        assert_eq!(what, ["icon", "text"]);
+        assert!(found[1].to_string().starts_with("the text color #eeeeee has a contrast of 1.2:1"));
+
+        let found = problems(&config(&["--icon-color", "#444", "--opacity", "1"]));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].what, "icon");
+    }
+}
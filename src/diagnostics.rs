@@ -0,0 +1,119 @@
+use std::fmt;
+
+use gtk4::gdk::{self, Display};
+use gtk4::prelude::*;
+
+use crate::quirks::Compositor;
+
+/// First `zwlr_layer_shell_v1` version supporting on-demand keyboard interactivity
+const ON_DEMAND_KEYBOARD_PROTOCOL_VERSION: u32 = 4;
+
+/// Summary of a single output advertised by the compositor
+#[derive(Debug)]
+pub struct OutputInfo {
+    pub connector: Option<String>,
+    pub model: Option<String>,
+    pub geometry: (i32, i32, i32, i32),
+    pub scale_factor: i32,
+    pub refresh_rate: i32,
+}
+
+/// What the compositor advertises that matters to the overlay, used for bug triage
+#[derive(Debug)]
+pub struct CompositorInfo {
+    pub desktop: Option<String>,
+    pub compositor: Compositor,
+    pub display: Option<String>,
+    pub layer_shell_library: String,
+    pub layer_shell_protocol: u32,
+    pub outputs: Vec<OutputInfo>,
+}
+
+impl CompositorInfo {
+    /// Query GTK and gtk4-layer-shell. GTK must already be initialized.
+    pub fn collect() -> Self {
+        let display = Display::default();
+        let layer_shell_protocol = if gtk4_layer_shell::is_supported() {
+            gtk4_layer_shell::protocol_version()
+        } else {
+            0
+        };
+
+        let outputs = display
+            .as_ref()
+            .map(|display| {
+                let monitors = display.monitors();
+                (0..monitors.n_items())
+                    .filter_map(|i| monitors.item(i).and_downcast::<gdk::Monitor>())
+                    .map(|monitor| {
+                        let geometry = monitor.geometry();
+                        OutputInfo {
+                            connector: monitor.connector().map(String::from),
+                            model: monitor.model().map(String::from),
+                            geometry: (
+                                geometry.x(),
+                                geometry.y(),
+                                geometry.width(),
+                                geometry.height(),
+                            ),
+                            scale_factor: monitor.scale_factor(),
+                            refresh_rate: monitor.refresh_rate(),
+                        }
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Self {
+            desktop: std::env::var("XDG_CURRENT_DESKTOP").ok(),
+            compositor: Compositor::detect(),
+            display: display.map(|d| d.name().to_string()),
+            layer_shell_library: format!(
+                "{}.{}.{}",
+                gtk4_layer_shell::major_version(),
+                gtk4_layer_shell::minor_version(),
+                gtk4_layer_shell::micro_version()
+            ),
+            layer_shell_protocol,
+            outputs,
+        }
+    }
+
+    pub fn layer_shell_supported(&self) -> bool {
+        self.layer_shell_protocol > 0
+    }
+
+    pub fn on_demand_keyboard_supported(&self) -> bool {
+        self.layer_shell_protocol >= ON_DEMAND_KEYBOARD_PROTOCOL_VERSION
+    }
+}
+
+impl fmt::Display for CompositorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unknown = "unknown";
+        writeln!(f, "desktop:               {}", self.desktop.as_deref().unwrap_or(unknown))?;
+        writeln!(f, "quirk profile:         {:?}", self.compositor)?;
+        writeln!(f, "display:               {}", self.display.as_deref().unwrap_or(unknown))?;
+        writeln!(f, "gtk4-layer-shell:      {}", self.layer_shell_library)?;
+        if self.layer_shell_supported() {
+            writeln!(f, "zwlr_layer_shell_v1:   version {}", self.layer_shell_protocol)?;
+        } else {
+            writeln!(f, "zwlr_layer_shell_v1:   not supported")?;
+        }
+        writeln!(f, "keyboard exclusive:    {}", self.layer_shell_supported())?;
+        writeln!(f, "keyboard on-demand:    {}", self.on_demand_keyboard_supported())?;
+        writeln!(f, "outputs:")?;
+        for output in &self.outputs {
+            let (x, y, width, height) = output.geometry;
+            writeln!(
+                f,
+                "  {} ({}): {width}x{height}+{x}+{y} scale {} @ {:.2} Hz",
+                output.connector.as_deref().unwrap_or(unknown),
+                output.model.as_deref().unwrap_or(unknown),
+                output.scale_factor,
+                output.refresh_rate as f64 / 1000.0,
+            )?;
+        }
+        Ok(())
+    }
+}
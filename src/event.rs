@@ -0,0 +1,1062 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::Signal;
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ColorScheme, OnClose, Setting};
+use crate::locale::{NumberFormat, TimeFormat};
+use crate::process::ESCALATION_SIGNALS;
+
+/// Exit code used when the overlay is closed out from under a recording session
+const CLOSED_EXIT_CODE: i32 = 130; // Similar to Ctrl+C
+
+/// Exit code used when the child could not be stopped at all
+const HUNG_EXIT_CODE: i32 = 1;
+
+/// Phase of the recording session, reflected in the overlay and the instance registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SessionState {
+    Recording,
+    Paused,
+    Processing,
+    Error,
+    /// The command finished without transcribing anything (`--skip-empty`)
+    Empty,
+    /// The transcription waits for Enter before replacing the clipboard (`--confirm-overwrite`)
+    ConfirmOverwrite,
+    /// The panic combo was pressed once and waits for confirmation
+    PanicWarning,
+}
+
+impl SessionState {
+    /// Every state, e.g. to clear the CSS class of the previous one
+    pub const ALL: [SessionState; 7] = [
+        SessionState::Recording,
+        SessionState::Paused,
+        SessionState::Processing,
+        SessionState::Error,
+        SessionState::Empty,
+        SessionState::ConfirmOverwrite,
+        SessionState::PanicWarning,
+    ];
+}
+
+impl fmt::Display for SessionState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            SessionState::Recording => "recording",
+            SessionState::Paused => "paused",
+            SessionState::Processing => "processing",
+            SessionState::Error => "error",
+            SessionState::Empty => "empty",
+            SessionState::ConfirmOverwrite => "confirm-overwrite",
+            SessionState::PanicWarning => "panic-warning",
+        })
+    }
+}
+
+/// Inputs to the application, posted to the event bus by keys, signals, timers and the child
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AppEvent {
+    /// Escape, SIGUSR1, ...: stop recording, or escalate if already stopping
+    StopRequested,
+    /// The panic combo: close every running instance
+    PanicRequested,
+    /// The child exited after being asked to stop
+    ChildExited(i32),
+    /// The child exited successfully but transcribed nothing; exit with the given code
+    NothingTranscribed(i32),
+    /// The child exited successfully, but its transcription would replace what the clipboard
+    /// holds
+    OverwritePending,
+    /// Enter: replace the clipboard with the pending transcription
+    OverwriteConfirmed,
+    /// A left click on the overlay or a mirror: stop like Escape, or replace the clipboard while
+    /// asking to (the only way to without keyboard input)
+    Clicked,
+    /// The child exited while still recording
+    ChildExitedUnexpectedly(i32),
+    /// The window was asked to close (e.g. by the compositor)
+    WindowClosed,
+    /// The child did not exit within the kill timeout (or escalation grace period)
+    KillTimeoutElapsed,
+    /// The panic combo was not confirmed in time
+    PanicConfirmExpired,
+    /// `--max-duration` passed: stop recording, but never escalate
+    MaxDurationElapsed,
+    /// The pause key: pause recording, or resume it if paused
+    PauseRequested,
+    /// The detach key or SIGUSR2: hide the overlay and let the transcription finish without it
+    DetachRequested,
+    /// A setting changed through the control socket
+    SettingChanged(Setting),
+    /// The desktop switched between dark and light (`--theme auto`)
+    ColorSchemeChanged(ColorScheme),
+    /// Context menu: kill the child without transcribing and close the overlay
+    CancelRequested,
+    /// Context menu: throw the recording away and start a new one
+    RestartRequested,
+}
+
+/// Side effects requested by the reducer, carried out by the [`AppModel`](crate::app::AppModel)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Effect {
+    /// Ask the child to stop gracefully and wait for it in the background
+    StopChild,
+    /// Send an escalation signal to a child that is already stopping
+    SignalChild(Signal),
+    /// Send the pause signal to the child, pausing or resuming it
+    TogglePause,
+    /// Kill the child outright, discarding the recording
+    CancelChild,
+    /// Kill the child outright and spawn a new one in its place
+    RestartChild,
+    /// Close the overlay, leaving the child to be waited for without it
+    Detach,
+    /// Copy the transcription held back by `--confirm-overwrite` to the clipboard
+    CopyPending,
+    /// Throw the transcription held back by `--confirm-overwrite` away
+    DiscardPending,
+    /// Show the overlay with a changed setting
+    ApplySetting(Setting),
+    /// Redraw the overlay for the desktop's dark/light preference
+    FollowColorScheme(ColorScheme),
+    /// Close every running instance of the wrapper
+    KillAll,
+    /// Post [`AppEvent::KillTimeoutElapsed`] once the configured kill timeout passes
+    ArmKillTimer,
+    /// Post [`AppEvent::KillTimeoutElapsed`] once the escalation grace period passes
+    ArmEscalationTimer,
+    /// Post [`AppEvent::PanicConfirmExpired`] once the confirmation window passes
+    ArmPanicConfirmTimer,
+    SetState(SessionState),
+    CloseWindow,
+}
+
+/// Format a recording time as `m:ss`, or `h:mm:ss` from an hour on, separated like `time`'s clock
+pub fn format_elapsed(elapsed: Duration, time: TimeFormat) -> String {
+    let seconds = elapsed.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let separator = time.separator;
+    if hours > 0 {
+        format!("{hours}{separator}{minutes:02}{separator}{seconds:02}")
+    } else {
+        format!("{minutes}{separator}{seconds:02}")
+    }
+}
+
+/// Coalesces requests of the same kind arriving within a short window of the last one that went
+/// through (hotkey bounce, a keybind firing twice)
+#[derive(Debug)]
+pub struct Debounce<K> {
+    window: Duration,
+    last: Vec<(K, Instant)>,
+}
+
+impl<K: PartialEq> Debounce<K> {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            last: Vec::new(),
+        }
+    }
+
+    /// Whether a request of kind `key` made at `now` should go through
+    pub fn accept(&mut self, key: K, now: Instant) -> bool {
+        match self.last.iter_mut().find(|(last_key, _)| *last_key == key) {
+            Some((_, last)) if now.duration_since(*last) < self.window => false,
+            Some((_, last)) => {
+                *last = now;
+                true
+            }
+            None => {
+                self.last.push((key, now));
+                true
+            }
+        }
+    }
+}
+
+/// Tells when the overlay has gone without sound or key presses for long enough to fade out of
+/// the way (`--auto-hide`), and when it should come back
+#[derive(Debug)]
+pub struct Inactivity {
+    timeout: Duration,
+    last_active: Instant,
+    idle: bool,
+}
+
+impl Inactivity {
+    pub fn new(timeout: Duration, now: Instant) -> Self {
+        Self {
+            timeout,
+            last_active: now,
+            idle: false,
+        }
+    }
+
+    /// Record activity at `now`, returning whether it ends an idle spell
+    pub fn active(&mut self, now: Instant) -> bool {
+        self.last_active = now;
+        std::mem::replace(&mut self.idle, false)
+    }
+
+    /// Whether the overlay goes idle at `now`, after the whole timeout without activity
+    pub fn check(&mut self, now: Instant) -> bool {
+        if self.idle || now.duration_since(self.last_active) < self.timeout {
+            return false;
+        }
+        self.idle = true;
+        true
+    }
+}
+
+/// Text of the timer label: the time `recorded` (`--show-timer`) and/or the time `remaining`
+/// until `--max-duration` stops the recording, rounded up so that it never shows 0:00 early
+pub fn timer_text(
+    recorded: Option<Duration>,
+    remaining: Option<Duration>,
+    time: TimeFormat,
+) -> String {
+    let remaining = remaining.map(|remaining| {
+        let seconds = remaining.as_secs() + u64::from(remaining.subsec_nanos() > 0);
+        format!("{} left", format_elapsed(Duration::from_secs(seconds), time))
+    });
+    recorded
+        .map(|recorded| format_elapsed(recorded, time))
+        .into_iter()
+        .chain(remaining)
+        .collect::<Vec<_>>()
+        .join(" · ")
+}
+
+/// What asked the child to stop
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    User,
+    MaxDuration,
+    /// The overlay was closed with `--on-close graceful`
+    WindowClosed,
+}
+
+/// How the session ended, for the exit summary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ending {
+    Stopped(StopReason),
+    /// The child exited while still recording
+    ExitedOnItsOwn,
+    /// The child could not be stopped, even with SIGKILL
+    Hung,
+    /// The overlay was closed out from under the session
+    Closed,
+    /// The session never started
+    Aborted,
+    /// Cancelled from the context menu, discarding the recording
+    Cancelled,
+    /// The overlay was hidden and the transcription finished in the background
+    Detached,
+}
+
+impl fmt::Display for Ending {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Ending::Stopped(StopReason::User) => "stopped by user",
+            Ending::Stopped(StopReason::MaxDuration) => "stopped at --max-duration",
+            Ending::Stopped(StopReason::WindowClosed) => "stopped when the overlay was closed",
+            Ending::ExitedOnItsOwn => "command exited on its own",
+            Ending::Hung => "command did not exit and was abandoned",
+            Ending::Closed => "overlay closed",
+            Ending::Cancelled => "cancelled",
+            Ending::Aborted => "failed to start",
+            Ending::Detached => "finished in the background",
+        })
+    }
+}
+
+/// The one-line exit summary, e.g. `stopped by user after 12.4s; transcription 214 chars →
+/// clipboard; exit 0`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Summary<'a> {
+    pub ending: Ending,
+    pub elapsed: Option<Duration>,
+    /// Length of the transcription, if the command's output was captured
+    pub transcription_chars: Option<usize>,
+    /// Where the transcription went, if known from the preset
+    pub destination: Option<&'a str>,
+    pub exit_code: i32,
+    /// How to write the time and length
+    pub numbers: NumberFormat,
+}
+
+impl fmt::Display for Summary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.ending)?;
+        if let Some(elapsed) = self.elapsed {
+            write!(f, " after {}s", self.numbers.decimal(elapsed.as_secs_f64(), 1))?;
+        }
+        let transcribed = self.exit_code == 0
+            && matches!(self.ending, Ending::Stopped(_) | Ending::Detached);
+        if transcribed && (self.transcription_chars.is_some() || self.destination.is_some()) {
+            f.write_str("; transcription")?;
+            if let Some(chars) = self.transcription_chars {
+                write!(f, " {} chars", self.numbers.integer(chars))?;
+            }
+            if let Some(destination) = self.destination {
+                write!(f, " → {destination}")?;
+            }
+        }
+        write!(f, "; exit {}", self.exit_code)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Phase {
+    Recording,
+    Stopping { escalation: usize },
+    /// Waiting for the clipboard to be allowed to be replaced
+    Confirming,
+    Finished,
+}
+
+/// The session state machine. All state transitions happen in [`Session::reduce`].
+#[derive(Debug)]
+pub struct Session {
+    phase: Phase,
+    exit_code: i32,
+    stop_reason: Option<StopReason>,
+    ending: Ending,
+    /// Whether recording is paused
+    paused: bool,
+    /// Whether the panic combo has to be pressed twice
+    confirm_panic: bool,
+    /// Whether the panic combo was pressed once and waits for confirmation
+    panic_armed: bool,
+    on_close: OnClose,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new(false, OnClose::default())
+    }
+}
+
+impl Session {
+    pub fn new(confirm_panic: bool, on_close: OnClose) -> Self {
+        Self {
+            phase: Phase::Recording,
+            exit_code: 0,
+            stop_reason: None,
+            ending: Ending::Aborted,
+            paused: false,
+            confirm_panic,
+            panic_armed: false,
+            on_close,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.exit_code
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.phase == Phase::Finished
+    }
+
+    pub fn ending(&self) -> Ending {
+        self.ending
+    }
+
+    /// Finish without a running session (e.g. the child failed to spawn)
+    pub fn abort(&mut self, code: i32) {
+        self.phase = Phase::Finished;
+        self.exit_code = code;
+    }
+
+    /// Finish with the child's exit code, flagging failures in the overlay
+    fn finish(&mut self, code: i32) -> Vec<Effect> {
+        self.abort(code);
+        if code == 0 {
+            vec![Effect::CloseWindow]
+        } else {
+            vec![Effect::SetState(SessionState::Error), Effect::CloseWindow]
+        }
+    }
+
+    /// How the session ends now that the child has exited
+    fn exit_ending(&self) -> Ending {
+        match (self.phase, self.stop_reason) {
+            (Phase::Stopping { .. }, Some(reason)) => Ending::Stopped(reason),
+            _ => Ending::ExitedOnItsOwn,
+        }
+    }
+
+    /// Ask the child to stop, and wait for it to exit
+    fn stop(&mut self, reason: StopReason) -> Vec<Effect> {
+        self.phase = Phase::Stopping { escalation: 0 };
+        self.stop_reason = Some(reason);
+        vec![
+            Effect::StopChild,
+            Effect::SetState(SessionState::Processing),
+            Effect::ArmKillTimer,
+        ]
+    }
+
+    fn cancel(&mut self) {
+        self.abort(CLOSED_EXIT_CODE);
+        self.ending = Ending::Cancelled;
+    }
+
+    /// Hand the stopping child over to be waited for once the overlay is gone. The exit code is
+    /// only known then.
+    fn detach(&mut self) -> Vec<Effect> {
+        self.phase = Phase::Finished;
+        self.ending = Ending::Detached;
+        vec![Effect::Detach]
+    }
+
+    /// Stop recording and detach right away
+    fn stop_detached(&mut self, reason: StopReason) -> Vec<Effect> {
+        // Waited for without the event loop, so the kill timer can't fire
+        let mut effects = self.stop(reason);
+        effects.retain(|effect| *effect != Effect::ArmKillTimer);
+        effects.extend(self.detach());
+        effects
+    }
+
+    fn recording_state(&self) -> SessionState {
+        if self.paused {
+            SessionState::Paused
+        } else {
+            SessionState::Recording
+        }
+    }
+
+    /// Kill everything, or arm the panic combo and show a warning if it needs confirmation
+    fn panic(&mut self) -> Vec<Effect> {
+        if !self.confirm_panic || self.panic_armed {
+            return vec![Effect::KillAll];
+        }
+        self.panic_armed = true;
+        vec![
+            Effect::SetState(SessionState::PanicWarning),
+            Effect::ArmPanicConfirmTimer,
+        ]
+    }
+
+    /// Disarm an unconfirmed panic combo, restoring the overlay
+    fn disarm_panic(&mut self) -> Vec<Effect> {
+        if !self.panic_armed {
+            return vec![];
+        }
+        self.panic_armed = false;
+        match self.phase {
+            Phase::Recording => vec![Effect::SetState(self.recording_state())],
+            Phase::Stopping { .. } => vec![Effect::SetState(SessionState::Processing)],
+            Phase::Confirming => vec![Effect::SetState(SessionState::ConfirmOverwrite)],
+            Phase::Finished => vec![],
+        }
+    }
+
+    /// Apply `event`, returning the effects to carry out
+    pub fn reduce(&mut self, event: AppEvent) -> Vec<Effect> {
+        match (self.phase, event) {
+            (_, AppEvent::PanicRequested) => self.panic(),
+            (_, AppEvent::PanicConfirmExpired) => self.disarm_panic(),
+            (_, AppEvent::SettingChanged(setting)) => vec![Effect::ApplySetting(setting)],
+            (_, AppEvent::ColorSchemeChanged(scheme)) => vec![Effect::FollowColorScheme(scheme)],
+            (Phase::Confirming, AppEvent::Clicked) => self.reduce(AppEvent::OverwriteConfirmed),
+            (_, AppEvent::Clicked) => self.reduce(AppEvent::StopRequested),
+
+            (Phase::Recording, AppEvent::StopRequested) => self.stop(StopReason::User),
+            (Phase::Recording, AppEvent::MaxDurationElapsed) => self.stop(StopReason::MaxDuration),
+            (Phase::Stopping { escalation }, AppEvent::StopRequested) => {
+                match ESCALATION_SIGNALS.get(escalation) {
+                    Some(&signal) => {
+                        self.phase = Phase::Stopping {
+                            escalation: escalation + 1,
+                        };
+                        vec![Effect::SignalChild(signal)]
+                    }
+                    None => vec![],
+                }
+            }
+            (Phase::Stopping { escalation }, AppEvent::KillTimeoutElapsed) => {
+                match ESCALATION_SIGNALS.get(escalation) {
+                    Some(&signal) => {
+                        self.phase = Phase::Stopping {
+                            escalation: escalation + 1,
+                        };
+                        vec![Effect::SignalChild(signal), Effect::ArmEscalationTimer]
+                    }
+                    // Not even SIGKILL worked (e.g. stuck in uninterruptible sleep): give up on it
+                    None => {
+                        self.ending = Ending::Hung;
+                        self.finish(HUNG_EXIT_CODE)
+                    }
+                }
+            }
+            (Phase::Recording, AppEvent::KillTimeoutElapsed) => vec![],
+
+            (Phase::Recording, AppEvent::PauseRequested) => {
+                self.paused = !self.paused;
+                vec![Effect::TogglePause, Effect::SetState(self.recording_state())]
+            }
+            (Phase::Stopping { .. }, AppEvent::PauseRequested) => vec![],
+
+            (Phase::Recording, AppEvent::CancelRequested) => {
+                self.cancel();
+                vec![Effect::CancelChild, Effect::CloseWindow]
+            }
+            // Already handed to the wait for its exit, so only reachable by pid
+            (Phase::Stopping { .. }, AppEvent::CancelRequested) => {
+                self.cancel();
+                vec![Effect::SignalChild(Signal::SIGKILL), Effect::CloseWindow]
+            }
+            (Phase::Recording, AppEvent::RestartRequested) => {
+                // The new child starts out recording
+                self.paused = false;
+                vec![Effect::RestartChild, Effect::SetState(SessionState::Recording)]
+            }
+            (Phase::Stopping { .. }, AppEvent::RestartRequested) => vec![],
+
+            (Phase::Stopping { .. }, AppEvent::MaxDurationElapsed) => vec![],
+
+            (Phase::Recording, AppEvent::DetachRequested) => self.stop_detached(StopReason::User),
+            (Phase::Stopping { .. }, AppEvent::DetachRequested) => self.detach(),
+
+            (
+                Phase::Finished,
+                AppEvent::ChildExited(_)
+                | AppEvent::ChildExitedUnexpectedly(_)
+                | AppEvent::NothingTranscribed(_)
+                | AppEvent::OverwritePending,
+            ) => vec![],
+            (_, AppEvent::ChildExited(code) | AppEvent::ChildExitedUnexpectedly(code)) => {
+                self.ending = self.exit_ending();
+                self.finish(code)
+            }
+            (_, AppEvent::NothingTranscribed(code)) => {
+                self.ending = self.exit_ending();
+                self.abort(code);
+                vec![Effect::SetState(SessionState::Empty), Effect::CloseWindow]
+            }
+            (_, AppEvent::OverwritePending) => {
+                self.ending = self.exit_ending();
+                self.phase = Phase::Confirming;
+                vec![Effect::SetState(SessionState::ConfirmOverwrite)]
+            }
+            (Phase::Confirming, AppEvent::OverwriteConfirmed) => {
+                self.abort(0);
+                vec![Effect::CopyPending, Effect::CloseWindow]
+            }
+            // Keep what the clipboard holds, throwing the transcription away
+            (
+                Phase::Confirming,
+                AppEvent::StopRequested | AppEvent::CancelRequested | AppEvent::WindowClosed,
+            ) => {
+                self.cancel();
+                vec![Effect::DiscardPending, Effect::CloseWindow]
+            }
+            (Phase::Confirming, _) | (_, AppEvent::OverwriteConfirmed) => vec![],
+
+            (Phase::Recording, AppEvent::WindowClosed) => match self.on_close {
+                OnClose::Graceful => self.stop(StopReason::WindowClosed),
+                OnClose::Cancel => {
+                    self.abort(CLOSED_EXIT_CODE);
+                    self.ending = Ending::Closed;
+                    vec![Effect::CancelChild, Effect::CloseWindow]
+                }
+                // As with the detach key: the wrapper waits for the transcription without the
+                // overlay, rather than leave a command that dies with it (--spawn-first) behind
+                OnClose::Detach => self.stop_detached(StopReason::WindowClosed),
+            },
+            // Keep waiting for the transcription
+            (Phase::Stopping { .. }, AppEvent::WindowClosed)
+                if self.on_close == OnClose::Graceful =>
+            {
+                vec![]
+            }
+            (Phase::Stopping { .. }, AppEvent::WindowClosed)
+                if self.on_close == OnClose::Detach =>
+            {
+                self.detach()
+            }
+            (Phase::Stopping { .. }, AppEvent::WindowClosed) => {
+                self.abort(self.exit_code);
+                self.ending = Ending::Closed;
+                vec![Effect::CloseWindow]
+            }
+
+            (Phase::Finished, _) => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state_names_match_the_registry() {
+        // Also the CSS classes of the icon, so themes and `list` agree on them
+        for state in SessionState::ALL {
+            assert_eq!(serde_json::to_string(&state).unwrap(), format!("\"{state}\""));
+        }
+    }
+
+    #[test]
+    fn test_stop_then_exit() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::StopRequested),
+            vec![
+                Effect::StopChild,
+                Effect::SetState(SessionState::Processing),
+                Effect::ArmKillTimer,
+            ]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::ChildExited(0)),
+            vec![Effect::CloseWindow]
+        );
+        assert!(session.is_finished());
+        assert_eq!(session.exit_code(), 0);
+        assert_eq!(session.ending(), Ending::Stopped(StopReason::User));
+    }
+
+    #[test]
+    fn test_nothing_transcribed() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(
+            session.reduce(AppEvent::NothingTranscribed(3)),
+            vec![Effect::SetState(SessionState::Empty), Effect::CloseWindow]
+        );
+        assert!(session.is_finished());
+        assert_eq!(session.exit_code(), 3);
+        assert_eq!(session.ending(), Ending::Stopped(StopReason::User));
+        assert_eq!(session.reduce(AppEvent::ChildExited(0)), vec![]);
+    }
+
+    #[test]
+    fn test_confirm_overwrite() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(
+            session.reduce(AppEvent::OverwritePending),
+            vec![Effect::SetState(SessionState::ConfirmOverwrite)]
+        );
+        assert!(!session.is_finished());
+        assert_eq!(session.reduce(AppEvent::KillTimeoutElapsed), vec![]);
+        assert_eq!(session.reduce(AppEvent::PauseRequested), vec![]);
+        assert_eq!(
+            session.reduce(AppEvent::OverwriteConfirmed),
+            vec![Effect::CopyPending, Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), 0);
+        assert_eq!(session.ending(), Ending::Stopped(StopReason::User));
+
+        let mut session = Session::default();
+        assert_eq!(session.reduce(AppEvent::OverwriteConfirmed), vec![]);
+        session.reduce(AppEvent::StopRequested);
+        session.reduce(AppEvent::OverwritePending);
+        assert_eq!(
+            session.reduce(AppEvent::StopRequested),
+            vec![Effect::DiscardPending, Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), CLOSED_EXIT_CODE);
+        assert_eq!(session.ending(), Ending::Cancelled);
+    }
+
+    #[test]
+    fn test_click_stops_or_confirms_the_overwrite() {
+        let mut session = Session::default();
+        let stopped = Session::default().reduce(AppEvent::StopRequested);
+        assert_eq!(session.reduce(AppEvent::Clicked), stopped);
+        session.reduce(AppEvent::OverwritePending);
+        assert_eq!(
+            session.reduce(AppEvent::Clicked),
+            vec![Effect::CopyPending, Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), 0);
+    }
+
+    #[test]
+    fn test_endings() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::MaxDurationElapsed);
+        session.reduce(AppEvent::ChildExited(0));
+        assert_eq!(session.ending(), Ending::Stopped(StopReason::MaxDuration));
+
+        let mut session = Session::default();
+        session.reduce(AppEvent::ChildExitedUnexpectedly(2));
+        assert_eq!(session.ending(), Ending::ExitedOnItsOwn);
+
+        let mut session = Session::new(false, OnClose::Cancel);
+        session.reduce(AppEvent::WindowClosed);
+        assert_eq!(session.ending(), Ending::Closed);
+
+        let mut session = Session::default();
+        session.abort(1);
+        assert_eq!(session.ending(), Ending::Aborted);
+    }
+
+    #[test]
+    fn test_debounce_coalesces_repeats() {
+        let mut debounce = Debounce::new(Duration::from_millis(200));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(debounce.accept(AppEvent::StopRequested, at(0)));
+        assert!(!debounce.accept(AppEvent::StopRequested, at(50)));
+        // Other kinds of request are not held up
+        assert!(debounce.accept(AppEvent::PauseRequested, at(60)));
+        // Measured from the last request that went through, so a bounce can't extend it
+        assert!(!debounce.accept(AppEvent::StopRequested, at(199)));
+        assert!(debounce.accept(AppEvent::StopRequested, at(200)));
+    }
+
+    #[test]
+    fn test_inactivity_goes_idle_once_until_active() {
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let mut inactivity = Inactivity::new(Duration::from_secs(10), start);
+        assert!(!inactivity.check(at(9)));
+        assert!(inactivity.check(at(10)));
+        assert!(!inactivity.check(at(11)));
+        assert!(inactivity.active(at(12)));
+        // The timeout starts over from the last activity
+        assert!(!inactivity.active(at(15)));
+        assert!(!inactivity.check(at(24)));
+        assert!(inactivity.check(at(25)));
+    }
+
+    #[test]
+    fn test_format_elapsed() {
+        let time = TimeFormat::default();
+        assert_eq!(format_elapsed(Duration::ZERO, time), "0:00");
+        assert_eq!(format_elapsed(Duration::from_millis(65_900), time), "1:05");
+        assert_eq!(format_elapsed(Duration::from_secs(3723), time), "1:02:03");
+
+        let finnish = TimeFormat { separator: '.' };
+        assert_eq!(format_elapsed(Duration::from_secs(3723), finnish), "1.02.03");
+    }
+
+    #[test]
+    fn test_timer_text() {
+        let time = TimeFormat::default();
+        let recorded = Some(Duration::from_secs(65));
+        assert_eq!(timer_text(recorded, None, time), "1:05");
+        assert_eq!(timer_text(None, Some(Duration::from_millis(54_200)), time), "0:55 left");
+        assert_eq!(
+            timer_text(recorded, Some(Duration::from_secs(55)), time),
+            "1:05 · 0:55 left"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = Summary {
+            ending: Ending::Stopped(StopReason::User),
+            elapsed: Some(Duration::from_millis(12_440)),
+            transcription_chars: Some(214),
+            destination: Some("clipboard"),
+            exit_code: 0,
+            numbers: NumberFormat::default(),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "stopped by user after 12.4s; transcription 214 chars → clipboard; exit 0"
+        );
+
+        let summary = Summary {
+            ending: Ending::ExitedOnItsOwn,
+            elapsed: Some(Duration::from_secs(3)),
+            transcription_chars: None,
+            destination: Some("clipboard"),
+            exit_code: 2,
+            numbers: NumberFormat::default(),
+        };
+        assert_eq!(summary.to_string(), "command exited on its own after 3.0s; exit 2");
+    }
+
+    #[test]
+    fn test_summary_uses_locale_numbers() {
+        let summary = Summary {
+            ending: Ending::Stopped(StopReason::User),
+            elapsed: Some(Duration::from_millis(12_440)),
+            transcription_chars: Some(1_214),
+            destination: None,
+            exit_code: 0,
+            numbers: NumberFormat::from_conv(b",", b".", &[3, 3]),
+        };
+        assert_eq!(
+            summary.to_string(),
+            "stopped by user after 12,4s; transcription 1.214 chars; exit 0"
+        );
+    }
+
+    #[test]
+    fn test_repeated_stop_escalates() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(
+            session.reduce(AppEvent::StopRequested),
+            vec![Effect::SignalChild(Signal::SIGTERM)]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::StopRequested),
+            vec![Effect::SignalChild(Signal::SIGKILL)]
+        );
+        assert_eq!(session.reduce(AppEvent::StopRequested), vec![]);
+    }
+
+    #[test]
+    fn test_kill_timeout_escalates_then_gives_up() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(
+            session.reduce(AppEvent::KillTimeoutElapsed),
+            vec![
+                Effect::SignalChild(Signal::SIGTERM),
+                Effect::ArmEscalationTimer
+            ]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::KillTimeoutElapsed),
+            vec![
+                Effect::SignalChild(Signal::SIGKILL),
+                Effect::ArmEscalationTimer
+            ]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::KillTimeoutElapsed),
+            vec![Effect::SetState(SessionState::Error), Effect::CloseWindow]
+        );
+        assert!(session.is_finished());
+        assert_eq!(session.exit_code(), 1);
+    }
+
+    #[test]
+    fn test_kill_timeout_after_exit_is_ignored() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        session.reduce(AppEvent::ChildExited(0));
+        assert_eq!(session.reduce(AppEvent::KillTimeoutElapsed), vec![]);
+        assert_eq!(session.exit_code(), 0);
+    }
+
+    #[test]
+    fn test_max_duration_stops_but_never_escalates() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::MaxDurationElapsed),
+            vec![
+                Effect::StopChild,
+                Effect::SetState(SessionState::Processing),
+                Effect::ArmKillTimer,
+            ]
+        );
+        assert_eq!(session.reduce(AppEvent::MaxDurationElapsed), vec![]);
+    }
+
+    #[test]
+    fn test_pause_toggles() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::PauseRequested),
+            vec![Effect::TogglePause, Effect::SetState(SessionState::Paused)]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::PauseRequested),
+            vec![Effect::TogglePause, Effect::SetState(SessionState::Recording)]
+        );
+    }
+
+    #[test]
+    fn test_pause_ignored_while_stopping() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(session.reduce(AppEvent::PauseRequested), vec![]);
+    }
+
+    #[test]
+    fn test_unexpected_exit_propagates_code() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::ChildExitedUnexpectedly(3)),
+            vec![Effect::SetState(SessionState::Error), Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), 3);
+    }
+
+    #[test]
+    fn test_window_closed_while_recording_detaches() {
+        // Also with --spawn-first, whose command dies with the wrapper: the wrapper stays to wait
+        // for it
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::WindowClosed),
+            vec![
+                Effect::StopChild,
+                Effect::SetState(SessionState::Processing),
+                Effect::Detach,
+            ]
+        );
+        assert_eq!(session.ending(), Ending::Detached);
+        assert!(session.is_finished());
+
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(session.reduce(AppEvent::WindowClosed), vec![Effect::Detach]);
+        assert_eq!(session.ending(), Ending::Detached);
+    }
+
+    #[test]
+    fn test_window_closed_with_on_close_cancel() {
+        let mut session = Session::new(false, OnClose::Cancel);
+        assert_eq!(
+            session.reduce(AppEvent::WindowClosed),
+            vec![Effect::CancelChild, Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), 130);
+    }
+
+    #[test]
+    fn test_window_closed_with_on_close_graceful_waits_for_child() {
+        let mut session = Session::new(false, OnClose::Graceful);
+        assert_eq!(
+            session.reduce(AppEvent::WindowClosed),
+            vec![
+                Effect::StopChild,
+                Effect::SetState(SessionState::Processing),
+                Effect::ArmKillTimer,
+            ]
+        );
+        assert_eq!(session.reduce(AppEvent::WindowClosed), vec![]);
+        assert_eq!(session.reduce(AppEvent::ChildExited(0)), vec![Effect::CloseWindow]);
+        assert_eq!(session.exit_code(), 0);
+        assert_eq!(session.ending(), Ending::Stopped(StopReason::WindowClosed));
+    }
+
+    #[test]
+    fn test_detach_while_recording_stops_first() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::DetachRequested),
+            vec![
+                Effect::StopChild,
+                Effect::SetState(SessionState::Processing),
+                Effect::Detach
+            ]
+        );
+        assert!(session.is_finished());
+        assert_eq!(session.ending(), Ending::Detached);
+        // The exit is collected outside the event loop from now on
+        assert_eq!(session.reduce(AppEvent::ChildExited(0)), vec![]);
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut session = Session::default();
+        assert_eq!(
+            session.reduce(AppEvent::CancelRequested),
+            vec![Effect::CancelChild, Effect::CloseWindow]
+        );
+        assert_eq!(session.exit_code(), 130);
+        assert_eq!(session.ending(), Ending::Cancelled);
+        assert_eq!(session.reduce(AppEvent::ChildExited(0)), vec![]);
+    }
+
+    #[test]
+    fn test_cancel_while_stopping() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(
+            session.reduce(AppEvent::CancelRequested),
+            vec![Effect::SignalChild(Signal::SIGKILL), Effect::CloseWindow]
+        );
+        assert_eq!(session.ending(), Ending::Cancelled);
+    }
+
+    #[test]
+    fn test_color_scheme_change_applies_in_any_phase() {
+        let mut session = Session::default();
+        let event = AppEvent::ColorSchemeChanged(ColorScheme::PreferLight);
+        let effect = Effect::FollowColorScheme(ColorScheme::PreferLight);
+        assert_eq!(session.reduce(event.clone()), vec![effect.clone()]);
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(session.reduce(event), vec![effect]);
+    }
+
+    #[test]
+    fn test_restart_resumes() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::PauseRequested);
+        assert_eq!(
+            session.reduce(AppEvent::RestartRequested),
+            vec![Effect::RestartChild, Effect::SetState(SessionState::Recording)]
+        );
+        // A restarted session still stops normally
+        assert_eq!(session.reduce(AppEvent::StopRequested)[0], Effect::StopChild);
+
+        // Too late once stopping
+        assert_eq!(session.reduce(AppEvent::RestartRequested), vec![]);
+    }
+
+    #[test]
+    fn test_detach_while_stopping() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::StopRequested);
+        assert_eq!(session.reduce(AppEvent::DetachRequested), vec![Effect::Detach]);
+        assert_eq!(session.ending(), Ending::Detached);
+    }
+
+    #[test]
+    fn test_panic_kills_all() {
+        let mut session = Session::default();
+        assert_eq!(session.reduce(AppEvent::PanicRequested), vec![Effect::KillAll]);
+        assert!(!session.is_finished());
+    }
+
+    #[test]
+    fn test_confirm_panic_requires_second_press() {
+        let mut session = Session::new(true, OnClose::default());
+        assert_eq!(
+            session.reduce(AppEvent::PanicRequested),
+            vec![
+                Effect::SetState(SessionState::PanicWarning),
+                Effect::ArmPanicConfirmTimer
+            ]
+        );
+        assert_eq!(session.reduce(AppEvent::PanicRequested), vec![Effect::KillAll]);
+    }
+
+    #[test]
+    fn test_unconfirmed_panic_expires() {
+        let mut session = Session::new(true, OnClose::default());
+        session.reduce(AppEvent::PanicRequested);
+        assert_eq!(
+            session.reduce(AppEvent::PanicConfirmExpired),
+            vec![Effect::SetState(SessionState::Recording)]
+        );
+        assert_eq!(
+            session.reduce(AppEvent::PanicRequested),
+            vec![
+                Effect::SetState(SessionState::PanicWarning),
+                Effect::ArmPanicConfirmTimer
+            ]
+        );
+    }
+
+    #[test]
+    fn test_events_after_finish_are_ignored() {
+        let mut session = Session::default();
+        session.reduce(AppEvent::ChildExitedUnexpectedly(2));
+        assert_eq!(session.reduce(AppEvent::StopRequested), vec![]);
+        assert_eq!(session.reduce(AppEvent::WindowClosed), vec![]);
+        assert_eq!(session.reduce(AppEvent::ChildExited(0)), vec![]);
+        assert_eq!(session.exit_code(), 2);
+    }
+}
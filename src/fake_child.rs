@@ -0,0 +1,50 @@
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use nix::sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+use crate::config::FakeChildArgs;
+
+static STOP_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn request_stop(_: nix::libc::c_int) {
+    STOP_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+fn set_handler(signal: Signal, handler: SigHandler) -> nix::Result<()> {
+    let action = SigAction::new(handler, SaFlags::empty(), SigSet::empty());
+    // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+    unsafe { sigaction(signal, &action) }.map(|_| ())
+}
+
+/// Behave like a minimal waystt: run until SIGUSR1 (or SIGTERM), then print the "transcription"
+/// and exit, so the wrapper's signal and exit handling can be tested without waystt
+pub fn run(args: FakeChildArgs) -> ExitCode {
+    let handler = if args.ignore_stop {
+        SigHandler::SigIgn
+    } else {
+        SigHandler::Handler(request_stop)
+    };
+    for signal in [Signal::SIGUSR1, Signal::SIGTERM] {
+        if let Err(e) = set_handler(signal, handler) {
+            eprintln!("fake-child: failed to install {signal} handler: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let deadline = args
+        .exit_after_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    loop {
+        if STOP_REQUESTED.load(Ordering::SeqCst) {
+            println!("{}", args.text);
+            return ExitCode::from(args.exit_code as u8);
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return ExitCode::from(args.exit_code as u8);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+}
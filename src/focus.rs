@@ -0,0 +1,90 @@
+use std::process::{Command, Stdio};
+
+use serde_json::Value;
+use tracing::debug;
+
+/// Value of `--output` that follows the focused output instead of naming one
+pub const FOCUSED: &str = "focused";
+
+/// Connector name of the output the user is working on, asked of the compositor over its IPC.
+/// `None` if the compositor is not one we can ask, or did not answer.
+pub fn focused_output() -> Option<String> {
+    let env = |name| std::env::var_os(name).is_some();
+    if env("SWAYSOCK") {
+        query(&["swaymsg", "--raw", "--type", "get_outputs"]).and_then(|v| focused_in_list(&v))
+    } else if env("HYPRLAND_INSTANCE_SIGNATURE") {
+        query(&["hyprctl", "monitors", "-j"]).and_then(|v| focused_in_list(&v))
+    } else if env("NIRI_SOCKET") {
+        query(&["niri", "msg", "--json", "focused-output"]).and_then(|v| niri_focused(&v))
+    } else {
+        debug!("No compositor IPC to ask for the focused output");
+        None
+    }
+}
+
+/// Run an IPC client and parse its JSON output
+fn query(command: &[&str]) -> Option<Value> {
+    let output = Command::new(command[0])
+        .args(&command[1..])
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => serde_json::from_slice(&output.stdout).ok(),
+        Ok(output) => {
+            debug!(command = command[0], status = ?output.status, "IPC query failed");
+            None
+        }
+        Err(e) => {
+            debug!(command = command[0], error = %e, "Could not run IPC client");
+            None
+        }
+    }
+}
+
+/// The focused one of a list of outputs, as printed by `swaymsg` and `hyprctl` alike
+fn focused_in_list(outputs: &Value) -> Option<String> {
+    outputs
+        .as_array()?
+        .iter()
+        .find(|output| output["focused"] == true)
+        .and_then(|output| output["name"].as_str())
+        .map(str::to_string)
+}
+
+fn niri_focused(output: &Value) -> Option<String> {
+    output["name"].as_str().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_sway_focused() {
+        let outputs = json!([
+            { "name": "eDP-1", "focused": false, "active": true },
+            { "name": "DP-2", "focused": true, "active": true }
+        ]);
+        assert_eq!(focused_in_list(&outputs).as_deref(), Some("DP-2"));
+        assert_eq!(focused_in_list(&json!([])), None);
+    }
+
+    #[test]
+    fn test_hyprland_focused() {
+        let monitors = json!([
+            { "id": 0, "name": "HDMI-A-1", "focused": true },
+            { "id": 1, "name": "DP-1", "focused": false }
+        ]);
+        assert_eq!(focused_in_list(&monitors).as_deref(), Some("HDMI-A-1"));
+    }
+
+    #[test]
+    fn test_niri_focused() {
+        let output = json!({ "name": "DP-3", "make": "Dell", "model": "U2720Q" });
+        assert_eq!(niri_focused(&output).as_deref(), Some("DP-3"));
+        // No output focused
+        assert_eq!(niri_focused(&Value::Null), None);
+    }
+}
@@ -0,0 +1,189 @@
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::debug;
+
+use crate::layout::Rect;
+
+/// How long to wait for Hyprland to answer, so that a wedged compositor can't delay the overlay
+const IPC_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The window the user is working in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FocusedWindow {
+    /// Connector name of the monitor it is on
+    pub monitor: String,
+    /// Its geometry in logical pixels, relative to that monitor
+    pub geometry: Rect,
+}
+
+/// Where the pointer is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pointer {
+    /// Connector name of the monitor it is on
+    pub monitor: String,
+    /// Its position in logical pixels, relative to that monitor
+    pub x: i32,
+    pub y: i32,
+}
+
+/// Hyprland's request socket, if running under Hyprland
+fn socket_path() -> Option<PathBuf> {
+    let signature = std::env::var_os("HYPRLAND_INSTANCE_SIGNATURE")?;
+    // Under $XDG_RUNTIME_DIR since Hyprland 0.40, /tmp before that
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(|dir| PathBuf::from(dir).join("hypr"))
+        .into_iter()
+        .chain([PathBuf::from("/tmp/hypr")])
+        .map(|dir| dir.join(&signature).join(".socket.sock"))
+        .find(|path| path.exists())
+}
+
+/// Send a request (e.g. `activewindow`) and parse the JSON reply
+fn request(socket: &Path, command: &str) -> io::Result<Value> {
+    let mut stream = UnixStream::connect(socket)?;
+    stream.set_read_timeout(Some(IPC_TIMEOUT))?;
+    stream.set_write_timeout(Some(IPC_TIMEOUT))?;
+    stream.write_all(format!("j/{command}").as_bytes())?;
+    let mut reply = Vec::new();
+    stream.read_to_end(&mut reply)?;
+    Ok(serde_json::from_slice(&reply)?)
+}
+
+/// The focused window, or `None` if not running under Hyprland, no window is focused, or
+/// Hyprland could not be asked
+pub fn focused_window() -> Option<FocusedWindow> {
+    let socket = socket_path()?;
+    let query = || -> io::Result<_> {
+        Ok((request(&socket, "activewindow")?, request(&socket, "monitors")?))
+    };
+    match query() {
+        Ok((window, monitors)) => parse_focused_window(&window, &monitors),
+        Err(e) => {
+            debug!(error = %e, "Hyprland IPC request failed");
+            None
+        }
+    }
+}
+
+/// The pointer's position, or `None` if not running under Hyprland or Hyprland could not be
+/// asked
+pub fn pointer() -> Option<Pointer> {
+    let socket = socket_path()?;
+    let query = || -> io::Result<_> {
+        Ok((request(&socket, "cursorpos")?, request(&socket, "monitors")?))
+    };
+    match query() {
+        Ok((position, monitors)) => parse_pointer(&position, &monitors),
+        Err(e) => {
+            debug!(error = %e, "Hyprland IPC request failed");
+            None
+        }
+    }
+}
+
+fn parse_pointer(position: &Value, monitors: &Value) -> Option<Pointer> {
+    let x = i32::try_from(position["x"].as_i64()?).ok()?;
+    let y = i32::try_from(position["y"].as_i64()?).ok()?;
+    // Monitors are laid out in logical pixels, but their sizes are given in physical ones
+    let contains = |monitor: &Value| -> Option<(i32, i32)> {
+        let origin_x = i32::try_from(monitor["x"].as_i64()?).ok()?;
+        let origin_y = i32::try_from(monitor["y"].as_i64()?).ok()?;
+        let scale = monitor["scale"].as_f64().unwrap_or(1.0);
+        let width = (monitor["width"].as_f64()? / scale).round() as i32;
+        let height = (monitor["height"].as_f64()? / scale).round() as i32;
+        let inside = (origin_x..origin_x + width).contains(&x)
+            && (origin_y..origin_y + height).contains(&y);
+        inside.then_some((origin_x, origin_y))
+    };
+    monitors.as_array()?.iter().find_map(|monitor| {
+        let (origin_x, origin_y) = contains(monitor)?;
+        Some(Pointer {
+            monitor: monitor["name"].as_str()?.to_string(),
+            x: x - origin_x,
+            y: y - origin_y,
+        })
+    })
+}
+
+fn parse_focused_window(window: &Value, monitors: &Value) -> Option<FocusedWindow> {
+    let pair = |value: &Value| -> Option<(i32, i32)> {
+        let x = i32::try_from(value[0].as_i64()?).ok()?;
+        let y = i32::try_from(value[1].as_i64()?).ok()?;
+        Some((x, y))
+    };
+    let (x, y) = pair(&window["at"])?;
+    let (width, height) = pair(&window["size"])?;
+    let monitor = monitors
+        .as_array()?
+        .iter()
+        .find(|monitor| monitor["id"] == window["monitor"])?;
+    let origin_x = i32::try_from(monitor["x"].as_i64()?).ok()?;
+    let origin_y = i32::try_from(monitor["y"].as_i64()?).ok()?;
+    Some(FocusedWindow {
+        monitor: monitor["name"].as_str()?.to_string(),
+        geometry: Rect {
+            x: x - origin_x,
+            y: y - origin_y,
+            width,
+            height,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_focused_window() {
+        let window = json!({ "at": [2660, 140], "size": [1200, 800], "monitor": 1 });
+        let monitors = json!([
+            { "id": 0, "name": "eDP-1", "x": 0, "y": 0 },
+            { "id": 1, "name": "DP-1", "x": 2560, "y": 0 }
+        ]);
+        assert_eq!(
+            parse_focused_window(&window, &monitors),
+            Some(FocusedWindow {
+                monitor: "DP-1".to_string(),
+                geometry: Rect {
+                    x: 100,
+                    y: 140,
+                    width: 1200,
+                    height: 800
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_pointer() {
+        let monitors = json!([
+            { "name": "eDP-1", "x": 0, "y": 0, "width": 2880, "height": 1800, "scale": 2.0 },
+            { "name": "DP-1", "x": 1440, "y": 0, "width": 2560, "height": 1440, "scale": 1.0 }
+        ]);
+        assert_eq!(
+            parse_pointer(&json!({ "x": 1500, "y": 300 }), &monitors),
+            Some(Pointer {
+                monitor: "DP-1".to_string(),
+                x: 60,
+                y: 300
+            })
+        );
+        assert_eq!(
+            parse_pointer(&json!({ "x": 100, "y": 850 }), &monitors).map(|p| p.monitor),
+            Some("eDP-1".to_string())
+        );
+        assert_eq!(parse_pointer(&json!({ "x": 100, "y": 950 }), &monitors), None);
+    }
+
+    #[test]
+    fn test_no_focused_window() {
+        // What Hyprland answers with an empty workspace
+        assert_eq!(parse_focused_window(&json!({}), &json!([])), None);
+    }
+}
@@ -0,0 +1,108 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+use tracing::{debug, info, warn};
+
+use crate::config::{parse_setting, Setting};
+use crate::registry;
+
+/// A running overlay's control socket, taking one `key=value` setting per line and answering
+/// each with `ok` or `error: ...`. Removed when dropped.
+pub struct ControlSocket {
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listen on `path`, calling `on_setting` from a background thread for every valid setting
+    pub fn bind(path: PathBuf, on_setting: impl Fn(Setting) + Send + 'static) -> io::Result<Self> {
+        // Left behind by a wrapper that was killed outright with the same pid
+        let _ = fs::remove_file(&path);
+        let listener = UnixListener::bind(&path)?;
+        registry::track_artifact(path.clone());
+        info!(path = %path.display(), "Listening for settings");
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        if let Err(e) = serve(stream, &on_setting) {
+                            debug!(error = %e, "Control connection failed");
+                        }
+                    }
+                    Err(e) => warn!(error = %e, "Failed to accept control connection"),
+                }
+            }
+        });
+
+        Ok(Self { path })
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        if registry::untrack_artifact(&self.path) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn serve(stream: UnixStream, on_setting: &impl Fn(Setting)) -> io::Result<()> {
+    let mut reply = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        match parse_setting(line?.trim()) {
+            Ok(setting) => {
+                info!(%setting, "Setting changed");
+                on_setting(setting);
+                writeln!(reply, "ok")?;
+            }
+            Err(e) => writeln!(reply, "error: {e}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Send `settings` to the overlay listening on `path`, returning its answer to each
+pub fn send(path: &Path, settings: &[Setting]) -> io::Result<Vec<String>> {
+    let mut stream = UnixStream::connect(path)?;
+    for setting in settings {
+        writeln!(stream, "{setting}")?;
+    }
+    stream.shutdown(std::net::Shutdown::Write)?;
+    BufReader::new(stream).lines().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Theme;
+    use std::sync::mpsc;
+
+    #[test]
+    fn test_settings_reach_the_overlay() {
+        let _tracking = registry::serialize_tracking();
+        let path = std::env::temp_dir().join(format!("waystt-wrapper-{}.sock", std::process::id()));
+        let (sender, settings) = mpsc::channel();
+        let socket = ControlSocket::bind(path.clone(), move |setting| {
+            let _ = sender.send(setting);
+        })
+        .unwrap();
+
+        let replies = send(&path, &[Setting::Theme(Theme::Light)]).unwrap();
+        assert_eq!(replies, vec!["ok"]);
+        assert_eq!(settings.recv().unwrap(), Setting::Theme(Theme::Light));
+
+        // Invalid lines are answered, not applied
+        let mut stream = UnixStream::connect(&path).unwrap();
+        writeln!(stream, "theme=sepia").unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let reply = BufReader::new(stream).lines().next().unwrap().unwrap();
+        assert!(reply.starts_with("error: "), "{reply}");
+        assert!(settings.try_recv().is_err());
+
+        drop(socket);
+        assert!(!path.exists());
+    }
+}
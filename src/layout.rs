@@ -0,0 +1,818 @@
+use std::fmt;
+
+use crate::config::{
+    Appearance, Config, ExclusiveZone, IconColor, KeyboardMode, Layer, Position, StateColors,
+};
+use crate::contrast::{self, ICON_MIN_CONTRAST, TEXT_MIN_CONTRAST};
+use crate::quirks::Quirks;
+
+/// Spacing between the icon and the labels below it, in pixels
+const SPACING: i32 = 6;
+
+/// Smallest icon (and hit target) size with `--a11y-large`, in pixels
+const A11Y_MIN_SIZE: i32 = 48;
+
+/// How much `--a11y-large` scales `--padding` and spacing
+const A11Y_SCALE: i32 = 2;
+
+/// Diameter of the `--appearance dot` indicator, in pixels (scaled by `--a11y-large`)
+const DOT_SIZE: i32 = 16;
+
+/// Monitor size assumed for percentage icon sizes when the monitor isn't known
+const FALLBACK_MONITOR_SIZE: (i32, i32) = (1920, 1080);
+
+/// A screen edge, independent of the surface implementation
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+impl Edge {
+    pub const ALL: [Edge; 4] = [Edge::Top, Edge::Bottom, Edge::Left, Edge::Right];
+}
+
+/// A rectangle in logical pixels, relative to a monitor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Gap between the overlay and the window it is placed next to, in pixels
+const WINDOW_GAP: i32 = 8;
+
+/// A surface the overlay can be realized on (e.g. a layer-shell window)
+pub trait LayoutTarget {
+    fn set_layer(&self, layer: Layer);
+    fn set_keyboard_mode(&self, mode: KeyboardMode);
+    fn set_anchor(&self, edge: Edge, anchored: bool);
+    fn set_margin(&self, edge: Edge, margin: i32);
+    fn set_exclusive_zone(&self, zone: ExclusiveZone);
+}
+
+/// Everything about the overlay's placement and look that can be computed without GTK
+#[derive(Debug, Clone, PartialEq)]
+pub struct Layout {
+    pub width: i32,
+    pub height: i32,
+    pub icon_size: i32,
+    /// Spacing between the icon and the labels next to it
+    pub spacing: i32,
+    /// Lay the icon and labels out side by side (bar) rather than stacked
+    pub horizontal: bool,
+    pub layer: Layer,
+    pub keyboard_mode: KeyboardMode,
+    /// Space to reserve; by default the overlay floats over windows
+    pub exclusive_zone: Option<ExclusiveZone>,
+    anchors: Vec<Edge>,
+    margins: [i32; 4],
+    pub css: String,
+}
+
+impl Layout {
+    /// Compute the layout for a monitor of `monitor` (width, height) logical pixels, if known
+    pub fn compute(config: &Config, quirks: Quirks, monitor: Option<(i32, i32)>) -> Self {
+        let anchors = match config.appearance {
+            Appearance::Floating | Appearance::Dot => anchors(config.position),
+            Appearance::Bar => bar_anchors(config.position),
+        };
+        let margins = Edge::ALL.map(|edge| {
+            if quirks.anchored_margins_only && !anchors.contains(&edge) {
+                0
+            } else {
+                config.margin
+            }
+        });
+
+        let (monitor_width, monitor_height) = monitor.unwrap_or(FALLBACK_MONITOR_SIZE);
+        let icon_size = config.icon_size.resolve(monitor_width, monitor_height);
+        let (icon_size, scale) = if config.a11y_large {
+            (icon_size.max(A11Y_MIN_SIZE), A11Y_SCALE)
+        } else {
+            (icon_size, 1)
+        };
+        let padding = config.padding as i32 * scale;
+        // The dot is the icon alone, without a box around it
+        let (icon_size, padding) = match config.appearance {
+            Appearance::Dot => (DOT_SIZE * scale, 0),
+            Appearance::Floating | Appearance::Bar => (icon_size, padding),
+        };
+
+        Self {
+            width: icon_size + 2 * padding,
+            height: icon_size + 2 * padding,
+            icon_size,
+            spacing: SPACING * scale,
+            horizontal: config.appearance == Appearance::Bar,
+            // Overlay layer and exclusive keyboard, unless configured or the compositor needs
+            // otherwise
+            layer: config.layer.unwrap_or(quirks.layer),
+            keyboard_mode: config.keyboard_mode.unwrap_or(quirks.keyboard_mode),
+            exclusive_zone: config.exclusive_zone,
+            anchors,
+            margins,
+            css: css(config, padding),
+        }
+    }
+
+    /// Place the overlay centered just above `window` by anchoring to the top-left corner and
+    /// offsetting with margins, or just inside its top edge if there is no room above
+    pub fn place_above(&mut self, window: Rect) {
+        let above = window.y - self.height - WINDOW_GAP;
+        let top = if above >= 0 {
+            above
+        } else {
+            window.y + WINDOW_GAP
+        };
+        let left = (window.x + (window.width - self.width) / 2).max(0);
+        self.place_at(top, left);
+    }
+
+    /// Place the overlay `top` and `left` pixels from the top-left corner of the monitor, e.g.
+    /// where it was last dragged to
+    pub fn place_at(&mut self, top: i32, left: i32) {
+        self.anchors = vec![Edge::Top, Edge::Left];
+        self.margins = Edge::ALL.map(|edge| match edge {
+            Edge::Top => top,
+            Edge::Left => left,
+            Edge::Bottom | Edge::Right => 0,
+        });
+    }
+
+    /// The `--dim-screen` surface behind this overlay: the whole output, over any panels, on the
+    /// layer just below and without keyboard input. `None` if the overlay is already on the
+    /// lowest layer.
+    pub fn dim(&self) -> Option<Layout> {
+        let layer = match self.layer {
+            Layer::Overlay => Layer::Top,
+            Layer::Top => Layer::Bottom,
+            Layer::Bottom => Layer::Background,
+            Layer::Background => return None,
+        };
+        Some(Layout {
+            width: 0,
+            height: 0,
+            layer,
+            keyboard_mode: KeyboardMode::None,
+            exclusive_zone: Some(ExclusiveZone::Pixels(-1)),
+            anchors: Edge::ALL.to_vec(),
+            margins: [0; 4],
+            ..self.clone()
+        })
+    }
+
+    pub fn is_anchored(&self, edge: Edge) -> bool {
+        self.anchors.contains(&edge)
+    }
+
+    pub fn margin(&self, edge: Edge) -> i32 {
+        let index = Edge::ALL.iter().position(|e| *e == edge).unwrap_or_default();
+        self.margins[index]
+    }
+
+    /// Realize the layer, keyboard mode, anchors, margins and exclusive zone on `target`
+    pub fn apply(&self, target: &impl LayoutTarget) {
+        target.set_layer(self.layer);
+        target.set_keyboard_mode(self.keyboard_mode);
+        for edge in Edge::ALL {
+            target.set_anchor(edge, self.is_anchored(edge));
+            target.set_margin(edge, self.margin(edge));
+        }
+        if let Some(zone) = self.exclusive_zone {
+            target.set_exclusive_zone(zone);
+        }
+    }
+}
+
+/// The requested geometry, for `--dry-run`
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size:          {}x{}", self.width, self.height)?;
+        writeln!(f, "layer:         {:?}", self.layer)?;
+        writeln!(f, "keyboard mode: {:?}", self.keyboard_mode)?;
+        if let Some(zone) = self.exclusive_zone {
+            writeln!(f, "exclusive zone: {zone:?}")?;
+        }
+        if self.anchors.is_empty() {
+            writeln!(f, "anchors:       none (centered)")?;
+        } else {
+            let anchors: Vec<String> = self.anchors.iter().map(|edge| format!("{edge:?}")).collect();
+            writeln!(f, "anchors:       {}", anchors.join(", "))?;
+        }
+        let margins: Vec<String> = Edge::ALL
+            .iter()
+            .map(|edge| format!("{edge:?} {}", self.margin(*edge)))
+            .collect();
+        writeln!(f, "margins:       {}", margins.join(", "))
+    }
+}
+
+/// Edges to anchor to for `position`. No anchors = centered; a single anchor = centered along
+/// that edge.
+fn anchors(position: Position) -> Vec<Edge> {
+    match position {
+        Position::TopLeft => vec![Edge::Top, Edge::Left],
+        Position::Top => vec![Edge::Top],
+        Position::TopRight => vec![Edge::Top, Edge::Right],
+        Position::Left => vec![Edge::Left],
+        Position::Right => vec![Edge::Right],
+        Position::BottomLeft => vec![Edge::Bottom, Edge::Left],
+        Position::Bottom => vec![Edge::Bottom],
+        Position::BottomRight => vec![Edge::Bottom, Edge::Right],
+        // Without a focused window or pointer to go above
+        Position::Center | Position::AboveWindow | Position::Cursor => vec![],
+    }
+}
+
+/// Distance of a surface of `size` from the start (top or left) of a monitor `extent` pixels
+/// long, given the margins of the start and end edges it is anchored to (if any). Unanchored
+/// surfaces are centered.
+pub fn start_offset(start: Option<i32>, end: Option<i32>, extent: i32, size: i32) -> i32 {
+    match (start, end) {
+        (Some(margin), _) => margin,
+        (None, Some(margin)) => extent - size - margin,
+        (None, None) => (extent - size) / 2,
+    }
+    .max(0)
+}
+
+/// A bar spans the width of the screen along the top edge for top positions, otherwise the bottom
+fn bar_anchors(position: Position) -> Vec<Edge> {
+    let edge = match position {
+        Position::TopLeft | Position::Top | Position::TopRight => Edge::Top,
+        _ => Edge::Bottom,
+    };
+    vec![edge, Edge::Left, Edge::Right]
+}
+
+/// Default `--border-width` when only `--border-color` is given
+const DEFAULT_BORDER_WIDTH: u32 = 2;
+
+/// Room around the overlay for `--shadow`, since nothing is drawn outside the surface
+const SHADOW_MARGIN: i32 = 10;
+
+/// CSS class of an overlay that has faded in with `--animation-duration`
+pub const SHOWN_CLASS: &str = "shown";
+
+/// CSS class of the `--dim-screen` surfaces behind the overlay
+pub const DIM_CLASS: &str = "dim";
+
+/// CSS class of an overlay faded out of the way by `--auto-hide`
+pub const IDLE_CLASS: &str = "idle";
+
+/// Opacity of an overlay faded out by `--auto-hide`
+const IDLE_OPACITY: f64 = 0.2;
+
+/// How dark the `--dim-screen` surfaces make the screen, from 0 to 1
+const DIM_OPACITY: f64 = 0.4;
+
+/// Color of the `--rec-dot`, the red screen recorders use
+const REC_COLOR: &str = "#e01b24";
+
+/// Corner radius of the floating overlay unless `--corner-radius` is given, in pixels
+const DEFAULT_CORNER_RADIUS: u32 = 10;
+
+/// Generate the overlay stylesheet from the configured colors, with `padding` pixels around the
+/// icon
+fn css(config: &Config, padding: i32) -> String {
+    let font_size = if config.a11y_large { "x-large" } else { "small" };
+    // A bar spans the screen, so its corners are square unless asked otherwise
+    let border_radius = match (config.corner_radius, config.appearance) {
+        (Some(radius), _) => radius,
+        (None, Appearance::Floating) => DEFAULT_CORNER_RADIUS,
+        (None, Appearance::Bar | Appearance::Dot) => 0,
+    };
+    let palette = config.palette();
+    let mut icon = match &config.icon_color {
+        Some(IconColor::Css(color)) => color.as_str(),
+        _ => palette.icon,
+    };
+    let mut text = palette.text;
+    let mut states = config.state_palette.map(|palette| palette.colors());
+    if config.enforce_contrast {
+        let (background, opacity) = (&config.background_color, config.opacity);
+        icon = contrast::ensure(icon, background, opacity, ICON_MIN_CONTRAST);
+        let ensure = |color: &'static str| {
+            contrast::ensure(color, background, opacity, ICON_MIN_CONTRAST)
+        };
+        text = contrast::ensure(text, background, opacity, TEXT_MIN_CONTRAST);
+        states = states.map(|states| StateColors {
+            recording: ensure(states.recording),
+            paused: ensure(states.paused),
+            processing: ensure(states.processing),
+            error: ensure(states.error),
+        });
+    }
+    // Over the single icon color, in the state classes of the icon
+    let state_colors = match states {
+        Some(states) => format!(
+            "image.recording {{
+            color: {};
+        }}
+        image.paused {{
+            color: {};
+        }}
+        image.processing, spinner.processing {{
+            color: {};
+        }}
+        image.error {{
+            color: {};
+        }}",
+            states.recording, states.paused, states.processing, states.error
+        ),
+        None => String::new(),
+    };
+    let background = match config.appearance {
+        Appearance::Dot => "transparent".to_string(),
+        Appearance::Floating | Appearance::Bar => {
+            format!("alpha({}, {})", config.background_color, config.opacity)
+        }
+    };
+    let border = match (config.border_width, &config.border_color) {
+        _ if config.appearance == Appearance::Dot => "none".to_string(),
+        (None, None) => palette.border.to_string(),
+        (width, color) => format!(
+            "{}px solid {}",
+            width.unwrap_or(DEFAULT_BORDER_WIDTH),
+            color.as_deref().unwrap_or(palette.text)
+        ),
+    };
+    // Transparent until shown, so that it fades in and back out
+    let fade = if config.animation_duration > 0 {
+        format!(
+            "opacity: 0;
+            transition: opacity {}ms ease-in-out;
+        }}
+        window.{SHOWN_CLASS} {{
+            opacity: 1;",
+            config.animation_duration
+        )
+    } else {
+        String::new()
+    };
+    let shadow = if config.shadow {
+        format!(
+            "box-shadow: 0 2px {SHADOW_MARGIN}px alpha(black, 0.6);
+            margin: {SHADOW_MARGIN}px;"
+        )
+    } else {
+        "box-shadow: none;".to_string()
+    };
+    let dim = if config.dim_screen {
+        format!(
+            "window.{DIM_CLASS} {{
+            background-color: alpha(black, {DIM_OPACITY});
+            border: none;
+            border-radius: 0;
+            padding: 0;
+            margin: 0;
+            box-shadow: none;
+        }}"
+        )
+    } else {
+        String::new()
+    };
+    // Over the opacity of a faded in window, which it then transitions from
+    let idle = if config.auto_hide.is_some() {
+        format!(
+            "window.{IDLE_CLASS}, window.{SHOWN_CLASS}.{IDLE_CLASS} {{
+            opacity: {IDLE_OPACITY};
+        }}"
+        )
+    } else {
+        String::new()
+    };
+    let rec_color = states.map_or(REC_COLOR, |states| states.recording);
+    // Blinks at 1Hz, half on and half off; transparent (but still taking up its room, so that
+    // nothing moves) unless it follows the icon while recording
+    let rec = if config.rec_dot {
+        format!(
+            "@keyframes blink {{
+            0% {{ opacity: 1; }}
+            50% {{ opacity: 1; }}
+            51% {{ opacity: 0; }}
+            100% {{ opacity: 0; }}
+        }}
+        label.rec {{
+            color: {rec_color};
+            font-weight: bold;
+            opacity: 0;
+        }}
+        image.recording ~ label.rec {{
+            animation: blink 1s linear infinite;
+        }}"
+        )
+    } else {
+        String::new()
+    };
+    format!(
+        "window {{
+            background-color: {background};
+            border: {border};
+            border-radius: {border_radius}px;
+            padding: {padding}px;
+            {shadow}
+            {fade}
+        }}
+        image, spinner {{
+            color: {icon};
+        }}
+        @keyframes pulse {{
+            from {{ opacity: 1; }}
+            to {{ opacity: 0.4; }}
+        }}
+        /* Only while recording, so that processing looks still */
+        image.recording {{
+            animation: pulse 1s ease-in-out infinite alternate;
+        }}
+        label.hint, label.keys, label.warning, label.error, label.onboarding {{
+            color: {text};
+            font-size: {font_size};
+        }}
+        label.status, label.timer, label.preview, label.caption {{
+            color: {text};
+        }}
+        label.keys {{
+            opacity: 0.7;
+        }}
+        {state_colors}
+        {rec}
+        {idle}
+        {dim}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    use clap::Parser;
+
+    use crate::config::{theme_colors, Args};
+
+    fn compute(args: &[&str], quirks: Quirks) -> Layout {
+        let args = Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap();
+        Layout::compute(&Config::from(args), quirks, None)
+    }
+
+    #[derive(Default)]
+    struct RecordingTarget {
+        calls: RefCell<Vec<String>>,
+    }
+
+    impl LayoutTarget for RecordingTarget {
+        fn set_layer(&self, layer: Layer) {
+            self.calls.borrow_mut().push(format!("layer {layer:?}"));
+        }
+        fn set_keyboard_mode(&self, mode: KeyboardMode) {
+            self.calls.borrow_mut().push(format!("keyboard {mode:?}"));
+        }
+        fn set_anchor(&self, edge: Edge, anchored: bool) {
+            if anchored {
+                self.calls.borrow_mut().push(format!("anchor {edge:?}"));
+            }
+        }
+        fn set_margin(&self, edge: Edge, margin: i32) {
+            self.calls.borrow_mut().push(format!("margin {edge:?} {margin}"));
+        }
+        fn set_exclusive_zone(&self, zone: ExclusiveZone) {
+            self.calls.borrow_mut().push(format!("exclusive zone {zone:?}"));
+        }
+    }
+
+    #[test]
+    fn test_size_includes_padding() {
+        let layout = compute(&["--icon-size", "48"], Quirks::default());
+        assert_eq!((layout.width, layout.height), (68, 68));
+    }
+
+    #[test]
+    fn test_corner_anchors() {
+        let layout = compute(&["--position", "top-right"], Quirks::default());
+        assert!(layout.is_anchored(Edge::Top));
+        assert!(layout.is_anchored(Edge::Right));
+        assert!(!layout.is_anchored(Edge::Bottom));
+        assert!(!layout.is_anchored(Edge::Left));
+    }
+
+    #[test]
+    fn test_center_has_no_anchors() {
+        let layout = compute(&["--position", "center"], Quirks::default());
+        assert!(Edge::ALL.iter().all(|edge| !layout.is_anchored(*edge)));
+    }
+
+    #[test]
+    fn test_place_above_window() {
+        let args = ["--position", "above-window", "--icon-size", "48"];
+        let mut layout = compute(&args, Quirks::default());
+        assert!(Edge::ALL.iter().all(|edge| !layout.is_anchored(*edge)));
+
+        let window = Rect {
+            x: 100,
+            y: 200,
+            width: 1000,
+            height: 600,
+        };
+        layout.place_above(window);
+        assert!(layout.is_anchored(Edge::Top) && layout.is_anchored(Edge::Left));
+        assert_eq!(layout.margin(Edge::Top), 200 - 68 - WINDOW_GAP);
+        assert_eq!(layout.margin(Edge::Left), 100 + (1000 - 68) / 2);
+
+        // A window at the top of the screen leaves no room above it
+        layout.place_above(Rect { y: 0, ..window });
+        assert_eq!(layout.margin(Edge::Top), WINDOW_GAP);
+    }
+
+    #[test]
+    fn test_place_at() {
+        let mut layout = compute(&["--position", "bottom-right"], Quirks::default());
+        layout.place_at(40, 1200);
+        assert!(layout.is_anchored(Edge::Top) && layout.is_anchored(Edge::Left));
+        assert!(!layout.is_anchored(Edge::Bottom) && !layout.is_anchored(Edge::Right));
+        assert_eq!(layout.margin(Edge::Top), 40);
+        assert_eq!(layout.margin(Edge::Left), 1200);
+    }
+
+    #[test]
+    fn test_start_offset() {
+        assert_eq!(start_offset(Some(20), None, 1080, 68), 20);
+        assert_eq!(start_offset(None, Some(20), 1080, 68), 1080 - 68 - 20);
+        assert_eq!(start_offset(None, None, 1080, 68), (1080 - 68) / 2);
+        // Never off screen
+        assert_eq!(start_offset(None, Some(20), 50, 68), 0);
+    }
+
+    #[test]
+    fn test_margins_on_all_edges_by_default() {
+        let layout = compute(&["--position", "top", "--margin", "7"], Quirks::default());
+        assert!(Edge::ALL.iter().all(|edge| layout.margin(*edge) == 7));
+    }
+
+    #[test]
+    fn test_anchored_margins_only_quirk() {
+        let quirks = Quirks {
+            anchored_margins_only: true,
+            ..Quirks::default()
+        };
+        let layout = compute(&["--position", "top", "--margin", "7"], quirks);
+        assert_eq!(layout.margin(Edge::Top), 7);
+        assert_eq!(layout.margin(Edge::Bottom), 0);
+        assert_eq!(layout.margin(Edge::Left), 0);
+        assert_eq!(layout.margin(Edge::Right), 0);
+    }
+
+    #[test]
+    fn test_config_overrides_quirks() {
+        let quirks = Quirks {
+            keyboard_mode: KeyboardMode::OnDemand,
+            layer: Layer::Top,
+            anchored_margins_only: false,
+        };
+        let layout = compute(&[], quirks);
+        assert_eq!(layout.layer, Layer::Top);
+        assert_eq!(layout.keyboard_mode, KeyboardMode::OnDemand);
+
+        let layout = compute(&["--layer", "bottom", "--keyboard-mode", "none"], quirks);
+        assert_eq!(layout.layer, Layer::Bottom);
+        assert_eq!(layout.keyboard_mode, KeyboardMode::None);
+    }
+
+    #[test]
+    fn test_css_uses_background_and_opacity() {
+        let layout = compute(
+            &["--background-color", "#112233", "--opacity", "0.5"],
+            Quirks::default(),
+        );
+        assert!(layout.css.contains("background-color: alpha(#112233, 0.5);"));
+    }
+
+    #[test]
+    fn test_css_uses_theme_colors() {
+        let layout = compute(&["--theme", "high-contrast"], Quirks::default());
+        assert!(layout.css.contains("background-color: alpha(black, 1);"));
+        assert!(layout.css.contains("border: 3px solid white;"));
+        assert!(layout.css.contains("color: #ffff00;"));
+
+        let layout = compute(&[], Quirks::default());
+        assert!(layout.css.contains("border: none;"));
+        assert!(layout.css.contains("color: #ff5555;"));
+    }
+
+    #[test]
+    fn test_css_icon_color() {
+        let layout = compute(&["--icon-color", "lime"], Quirks::default());
+        assert!(layout.css.contains("color: lime;"));
+
+        let layout = compute(&["--icon-color", "auto"], Quirks::default());
+        let background = format!("background-color: alpha({}, 0.8);", theme_colors::BACKGROUND);
+        assert!(layout.css.contains(&background));
+        assert!(layout.css.contains(&format!("color: {};", theme_colors::ACCENT)));
+        assert!(layout.css.contains("color: @theme_fg_color;"));
+    }
+
+    #[test]
+    fn test_css_enforces_contrast() {
+        let args = ["--background-color", "#ddd", "--opacity", "1", "--icon-color", "#ccc"];
+        let layout = compute(&args, Quirks::default());
+        assert!(layout.css.contains("color: #ccc;"));
+
+        let layout = compute(&[&args[..], &["--enforce-contrast"]].concat(), Quirks::default());
+        assert!(!layout.css.contains("color: #ccc;"));
+        assert!(!layout.css.contains("color: #eeeeee;"));
+        assert!(layout.css.contains("color: black;"));
+    }
+
+    #[test]
+    fn test_dim_covers_the_output_below_the_overlay() {
+        let layout = compute(&["--dim-screen", "--margin", "3"], Quirks::default());
+        assert!(layout.css.contains("window.dim {"));
+        let dim = layout.dim().unwrap();
+        let target = RecordingTarget::default();
+        dim.apply(&target);
+        let calls = target.calls.into_inner();
+        assert_eq!(calls[0], "layer Top");
+        assert_eq!(calls[1], "keyboard None");
+        assert!(Edge::ALL.iter().all(|edge| calls.contains(&format!("anchor {edge:?}"))));
+        assert!(calls.contains(&"margin Top 0".to_string()));
+        assert!(calls.contains(&"exclusive zone Pixels(-1)".to_string()));
+
+        let layout = compute(&["--layer", "background"], Quirks::default());
+        assert!(!layout.css.contains("window.dim"));
+        assert_eq!(layout.dim(), None);
+    }
+
+    #[test]
+    fn test_css_pulses_recording_icon() {
+        let layout = compute(&[], Quirks::default());
+        assert!(layout.css.contains("@keyframes pulse"));
+        assert!(layout.css.contains("image.recording {"));
+    }
+
+    #[test]
+    fn test_css_blinks_rec_dot_while_recording() {
+        let layout = compute(&["--rec-dot"], Quirks::default());
+        assert!(layout.css.contains("@keyframes blink"));
+        assert!(layout.css.contains("image.recording ~ label.rec {"));
+
+        let layout = compute(&[], Quirks::default());
+        assert!(!layout.css.contains("label.rec"));
+    }
+
+    #[test]
+    fn test_css_fades_idle_overlay() {
+        let layout = compute(&["--auto-hide", "30"], Quirks::default());
+        assert!(layout.css.contains("window.idle, window.shown.idle {"));
+
+        let layout = compute(&[], Quirks::default());
+        assert!(!layout.css.contains("window.idle"));
+    }
+
+    #[test]
+    fn test_css_state_palette() {
+        let layout = compute(&["--palette", "deuteranopia", "--rec-dot"], Quirks::default());
+        assert!(layout.css.contains("image.recording {\n            color: #e69f00;"));
+        assert!(layout.css.contains("image.paused {\n            color: #56b4e9;"));
+        // The REC dot follows, rather than stay red
+        assert!(!layout.css.contains(REC_COLOR));
+
+        let layout = compute(&[], Quirks::default());
+        assert!(!layout.css.contains("image.paused"));
+    }
+
+    #[test]
+    fn test_css_border_and_shadow() {
+        let layout = compute(&["--border-color", "red"], Quirks::default());
+        assert!(layout.css.contains("border: 2px solid red;"));
+        assert!(layout.css.contains("box-shadow: none;"));
+
+        let layout = compute(&["--border-width", "5", "--shadow"], Quirks::default());
+        assert!(layout.css.contains("border: 5px solid #eeeeee;"));
+        assert!(layout.css.contains("box-shadow: 0 2px 10px alpha(black, 0.6);"));
+        assert!(layout.css.contains("margin: 10px;"));
+    }
+
+    #[test]
+    fn test_a11y_large_enlarges_small_icons_and_spacing() {
+        let layout = compute(&["--icon-size", "24", "--a11y-large"], Quirks::default());
+        assert_eq!(layout.icon_size, 48);
+        assert_eq!((layout.width, layout.height), (88, 88));
+        assert_eq!(layout.spacing, 12);
+        assert!(layout.css.contains("padding: 20px;"));
+        assert!(layout.css.contains("font-size: x-large;"));
+    }
+
+    #[test]
+    fn test_a11y_large_keeps_large_icons() {
+        let layout = compute(&["--icon-size", "96", "--a11y-large"], Quirks::default());
+        assert_eq!(layout.icon_size, 96);
+    }
+
+    #[test]
+    fn test_display() {
+        let layout = compute(&["--position", "top-right", "--icon-size", "48"], Quirks::default());
+        let text = layout.to_string();
+        assert!(text.contains("size:          68x68\n"));
+        assert!(text.contains("anchors:       Top, Right\n"));
+        assert!(text.contains("margins:       Top 20, Bottom 20, Left 20, Right 20\n"));
+
+        let layout = compute(&[], Quirks::default());
+        assert!(layout.to_string().contains("anchors:       none (centered)\n"));
+    }
+
+    #[test]
+    fn test_percent_icon_size_uses_monitor() {
+        let args = Args::try_parse_from(["waystt-wrapper", "--icon-size", "10%"]).unwrap();
+        let config = Config::from(args);
+        assert_eq!(Layout::compute(&config, Quirks::default(), None).icon_size, 108);
+        assert_eq!(
+            Layout::compute(&config, Quirks::default(), Some((3840, 2160))).icon_size,
+            216
+        );
+    }
+
+    #[test]
+    fn test_bar_spans_the_screen() {
+        let layout = compute(&["--appearance", "bar", "--position", "top-left"], Quirks::default());
+        assert!(layout.horizontal);
+        assert!(layout.is_anchored(Edge::Top));
+        assert!(layout.is_anchored(Edge::Left));
+        assert!(layout.is_anchored(Edge::Right));
+        assert!(!layout.is_anchored(Edge::Bottom));
+        assert!(layout.css.contains("border-radius: 0px;"));
+
+        let layout = compute(&["--appearance", "bar"], Quirks::default());
+        assert!(layout.is_anchored(Edge::Bottom));
+        assert!(!layout.is_anchored(Edge::Top));
+    }
+
+    #[test]
+    fn test_padding_and_corner_radius() {
+        let args = ["--icon-size", "48", "--padding", "4", "--corner-radius", "0"];
+        let layout = compute(&args, Quirks::default());
+        assert_eq!((layout.width, layout.height), (56, 56));
+        assert!(layout.css.contains("padding: 4px;"));
+        assert!(layout.css.contains("border-radius: 0px;"));
+
+        let layout = compute(&["--appearance", "bar", "--corner-radius", "6"], Quirks::default());
+        assert!(layout.css.contains("border-radius: 6px;"));
+    }
+
+    #[test]
+    fn test_css_fades_in_and_out() {
+        let layout = compute(&["--animation-duration", "200"], Quirks::default());
+        assert!(layout.css.contains("opacity: 0;"));
+        assert!(layout.css.contains("transition: opacity 200ms ease-in-out;"));
+        assert!(layout.css.contains("window.shown {"));
+
+        let layout = compute(&["--animation-duration", "0"], Quirks::default());
+        assert!(!layout.css.contains("transition"));
+    }
+
+    #[test]
+    fn test_dot_is_a_bare_small_icon() {
+        let layout = compute(&["--appearance", "dot", "--position", "top-right"], Quirks::default());
+        assert_eq!(layout.icon_size, DOT_SIZE);
+        assert_eq!((layout.width, layout.height), (DOT_SIZE, DOT_SIZE));
+        assert!(!layout.horizontal);
+        assert!(layout.is_anchored(Edge::Top) && layout.is_anchored(Edge::Right));
+        assert!(layout.css.contains("background-color: transparent;"));
+        assert!(layout.css.contains("border: none;"));
+        assert!(layout.css.contains("padding: 0px;"));
+    }
+
+    #[test]
+    fn test_floating_is_stacked() {
+        let layout = compute(&[], Quirks::default());
+        assert!(!layout.horizontal);
+        assert!(layout.css.contains("border-radius: 10px;"));
+    }
+
+    #[test]
+    fn test_apply() {
+        let layout = compute(&["--position", "bottom-left", "--margin", "3"], Quirks::default());
+        let target = RecordingTarget::default();
+        layout.apply(&target);
+        let calls = target.calls.into_inner();
+        assert_eq!(calls[0], "layer Overlay");
+        assert_eq!(calls[1], "keyboard Exclusive");
+        assert!(calls.contains(&"anchor Bottom".to_string()));
+        assert!(calls.contains(&"anchor Left".to_string()));
+        assert!(!calls.contains(&"anchor Top".to_string()));
+        assert!(calls.contains(&"margin Right 3".to_string()));
+        assert!(!calls.iter().any(|call| call.starts_with("exclusive zone")));
+    }
+
+    #[test]
+    fn test_apply_exclusive_zone() {
+        let layout = compute(&["--appearance", "bar", "--exclusive-zone", "auto"], Quirks::default());
+        let target = RecordingTarget::default();
+        layout.apply(&target);
+        assert!(target.calls.into_inner().contains(&"exclusive zone Auto".to_string()));
+    }
+}
@@ -0,0 +1,227 @@
+use std::ffi::{c_char, CStr};
+
+use nix::libc;
+
+/// End of the digit groups in `localeconv`'s grouping: no more separators to the left
+const NO_MORE_GROUPS: u8 = libc::c_char::MAX as u8;
+
+/// Copy a string of the C library's locale data, which the next `setlocale` may overwrite
+///
+/// # Safety
+///
+/// `ptr` must be null or point to a NUL-terminated string
+unsafe fn locale_bytes(ptr: *const c_char) -> Vec<u8> {
+    if ptr.is_null() {
+        return Vec::new();
+    }
+    unsafe { CStr::from_ptr(ptr) }.to_bytes().to_vec()
+}
+
+/// The first character of a locale string, if it is one (legacy charsets are not)
+fn first_char(bytes: &[u8]) -> Option<char> {
+    std::str::from_utf8(bytes).ok()?.chars().next()
+}
+
+/// How numbers are written in the user's locale. The C locale (the default) writes them plainly.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumberFormat {
+    decimal: char,
+    /// Thousands separator, if digits are grouped
+    separator: Option<char>,
+    /// Sizes of the digit groups from the right, e.g. 3 for thousands, or 3 then 2 for lakhs
+    groups: Vec<usize>,
+    /// Whether the last group size repeats for the rest of the digits
+    repeat: bool,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimal: '.',
+            separator: None,
+            groups: Vec::new(),
+            repeat: false,
+        }
+    }
+}
+
+impl NumberFormat {
+    /// The format of the locale in `LC_ALL`, `LC_NUMERIC` or `LANG`, as the C library has it
+    /// (GTK already sets the locale from the environment the same way)
+    pub fn from_env() -> Self {
+        // SAFETY: only the main thread changes the locale, and the strings are copied right away
+        unsafe {
+            libc::setlocale(libc::LC_NUMERIC, c"".as_ptr());
+            let conv = &*libc::localeconv();
+            Self::from_conv(
+                &locale_bytes(conv.decimal_point),
+                &locale_bytes(conv.thousands_sep),
+                &locale_bytes(conv.grouping),
+            )
+        }
+    }
+
+    /// The format given by `localeconv`'s `decimal_point`, `thousands_sep` and `grouping`
+    pub fn from_conv(decimal: &[u8], thousands: &[u8], grouping: &[u8]) -> Self {
+        let sizes = grouping.iter().take_while(|&&size| size != NO_MORE_GROUPS);
+        Self {
+            decimal: first_char(decimal).unwrap_or('.'),
+            separator: first_char(thousands),
+            groups: sizes.map(|&size| usize::from(size)).collect(),
+            repeat: !grouping.contains(&NO_MORE_GROUPS),
+        }
+    }
+
+    /// `value` with its digits grouped
+    pub fn integer(&self, value: usize) -> String {
+        self.group(&value.to_string())
+    }
+
+    /// `value` with `precision` decimals
+    pub fn decimal(&self, value: f64, precision: usize) -> String {
+        let plain = format!("{value:.precision$}");
+        let (whole, fraction) = plain.split_once('.').unwrap_or((&plain, ""));
+        let (sign, digits) = match whole.strip_prefix('-') {
+            Some(digits) => ("-", digits),
+            None => ("", whole),
+        };
+        let mut formatted = format!("{sign}{}", self.group(digits));
+        if !fraction.is_empty() {
+            formatted.push(self.decimal);
+            formatted.push_str(fraction);
+        }
+        formatted
+    }
+
+    fn group(&self, digits: &str) -> String {
+        let Some(separator) = self.separator else {
+            return digits.to_string();
+        };
+        // How many digits from the right each separator goes after
+        let mut breaks = Vec::new();
+        let mut width = 0;
+        let mut sizes = self.groups.iter().copied();
+        let mut last = None;
+        while let Some(size) = sizes.next().or(last.filter(|_| self.repeat)) {
+            width += size;
+            if size == 0 || width >= digits.len() {
+                break;
+            }
+            breaks.push(width);
+            last = Some(size);
+        }
+        let mut grouped = String::new();
+        for (i, digit) in digits.chars().enumerate() {
+            if breaks.contains(&(digits.len() - i)) {
+                grouped.push(separator);
+            }
+            grouped.push(digit);
+        }
+        grouped
+    }
+}
+
+/// How the timer separates hours, minutes and seconds in the user's locale: like its clock, e.g.
+/// `1.05` in Finnish. Defaults to `:`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeFormat {
+    pub separator: char,
+}
+
+impl Default for TimeFormat {
+    fn default() -> Self {
+        Self { separator: ':' }
+    }
+}
+
+impl TimeFormat {
+    /// The format of the locale in `LC_ALL`, `LC_TIME` or `LANG`, as the C library has it
+    pub fn from_env() -> Self {
+        // SAFETY: only the main thread changes the locale, and the strings are copied right away
+        let (time, ampm) = unsafe {
+            libc::setlocale(libc::LC_TIME, c"".as_ptr());
+            (
+                locale_bytes(libc::nl_langinfo(libc::T_FMT)),
+                locale_bytes(libc::nl_langinfo(libc::T_FMT_AMPM)),
+            )
+        };
+        let separator = [time, ampm]
+            .iter()
+            .filter_map(|format| std::str::from_utf8(format).ok())
+            .find_map(Self::clock_separator);
+        separator.map_or_else(Self::default, |separator| Self { separator })
+    }
+
+    /// What separates the minutes from the seconds in the `strftime` format of a clock time, if
+    /// it is punctuation rather than a word (as in `%H時%M分`)
+    pub fn clock_separator(format: &str) -> Option<char> {
+        let separator = format.split_once("%M")?.1.chars().next()?;
+        (separator.is_ascii_punctuation() && separator != '%').then_some(separator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `grouping` as glibc has it for most locales
+    const THOUSANDS: &[u8] = &[3, 3];
+
+    #[test]
+    fn test_c_locale_is_plain() {
+        for format in [NumberFormat::default(), NumberFormat::from_conv(b".", b"", b"")] {
+            assert_eq!(format.integer(12345), "12345");
+            assert_eq!(format.decimal(1234.56, 1), "1234.6");
+        }
+    }
+
+    #[test]
+    fn test_english_groups_with_commas() {
+        let format = NumberFormat::from_conv(b".", b",", THOUSANDS);
+        assert_eq!(format.integer(1234567), "1,234,567");
+        assert_eq!(format.integer(214), "214");
+        assert_eq!(format.decimal(12.44, 1), "12.4");
+        assert_eq!(format.decimal(-1234.5, 1), "-1,234.5");
+    }
+
+    #[test]
+    fn test_decimal_comma_locales() {
+        let german = NumberFormat::from_conv(b",", b".", THOUSANDS);
+        assert_eq!(german.decimal(1234.56, 1), "1.234,6");
+        assert_eq!(german.decimal(3.0, 0), "3");
+
+        let french = NumberFormat::from_conv(b",", "\u{202f}".as_bytes(), THOUSANDS);
+        assert_eq!(french.integer(1234), "1\u{202f}234");
+        assert_eq!(french.decimal(12.44, 1), "12,4");
+    }
+
+    #[test]
+    fn test_swiss_apostrophe() {
+        let swiss = NumberFormat::from_conv(b".", "’".as_bytes(), THOUSANDS);
+        assert_eq!(swiss.decimal(1234567.8, 1), "1’234’567.8");
+    }
+
+    #[test]
+    fn test_lakh_grouping() {
+        let indian = NumberFormat::from_conv(b".", b",", &[3, 2]);
+        assert_eq!(indian.integer(12345678), "1,23,45,678");
+        assert_eq!(indian.integer(1234), "1,234");
+    }
+
+    #[test]
+    fn test_grouping_can_stop() {
+        let format = NumberFormat::from_conv(b".", b",", &[3, NO_MORE_GROUPS]);
+        assert_eq!(format.integer(12345678), "12345,678");
+        assert_eq!(NumberFormat::from_conv(b".", b",", &[NO_MORE_GROUPS]).integer(1234), "1234");
+    }
+
+    #[test]
+    fn test_clock_separator() {
+        assert_eq!(TimeFormat::clock_separator("%H:%M:%S"), Some(':'));
+        assert_eq!(TimeFormat::clock_separator("%I:%M:%S %p"), Some(':'));
+        assert_eq!(TimeFormat::clock_separator("%H.%M.%S"), Some('.'));
+        // Only spelled out, or hidden in %T
+        assert_eq!(TimeFormat::clock_separator("%H時%M分%S秒"), None);
+        assert_eq!(TimeFormat::clock_separator("%T"), None);
+    }
+}
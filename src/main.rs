@@ -1,8 +1,29 @@
+mod app;
+mod attach;
+mod audio;
+mod audit;
+mod clipboard;
 mod config;
+mod contrast;
+mod diagnostics;
+mod event;
+mod fake_child;
+mod focus;
+mod hyprland;
+mod ipc;
+mod layout;
+mod locale;
+mod notify;
+mod onboarding;
 mod overlay;
+mod placement;
+mod portal;
 mod process;
+mod quirks;
+mod registry;
 
-use std::cell::{Cell, RefCell};
+use std::cell::RefCell;
+use std::io::Write;
 use std::process::ExitCode;
 use std::rc::Rc;
 use std::time::Duration;
@@ -12,205 +33,455 @@ use gtk4::gdk;
 use gtk4::gio;
 use gtk4::glib;
 use gtk4::prelude::*;
-use gtk4::{Application, ApplicationWindow, EventControllerKey, Image};
+use gtk4::{Application, ApplicationWindow, EventControllerKey};
+use nix::sys::signal::{SigHandler, Signal};
 use tracing::*;
 
-use config::{Args, Config};
+use app::AppModel;
+use event::{AppEvent, Ending, SessionState, Summary};
+use config::{Action, Appearance, Args, Config, Modifier, OutputMode, Preset, Setting};
+use diagnostics::CompositorInfo;
+use layout::Layout;
+use locale::NumberFormat;
 use overlay::create_overlay_window;
-use process::{killall, ChildProcess};
+use process::ChildProcess;
+use quirks::Compositor;
+use registry::Registry;
+
+/// Exit code when the command can't be found, as in shells
+const COMMAND_NOT_FOUND: u8 = 127;
+
+/// How long to wait at exit for the rest of the command's piped output
+const OUTPUT_DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The GDK modifier mask for `modifiers`
+fn modifier_mask(modifiers: &[Modifier]) -> gdk::ModifierType {
+    modifiers
+        .iter()
+        .map(|modifier| match modifier {
+            Modifier::Ctrl => gdk::ModifierType::CONTROL_MASK,
+            Modifier::Alt => gdk::ModifierType::ALT_MASK,
+            Modifier::Shift => gdk::ModifierType::SHIFT_MASK,
+            Modifier::Super => gdk::ModifierType::SUPER_MASK,
+        })
+        .fold(gdk::ModifierType::empty(), |mask, modifier| mask | modifier)
+}
 
-/// Shared state for the application's activate handler
-struct AppState {
-    exit_code: Rc<Cell<i32>>,
-    config: Rc<Config>,
+/// Look up a configured key by name, warning (and disabling it) if GDK doesn't know it
+fn configured_key(name: &str, what: &str) -> Option<gdk::Key> {
+    let key = gdk::Key::from_name(name);
+    if key.is_none() {
+        warn!(key = name, "Unknown {what} key, it is disabled");
+    }
+    key
 }
 
-/// Wait for child process exit and update state accordingly
-fn wait_for_child_exit(
-    child: ChildProcess,
-    exit_code: Rc<Cell<i32>>,
-    window_weak: glib::WeakRef<ApplicationWindow>,
-) {
-    glib::spawn_future_local(async move {
-        let result = gio::spawn_blocking(move || child.wait()).await;
-        let code = match result {
-            Ok(Ok(status)) => {
-                let code = status.code().unwrap_or(1);
-                info!(exit_code = code, "Child process exited");
-                code
-            }
-            Ok(Err(e)) => {
-                error!(error = %e, "Failed waiting for child");
-                1
-            }
-            Err(e) => {
-                error!(error = ?e, "spawn_blocking failed");
-                1
-            }
-        };
-        exit_code.set(code);
+/// Setup keyboard controller for Escape, panic combo, pause and detach key handling
+fn setup_key_controller(window: &ApplicationWindow, model: Rc<AppModel>) {
+    let controller = EventControllerKey::new();
 
-        if let Some(window) = window_weak.upgrade() {
-            window.close();
+    let config = model.config();
+    let pause_key = configured_key(&config.pause_key, "pause");
+    let detach_key = configured_key(&config.detach_key, "detach");
+    let panic_key = if config.no_panic {
+        None
+    } else {
+        configured_key(&config.panic_key, "panic")
+    };
+    let panic_mask = modifier_mask(&config.panic_modifiers);
+
+    controller.connect_key_pressed(move |_, keyval, _, m_state| {
+        model.note_activity();
+        if Some(keyval) == panic_key && m_state.contains(panic_mask) {
+            warn!("Panic combo pressed");
+            model.post(AppEvent::PanicRequested);
+            return glib::Propagation::Stop;
+        }
+        if keyval == gdk::Key::Escape {
+            info!("Escape pressed, initiating shutdown");
+            model.post(AppEvent::StopRequested);
+            return glib::Propagation::Stop;
+        }
+        // Only taken while asking whether to replace the clipboard, so that it still activates
+        // the buttons of a --layout
+        if matches!(keyval, gdk::Key::Return | gdk::Key::KP_Enter)
+            && model.state() == SessionState::ConfirmOverwrite
+        {
+            model.post(AppEvent::OverwriteConfirmed);
+            return glib::Propagation::Stop;
         }
+        if Some(keyval) == pause_key {
+            info!("Pause key pressed");
+            model.post(AppEvent::PauseRequested);
+            return glib::Propagation::Stop;
+        }
+        if Some(keyval) == detach_key {
+            info!("Detach key pressed");
+            model.post(AppEvent::DetachRequested);
+            return glib::Propagation::Stop;
+        }
+
+        glib::Propagation::Proceed
     });
-}
 
-/// Handle graceful shutdown initiated by Escape key
-fn initiate_shutdown(
-    child: ChildProcess,
-    icon: &Image,
-    exit_code: Rc<Cell<i32>>,
-    window_weak: glib::WeakRef<ApplicationWindow>,
-) {
-    if let Err(e) = child.send_sigusr1() {
-        warn!(error = %e, "Failed to send SIGUSR1");
-    }
+    overlay::track(&controller, audit::Kind::Controller);
+    window.add_controller(controller);
+}
 
-    icon.set_icon_name(Some("content-loading-symbolic"));
-    wait_for_child_exit(child, exit_code, window_weak);
+/// Stop on a left click, the same way as Escape, or replace the clipboard when asked to
+fn setup_click_controller(window: &ApplicationWindow, model: Rc<AppModel>) {
+    overlay::connect_click(window, move || {
+        info!("Overlay clicked");
+        model.post(AppEvent::Clicked);
+    });
 }
 
-/// Handle the Escape key press event
-fn handle_escape_press(
-    m_state: gdk::ModifierType,
-    child_cell: &Rc<RefCell<Option<ChildProcess>>>,
-    icon: &Image,
-    exit_code: Rc<Cell<i32>>,
-    window_weak: glib::WeakRef<ApplicationWindow>,
-) {
-    info!("Escape pressed, initiating shutdown");
-
-    let is_panic_combo =
-        m_state.contains(gdk::ModifierType::ALT_MASK | gdk::ModifierType::CONTROL_MASK);
-    if is_panic_combo {
-        warn!("user pressed the panic exit hotkey, closing all windows");
-        if let Err(e) = killall(env!("CARGO_PKG_NAME"), Some("-1")) {
-            error!("error killing other windows, some may still exist: {e}");
-        }
+/// Remember where the floating overlay is dragged to, for the next ones to open there
+fn setup_drag(window: &ApplicationWindow, config: &Config) {
+    if config.appearance == Appearance::Bar {
+        return;
     }
+    overlay::setup_drag(window, |placement| {
+        info!(?placement, "Overlay moved");
+        if let Err(e) = placement::save(&placement) {
+            warn!(error = %e, "Could not save the overlay position");
+        }
+    });
+}
 
-    if let Some(child) = child_cell.borrow_mut().take() {
-        initiate_shutdown(child, icon, exit_code, window_weak);
+/// Offer stop, cancel and restart on a right click, as `overlay.*` actions posting the matching
+/// events
+fn setup_context_menu(window: &ApplicationWindow, model: Rc<AppModel>) {
+    let actions = gio::SimpleActionGroup::new();
+    for (name, event) in [
+        ("stop", AppEvent::StopRequested),
+        ("cancel", AppEvent::CancelRequested),
+        ("restart", AppEvent::RestartRequested),
+    ] {
+        let action = gio::SimpleAction::new(name, None);
+        let model = model.clone();
+        action.connect_activate(move |_, _| {
+            info!(action = name, "Context menu action");
+            model.post(event.clone());
+        });
+        actions.add_action(&action);
     }
+    window.insert_action_group("overlay", Some(&actions));
+    overlay::setup_context_menu(window);
 }
 
-/// Setup keyboard controller for Escape key handling
-fn setup_key_controller(
-    window: &ApplicationWindow,
-    child_cell: Rc<RefCell<Option<ChildProcess>>>,
-    icon: Rc<Image>,
-    exit_code: Rc<Cell<i32>>,
-) {
-    let controller = EventControllerKey::new();
-    let window_weak = window.downgrade();
+/// Stop recording when the wrapper itself receives SIGUSR1, so there is a way to stop that does
+/// not depend on the keyboard grab (e.g. `pkill -USR1 -x waystt-wrapper`). SIGUSR2 detaches the
+/// same way as the detach key.
+fn setup_signal_handler(model: Rc<AppModel>) {
+    glib::unix_signal_add_local(
+        nix::libc::SIGUSR1,
+        glib::clone!(
+            #[weak]
+            model,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                info!("Received SIGUSR1, initiating shutdown");
+                model.post(AppEvent::StopRequested);
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+    glib::unix_signal_add_local(
+        nix::libc::SIGUSR2,
+        glib::clone!(
+            #[weak]
+            model,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                info!("Received SIGUSR2, detaching");
+                model.post(AppEvent::DetachRequested);
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+}
 
-    controller.connect_key_pressed(move |_, keyval, _, m_state| {
-        if keyval != gdk::Key::Escape {
+/// Remove the registry entry on the termination signals (including the default `--panic-signal`),
+/// which would otherwise kill the process before any destructor runs. Installed before the
+/// command is spawned; a `--spawn-first` command dies along with the wrapper.
+fn setup_termination_handler() {
+    for signal in [Signal::SIGHUP, Signal::SIGINT, Signal::SIGTERM] {
+        glib::unix_signal_add_local(signal as i32, move || {
+            info!(?signal, "Received termination signal, exiting");
+            registry::remove_artifacts();
+            // Still die by the signal, so that the exit status reports it as before
+            // SAFETY: no handler is installed, only the default action is restored
+            let _ = unsafe { nix::sys::signal::signal(signal, SigHandler::SigDfl) };
+            let _ = nix::sys::signal::raise(signal);
+            glib::ControlFlow::Break
+        });
+    }
+}
+
+/// Handle window close request (e.g., compositor closes it). The window only actually closes once
+/// the session has finished; until then the request is turned into an event.
+fn setup_close_handler(window: &ApplicationWindow, model: Rc<AppModel>) {
+    window.connect_close_request(move |_| {
+        if model.is_finished() {
+            model.close_mirrors();
+            model.close_dims();
             return glib::Propagation::Proceed;
         }
-
-        handle_escape_press(
-            m_state,
-            &child_cell,
-            &icon,
-            exit_code.clone(),
-            window_weak.clone(),
-        );
+        model.post(AppEvent::WindowClosed);
         glib::Propagation::Stop
     });
+}
 
-    window.add_controller(controller);
+/// Call `sync` with the monitors now and whenever they come and go. The handler stays connected
+/// for as long as the display, so `sync` should only hold the model weakly.
+fn watch_monitors(sync: impl Fn(&[gdk::Monitor]) + 'static) {
+    let Some(display) = gdk::Display::default() else {
+        return;
+    };
+    let sync = move |monitors: &gio::ListModel| {
+        let monitors: Vec<gdk::Monitor> = (0..monitors.n_items())
+            .filter_map(|i| monitors.item(i).and_downcast())
+            .collect();
+        sync(&monitors);
+    };
+    let monitors = display.monitors();
+    sync(&monitors);
+    monitors.connect_items_changed(move |monitors, _, _, _| sync(monitors));
 }
 
-/// Handle window close request (e.g., compositor closes it)
-fn setup_close_handler(
-    window: &ApplicationWindow,
-    child_cell: Rc<RefCell<Option<ChildProcess>>>,
-    exit_code: Rc<Cell<i32>>,
-) {
-    window.connect_close_request(move |_| {
-        if let Some(mut child) = child_cell.borrow_mut().take() {
-            warn!("Window closed, killing child process");
-            if let Err(e) = child.send_sigusr1() {
-                warn!(error = %e, "Failed to send SIGUSR1, force killing");
-                child.force_kill();
-            }
-            exit_code.set(130); // Similar to Ctrl+C
-        }
-        glib::Propagation::Proceed
-    });
+/// With `--all-outputs`, mirror the overlay on every other monitor, as monitors come and go
+fn setup_mirrors(app: &Application, model: Rc<AppModel>) {
+    // Pinning a window to each monitor takes layer shell
+    if !model.config().all_outputs || !gtk4_layer_shell::is_supported() {
+        return;
+    }
+    watch_monitors(glib::clone!(
+        #[weak]
+        model,
+        #[weak]
+        app,
+        move |monitors: &[gdk::Monitor]| model.sync_mirrors(&app, monitors)
+    ));
 }
 
-/// Handle unexpected child exit during monitoring
-fn handle_unexpected_exit(
-    status: std::process::ExitStatus,
-    exit_code: &Rc<Cell<i32>>,
-    window_weak: &glib::WeakRef<ApplicationWindow>,
-) {
-    let code = status.code().unwrap_or(1);
-    warn!(exit_code = code, "Child process exited unexpectedly");
-    exit_code.set(code);
-    if let Some(window) = window_weak.upgrade() {
-        window.close();
+/// With `--dim-screen`, dim every monitor behind the overlay while recording
+fn setup_dimming(app: &Application, model: Rc<AppModel>) {
+    if !model.config().dim_screen || !gtk4_layer_shell::is_supported() {
+        return;
     }
+    watch_monitors(glib::clone!(
+        #[weak]
+        model,
+        #[weak]
+        app,
+        move |monitors: &[gdk::Monitor]| model.sync_dims(&app, monitors)
+    ));
 }
 
 /// Monitor child process for unexpected exit
-fn setup_child_monitor(
-    window: &ApplicationWindow,
-    child_cell: Rc<RefCell<Option<ChildProcess>>>,
-    exit_code: Rc<Cell<i32>>,
-) {
-    let window_weak = window.downgrade();
-
-    glib::timeout_add_local(Duration::from_millis(100), move || {
-        let mut child_ref = child_cell.borrow_mut();
-        let Some(ref mut child) = *child_ref else {
-            return glib::ControlFlow::Break;
-        };
+fn setup_child_monitor(model: Rc<AppModel>) {
+    let poll = glib::clone!(
+        #[weak]
+        model,
+        #[upgrade_or]
+        glib::ControlFlow::Break,
+        move || model.poll_child()
+    );
+    model.guard(glib::timeout_add_local(Duration::from_millis(100), poll));
+}
 
-        match child.try_wait() {
-            Ok(Some(status)) => {
-                handle_unexpected_exit(status, &exit_code, &window_weak);
-                glib::ControlFlow::Break
-            }
-            _ => glib::ControlFlow::Continue,
+/// Stop recording automatically once `--max-duration` has passed
+fn setup_max_duration_timer(model: Rc<AppModel>) {
+    let Some(max_duration) = model.config().max_duration else {
+        return;
+    };
+    let elapsed = glib::clone!(
+        #[weak]
+        model,
+        move || {
+            info!(max_duration, "Maximum recording duration reached, initiating shutdown");
+            model.post(AppEvent::MaxDurationElapsed);
         }
-    });
+    );
+    model.guard(glib::timeout_add_local_once(Duration::from_secs(max_duration), elapsed));
 }
 
-/// GTK application activate handler
-fn on_activate(app: &Application, state: &AppState) {
-    let child = match ChildProcess::spawn(&state.config.command) {
-        Ok(c) => c,
-        Err(e) => {
-            error!(error = %e, "Failed to spawn child process");
-            state.exit_code.set(1);
-            return;
+/// GTK application activate handler, with the child already running with `--spawn-first`
+fn on_activate(app: &Application, model: &Rc<AppModel>, early_child: Option<ChildProcess>) {
+    if enabled!(Level::DEBUG) {
+        debug!(compositor = ?CompositorInfo::collect(), "Compositor capabilities");
+    }
+
+    let config = model.config();
+    let (child, capture_warning) = match early_child {
+        Some(child) => (child, None),
+        None => {
+            // Before spawning, so that the command's own capture stream isn't counted
+            let capture_warning = audio::capture_warning(&audio::other_capture_apps());
+            match model.spawn_child() {
+                Ok(child) => (child, capture_warning),
+                Err(e) => {
+                    error!(error = %e, "Failed to spawn child process");
+                    model.abort(1);
+                    return;
+                }
+            }
         }
     };
 
-    let (window, icon) = match create_overlay_window(app, &state.config) {
+    let overlay = match create_overlay_window(app, model.config()) {
         Ok(w) => w,
         Err(e) => {
             error!(error = %e, "Failed to create overlay window");
-            state.exit_code.set(1);
+            model.abort(1);
             return;
         }
     };
 
-    let icon = Rc::new(icon);
-    let child_cell: Rc<RefCell<Option<ChildProcess>>> = Rc::new(RefCell::new(Some(child)));
+    model.attach(child, &overlay);
+    if config.preview {
+        model.show_preview(overlay.preview.clone());
+    }
+
+    let window = &overlay.window;
+    setup_key_controller(window, model.clone());
+    setup_click_controller(window, model.clone());
+    setup_drag(window, config);
+    setup_context_menu(window, model.clone());
+    setup_signal_handler(model.clone());
+    setup_close_handler(window, model.clone());
+    setup_child_monitor(model.clone());
+    setup_max_duration_timer(model.clone());
+    setup_mirrors(app, model.clone());
+    setup_dimming(app, model.clone());
+
+    if let Some(warning) = capture_warning {
+        warn!("{warning}");
+        overlay.warning.set_text(&warning);
+        overlay.warning.set_visible(true);
+    }
 
-    setup_key_controller(&window, child_cell.clone(), icon.clone(), state.exit_code.clone());
-    setup_close_handler(&window, child_cell.clone(), state.exit_code.clone());
-    setup_child_monitor(&window, child_cell, state.exit_code.clone());
+    if onboarding::is_first_run() {
+        overlay.show_onboarding(&onboarding::text(model.config()));
+    }
 
     window.present();
+    overlay.setup_keyboard_fallback();
     info!("Overlay window presented, waiting for Escape key");
 }
 
+/// Print what would be spawned and requested from the compositor, without doing either
+fn dry_run_report(config: &Config) -> ExitCode {
+    let compositor = Compositor::detect();
+    // The compositor picks the output at runtime, so percentage sizes use a nominal monitor
+    let layout = Layout::compute(config, compositor.quirks(), None);
+
+    println!("command:       {:?}", config.command);
+    println!("compositor:    {compositor:?}");
+    println!("output:        {}", config.output.as_deref().unwrap_or("compositor default"));
+    print!("{layout}");
+    ExitCode::SUCCESS
+}
+
+/// Print the generated stylesheet followed by the user stylesheet, in the order GTK applies them
+fn css_report(config: &Config) -> ExitCode {
+    let layout = Layout::compute(config, Compositor::detect().quirks(), None);
+    println!("/* built-in */\n{}", layout.css);
+    if let Some(path) = config.user_css() {
+        match std::fs::read_to_string(&path) {
+            Ok(css) => println!("\n/* {} */\n{css}", path.display()),
+            Err(e) => {
+                error!(path = %path.display(), error = %e, "Failed to read user stylesheet");
+                return ExitCode::FAILURE;
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Send `settings` to the overlay of `pid`, or else to every overlay on this display
+fn set_settings(pid: Option<u32>, settings: &[Setting]) -> ExitCode {
+    let registry = match Registry::open() {
+        Ok(registry) => registry,
+        Err(e) => {
+            error!(error = %e, "Failed to open the instance registry");
+            return ExitCode::FAILURE;
+        }
+    };
+    let targets: Vec<u32> = match pid {
+        Some(pid) => vec![pid],
+        None => registry.peers().iter().map(|instance| instance.pid).collect(),
+    };
+    if targets.is_empty() {
+        error!("No running overlay to change");
+        return ExitCode::FAILURE;
+    }
+
+    let mut failed = false;
+    for pid in targets {
+        match ipc::send(&registry.socket_path(pid), settings) {
+            Ok(replies) => {
+                for reply in replies.iter().filter(|reply| *reply != "ok") {
+                    error!(pid, "{reply}");
+                    failed = true;
+                }
+            }
+            Err(e) => {
+                error!(pid, error = %e, "Failed to reach the overlay");
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Run an auxiliary subcommand instead of the overlay
+fn run_action(action: Action, args: Args) -> ExitCode {
+    match action {
+        Action::Doctor => {
+            if let Err(e) = gtk4::init() {
+                error!(error = %e, "Failed to initialize GTK");
+                return ExitCode::FAILURE;
+            }
+            print!("{}", CompositorInfo::collect());
+            ExitCode::SUCCESS
+        }
+        Action::Completions { shell } => {
+            config::write_completions(shell, &mut std::io::stdout());
+            ExitCode::SUCCESS
+        }
+        Action::Status => {
+            let registry = match Registry::open() {
+                Ok(registry) => registry,
+                Err(e) => {
+                    error!(error = %e, "Failed to open the instance registry");
+                    return ExitCode::FAILURE;
+                }
+            };
+            for instance in registry.instances() {
+                println!(
+                    "{}\t{}\t{}",
+                    instance.pid,
+                    instance.state,
+                    instance.session.as_deref().unwrap_or("-")
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Action::FakeChild(args) => fake_child::run(args),
+        Action::Set { pid, settings } => set_settings(pid, &settings),
+        // The overlay options still apply to the overlay it shows
+        Action::Attach { pid } => attach::run(Config::from(args), pid),
+    }
+}
+
 fn main() -> ExitCode {
     tracing_subscriber::fmt()
         .with_env_filter(
@@ -219,27 +490,158 @@ fn main() -> ExitCode {
         )
         .init();
 
-    let args = Args::parse();
-    let config = Config::from(args);
+    let mut args = Args::parse();
+    if args.audit_lifecycle {
+        audit::enable();
+    }
+    if let Some(action) = args.action.take() {
+        return run_action(action, args);
+    }
+
+    let print_config = args.print_config;
+    let dry_run = args.dry_run;
+    let dump_css = args.dump_css;
+    let reset_position = args.reset_position;
+    let mut config = Config::from(args);
+    portal::follow_color_scheme(&mut config);
+    contrast::report(&config);
+
+    if let Some(format) = print_config {
+        return match config.render(format) {
+            Ok(rendered) => {
+                print!("{rendered}");
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                error!(error = %e, "Failed to render config");
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    if dump_css {
+        return css_report(&config);
+    }
+
+    if dry_run {
+        return dry_run_report(&config);
+    }
+
+    // Catch a missing waystt or clipboard tool up front, rather than as a cryptic child failure
+    if let Some(preset) = config.preset {
+        if let Err(e) = process::require_programs(&config.required_programs()) {
+            error!(
+                error = %e,
+                ?preset,
+                "The command preset can't run; install the missing program, choose another \
+                 --preset (e.g. `--preset stdout` to skip the clipboard) or --translate-command, \
+                 or pass a command after --"
+            );
+            return ExitCode::from(COMMAND_NOT_FOUND);
+        }
+    }
+
+    if reset_position {
+        if let Err(e) = placement::forget() {
+            warn!(error = %e, "Could not forget the overlay position");
+        }
+    }
+
+    // A keybind that fires twice would otherwise start a second recording right away
+    let debounce = Duration::from_millis(config.debounce);
+    if let Ok(registry) = Registry::open() {
+        if registry::is_bounce(&registry.peers(), debounce) {
+            info!("Another overlay just started, ignoring this launch as a repeated keybind");
+            return ExitCode::SUCCESS;
+        }
+    }
 
     info!("Starting waystt-wrapper");
+    overlay::apply_render_env(&config);
 
     let app = Application::builder()
         .application_id("com.github.mcoffin.waystt-wrapper")
         .build();
+    overlay::init_adwaita(&app);
+
+    let model = Rc::new(AppModel::new(config));
+    model.register();
+    model.watch_color_scheme();
+    registry::install_panic_hook();
+    // Before anything is spawned, so that no termination signal finds the command unwatched
+    setup_termination_handler();
+
+    // Recording starts while GTK is still coming up
+    let early_child = if model.config().spawn_first {
+        match model.spawn_child() {
+            Ok(child) => Some(child),
+            Err(e) => {
+                error!(error = %e, "Failed to spawn child process");
+                return ExitCode::FAILURE;
+            }
+        }
+    } else {
+        None
+    };
+    let early_child = Rc::new(RefCell::new(early_child));
 
-    let state = AppState {
-        exit_code: Rc::new(Cell::new(0)),
-        config: Rc::new(config),
+    app.connect_activate(glib::clone!(
+        #[strong]
+        model,
+        #[strong]
+        early_child,
+        move |app| on_activate(app, &model, early_child.take())
+    ));
+
+    let _status = app.run_with_args::<&str>(&[]);
+
+    // Activation never took the command over, so nothing else will stop it
+    if let Some(mut child) = early_child.take() {
+        error!("The overlay never came up, stopping the command");
+        child.force_kill();
+        let _ = child.wait();
+        model.abort(1);
+    }
+
+    let code = if model.ending() == Ending::Detached {
+        model.wait_detached()
+    } else {
+        model.exit_code()
     };
 
-    let exit_code = state.exit_code.clone();
+    let mut transcription_chars = None;
+    if let Some(output) = model.take_output() {
+        let captured = output.finish(OUTPUT_DRAIN_TIMEOUT);
+        if model.config().child_stdout == OutputMode::Capture {
+            let transcription = String::from_utf8_lossy(&captured.stdout);
+            transcription_chars = Some(transcription.trim().chars().count());
+        }
+        let _ = std::io::stdout().write_all(&captured.stdout);
+        let _ = std::io::stderr().write_all(&captured.stderr);
+    }
 
-    app.connect_activate(move |app| on_activate(app, &state));
+    // The registration and control socket would otherwise only go with the model, which handlers
+    // GTK still holds can outlive
+    registry::remove_artifacts();
+
+    let summary = Summary {
+        ending: model.ending(),
+        elapsed: model.elapsed(),
+        transcription_chars,
+        destination: model.config().preset.map(Preset::destination),
+        exit_code: code,
+        numbers: NumberFormat::from_env(),
+    };
+    eprintln!("{summary}");
 
-    let _status = app.run_with_args::<&str>(&[]);
+    // Never clears what the user copied themselves while nothing was transcribed or copied
+    if let (true, Some(ttl)) = (model.copied(code), model.config().clipboard_ttl) {
+        if let Err(e) = clipboard::schedule_clear(Duration::from_secs(ttl)) {
+            warn!(error = %e, "Failed to schedule clipboard clear");
+        }
+    }
 
-    let code = exit_code.get();
+    audit::report("exit");
     info!(exit_code = code, "waystt-wrapper exiting");
 
     ExitCode::from(code as u8)
@@ -250,26 +652,14 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_app_state_creation() {
-        let config = Config {
-            icon: "test-icon".to_string(),
-            icon_size: 64,
-            position: config::Position::Center,
-            margin: 10,
-            command: vec!["echo".to_string()],
-        };
-
-        let state = AppState {
-            exit_code: Rc::new(Cell::new(0)),
-            config: Rc::new(config),
-        };
-
-        assert_eq!(state.exit_code.get(), 0);
-        assert_eq!(state.config.icon, "test-icon");
+    fn test_modifier_mask() {
+        assert_eq!(
+            modifier_mask(&[Modifier::Ctrl, Modifier::Alt]),
+            gdk::ModifierType::ALT_MASK | gdk::ModifierType::CONTROL_MASK
+        );
+        assert_eq!(modifier_mask(&[]), gdk::ModifierType::empty());
     }
 
-
-
     #[test]
     fn test_panic_combo_detection() {
         // Test that Ctrl+Alt is detected correctly
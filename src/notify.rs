@@ -0,0 +1,368 @@
+use std::cell::Cell;
+use std::process::{Command, Stdio};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use tracing::{debug, warn};
+
+use crate::config::{NotifyBackend, Preset};
+use crate::event::SessionState;
+use crate::portal;
+
+/// Application name notifications are sent under, and the tag that lets mako and swaync replace
+/// one notice of this wrapper with the next
+const APP_NAME: &str = "waystt-wrapper";
+
+const FDO_NAME: &str = "org.freedesktop.Notifications";
+const FDO_PATH: &str = "/org/freedesktop/Notifications";
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.Notification";
+
+/// Hint of mako, swaync and dunst for notices that replace each other
+const SYNCHRONOUS_HINT: &str = "x-canonical-private-synchronous";
+
+const RECORDING_ICON: &str = "media-record-symbolic";
+const PAUSED_ICON: &str = "media-playback-pause-symbolic";
+const PROCESSING_ICON: &str = "content-loading-symbolic";
+const DONE_ICON: &str = "audio-input-microphone-symbolic";
+const EMPTY_ICON: &str = "microphone-sensitivity-muted-symbolic";
+const ERROR_ICON: &str = "dialog-error-symbolic";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Urgency {
+    Low,
+    Normal,
+    Critical,
+}
+
+impl Urgency {
+    /// The `urgency` hint of the notification spec
+    fn level(self) -> u8 {
+        match self {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "critical",
+        }
+    }
+
+    /// The portal's `priority`
+    fn priority(self) -> &'static str {
+        match self {
+            Urgency::Low => "low",
+            Urgency::Normal => "normal",
+            Urgency::Critical => "urgent",
+        }
+    }
+}
+
+/// A desktop notification
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notice {
+    pub summary: String,
+    pub body: String,
+    pub icon: &'static str,
+    pub urgency: Urgency,
+}
+
+/// Shows notices on the desktop, each one replacing the last one it showed. Failures are only
+/// logged: a missing notification daemon never gets in the way of the transcription.
+pub trait Notifier {
+    fn show(&self, notice: &Notice);
+    /// Take the last notice down, if it is still shown
+    fn withdraw(&self);
+}
+
+/// The notifier for `backend`
+pub fn notifier(backend: NotifyBackend) -> Box<dyn Notifier> {
+    match backend {
+        NotifyBackend::Portal => Box::new(Portal),
+        NotifyBackend::NotifySend => Box::new(NotifySend::default()),
+        NotifyBackend::Dbus => Box::new(Freedesktop::default()),
+    }
+}
+
+/// The notice for how the session ended, or `None` if it was cancelled or closed: `state` and
+/// `exit_code` as it ended, `transcript` as previewed and `error` as shown on the overlay
+pub fn completion(
+    state: SessionState,
+    exit_code: i32,
+    preset: Option<Preset>,
+    transcript: &str,
+    error: &str,
+) -> Option<Notice> {
+    let (summary, body, icon, urgency) = match state {
+        SessionState::Empty => ("Nothing transcribed", "", EMPTY_ICON, Urgency::Low),
+        SessionState::Error => ("Transcription failed", error, ERROR_ICON, Urgency::Critical),
+        _ if exit_code == 0 => (done_summary(preset), transcript, DONE_ICON, Urgency::Normal),
+        _ => return None,
+    };
+    Some(Notice {
+        summary: summary.to_string(),
+        body: body.to_string(),
+        icon,
+        urgency,
+    })
+}
+
+/// The notice for the session being in `state` with `--notify-progress`, or `None` for the
+/// states [`completion`] covers
+pub fn progress(state: SessionState) -> Option<Notice> {
+    let (summary, body, icon) = match state {
+        SessionState::Recording => ("Recording…", "Escape on the overlay stops", RECORDING_ICON),
+        SessionState::Paused => ("Recording paused", "", PAUSED_ICON),
+        SessionState::Processing => ("Transcribing…", "", PROCESSING_ICON),
+        SessionState::ConfirmOverwrite => {
+            ("Replace the clipboard?", "Enter on the overlay or a click replaces it", DONE_ICON)
+        }
+        SessionState::Error | SessionState::Empty | SessionState::PanicWarning => return None,
+    };
+    Some(Notice {
+        summary: summary.to_string(),
+        body: body.to_string(),
+        icon,
+        urgency: Urgency::Low,
+    })
+}
+
+fn done_summary(preset: Option<Preset>) -> &'static str {
+    match preset {
+        Some(Preset::Copy) => "Transcription copied to the clipboard",
+        Some(Preset::Type) => "Transcription typed into the focused window",
+        Some(Preset::Stdout) | None => "Transcription done",
+    }
+}
+
+/// The XDG desktop portal, which replaces notices by their id
+struct Portal;
+
+impl Notifier for Portal {
+    fn show(&self, notice: &Notice) {
+        let Some(bus) = portal::session_bus() else {
+            return;
+        };
+        let notification = glib::VariantDict::new(None);
+        notification.insert("title", &notice.summary);
+        notification.insert("body", &notice.body);
+        notification.insert("priority", notice.urgency.priority());
+        if let Some(icon) = gio::ThemedIcon::new(notice.icon).serialize() {
+            notification.insert_value("icon", &icon);
+        }
+        let parameters =
+            glib::Variant::tuple_from_iter([APP_NAME.to_variant(), notification.end()]);
+        bus.call(
+            Some(portal::PORTAL_NAME),
+            portal::PORTAL_PATH,
+            PORTAL_INTERFACE,
+            "AddNotification",
+            Some(&parameters),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            |reply| {
+                if let Err(e) = reply {
+                    warn!(error = %e, "Failed to send a notification through the portal");
+                }
+            },
+        );
+    }
+
+    fn withdraw(&self) {
+        let Some(bus) = portal::session_bus() else {
+            return;
+        };
+        bus.call(
+            Some(portal::PORTAL_NAME),
+            portal::PORTAL_PATH,
+            PORTAL_INTERFACE,
+            "RemoveNotification",
+            Some(&(APP_NAME,).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            |reply| {
+                if let Err(e) = reply {
+                    debug!(error = %e, "Failed to withdraw the notification");
+                }
+            },
+        );
+    }
+}
+
+/// `notify-send`, which prints the id of each notice to replace it with the next
+#[derive(Default)]
+struct NotifySend {
+    id: Arc<Mutex<Option<u32>>>,
+}
+
+impl Notifier for NotifySend {
+    fn show(&self, notice: &Notice) {
+        let args = notify_send_args(notice, *self.id.lock().unwrap());
+        let id = self.id.clone();
+        thread::spawn(move || {
+            match Command::new("notify-send").args(&args).stderr(Stdio::null()).output() {
+                Ok(output) => {
+                    let printed = String::from_utf8_lossy(&output.stdout);
+                    if let Ok(printed) = printed.trim().parse() {
+                        *id.lock().unwrap() = Some(printed);
+                    }
+                }
+                Err(e) => warn!(error = %e, "Failed to run notify-send"),
+            }
+        });
+    }
+
+    /// notify-send can't close a notice, so it is replaced by one that expires right away
+    fn withdraw(&self) {
+        let Some(id) = *self.id.lock().unwrap() else {
+            return;
+        };
+        let args = [format!("--replace-id={id}"), "--expire-time=1".to_string(), APP_NAME.into()];
+        thread::spawn(move || {
+            let _ = Command::new("notify-send").args(&args).stderr(Stdio::null()).status();
+        });
+    }
+}
+
+fn notify_send_args(notice: &Notice, replaces: Option<u32>) -> Vec<String> {
+    let mut args = vec![
+        format!("--app-name={APP_NAME}"),
+        format!("--urgency={}", notice.urgency.name()),
+        format!("--icon={}", notice.icon),
+        format!("--hint=string:{SYNCHRONOUS_HINT}:{APP_NAME}"),
+        "--print-id".to_string(),
+    ];
+    args.extend(replaces.map(|id| format!("--replace-id={id}")));
+    args.extend(["--".to_string(), notice.summary.clone(), notice.body.clone()]);
+    args
+}
+
+/// The notification daemon itself, replacing notices by the id it returned for the last one
+#[derive(Default)]
+struct Freedesktop {
+    id: Rc<Cell<u32>>,
+}
+
+impl Notifier for Freedesktop {
+    fn show(&self, notice: &Notice) {
+        let Some(bus) = portal::session_bus() else {
+            return;
+        };
+        let hints = glib::VariantDict::new(None);
+        hints.insert("urgency", notice.urgency.level());
+        hints.insert(SYNCHRONOUS_HINT, APP_NAME);
+        let parameters = glib::Variant::tuple_from_iter([
+            APP_NAME.to_variant(),
+            self.id.get().to_variant(),
+            notice.icon.to_variant(),
+            notice.summary.to_variant(),
+            notice.body.to_variant(),
+            Vec::<String>::new().to_variant(),
+            hints.end(),
+            (-1i32).to_variant(),
+        ]);
+        let id = self.id.clone();
+        bus.call(
+            Some(FDO_NAME),
+            FDO_PATH,
+            FDO_NAME,
+            "Notify",
+            Some(&parameters),
+            Some(glib::VariantTy::new("(u)").expect("valid variant type")),
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            move |reply| match reply.map(|reply| reply.get::<(u32,)>()) {
+                Ok(Some((replaced,))) => {
+                    debug!(id = replaced, "Notification shown");
+                    id.set(replaced);
+                }
+                Ok(None) => {}
+                Err(e) => warn!(error = %e, "Failed to send a notification"),
+            },
+        );
+    }
+
+    fn withdraw(&self) {
+        let id = self.id.replace(0);
+        let Some(bus) = portal::session_bus().filter(|_| id != 0) else {
+            return;
+        };
+        bus.call(
+            Some(FDO_NAME),
+            FDO_PATH,
+            FDO_NAME,
+            "CloseNotification",
+            Some(&(id,).to_variant()),
+            None,
+            gio::DBusCallFlags::NONE,
+            -1,
+            gio::Cancellable::NONE,
+            |reply| {
+                if let Err(e) = reply {
+                    debug!(error = %e, "Failed to withdraw the notification");
+                }
+            },
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_notices() {
+        let copied = completion(SessionState::Processing, 0, Some(Preset::Copy), "hi", "").unwrap();
+        assert_eq!(copied.summary, "Transcription copied to the clipboard");
+        assert_eq!(copied.body, "hi");
+
+        let failed = completion(SessionState::Error, 1, None, "", "waystt exited").unwrap();
+        assert_eq!((failed.body.as_str(), failed.urgency), ("waystt exited", Urgency::Critical));
+
+        let empty = completion(SessionState::Empty, 0, Some(Preset::Copy), "", "").unwrap();
+        assert_eq!(empty.urgency, Urgency::Low);
+
+        // Cancelled
+        assert_eq!(completion(SessionState::Processing, 130, None, "", ""), None);
+    }
+
+    #[test]
+    fn test_progress_notices_leave_the_end_to_completion() {
+        let recording = progress(SessionState::Recording).unwrap();
+        assert_eq!((recording.summary.as_str(), recording.urgency), ("Recording…", Urgency::Low));
+        assert!(progress(SessionState::Processing).is_some());
+        assert_eq!(progress(SessionState::Error), None);
+        assert_eq!(progress(SessionState::Empty), None);
+    }
+
+    #[test]
+    fn test_notify_send_replaces_the_last_notice() {
+        let notice = Notice {
+            summary: "-summary".to_string(),
+            body: "body".to_string(),
+            icon: DONE_ICON,
+            urgency: Urgency::Critical,
+        };
+        let args = notify_send_args(&notice, None);
+        assert!(args.contains(&"--urgency=critical".to_string()));
+        assert!(!args.iter().any(|arg| arg.starts_with("--replace-id")));
+        // Text that looks like an option stays text
+        assert_eq!(args[args.len() - 3..], ["--", "-summary", "body"]);
+
+        let args = notify_send_args(&notice, Some(7));
+        assert!(args.contains(&"--replace-id=7".to_string()));
+    }
+}
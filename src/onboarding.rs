@@ -0,0 +1,94 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::{Config, Modifier};
+
+/// Per-user state directory: `$XDG_STATE_HOME/waystt-wrapper`, or `~/.local/state/waystt-wrapper`
+pub fn state_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+/// Marker recording that the onboarding panel was dismissed
+fn marker() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("onboarded"))
+}
+
+/// Whether the onboarding panel should be shown. Without a state directory the dismissal couldn't
+/// be remembered, so it is never shown.
+pub fn is_first_run() -> bool {
+    marker().is_some_and(|marker| !marker.exists())
+}
+
+/// Never show the onboarding panel again
+pub fn mark_done() -> io::Result<()> {
+    let Some(marker) = marker() else {
+        return Ok(());
+    };
+    if let Some(dir) = marker.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(marker, "")
+}
+
+fn modifier_name(modifier: Modifier) -> &'static str {
+    match modifier {
+        Modifier::Ctrl => "Ctrl",
+        Modifier::Alt => "Alt",
+        Modifier::Shift => "Shift",
+        Modifier::Super => "Super",
+    }
+}
+
+/// The onboarding panel text, listing the keys as configured
+pub fn text(config: &Config) -> String {
+    let mut lines = vec![
+        "Escape or click: stop recording".to_string(),
+        format!("{}: pause / resume", config.pause_key),
+        format!("{}: hide and finish in the background", config.detach_key),
+    ];
+    if !config.no_panic {
+        let combo: Vec<&str> = config
+            .panic_modifiers
+            .iter()
+            .map(|modifier| modifier_name(*modifier))
+            .chain([config.panic_key.as_str()])
+            .collect();
+        lines.push(format!("{}: close every overlay", combo.join("+")));
+    }
+    lines.push(String::new());
+    lines.push("Press any key to hide this for good".to_string());
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use clap::Parser;
+
+    use crate::config::Args;
+
+    fn config(args: &[&str]) -> Config {
+        Config::from(Args::try_parse_from(["waystt-wrapper"].iter().chain(args)).unwrap())
+    }
+
+    #[test]
+    fn test_text_lists_configured_keys() {
+        let text = text(&config(&["--pause-key", "p", "--panic-modifiers", "super"]));
+        assert!(text.contains("Escape or click: stop recording"));
+        assert!(text.contains("p: pause / resume"));
+        assert!(text.contains("d: hide and finish in the background"));
+        assert!(text.contains("Super+Escape: close every overlay"));
+    }
+
+    #[test]
+    fn test_text_omits_disabled_panic_combo() {
+        let text = text(&config(&["--no-panic"]));
+        assert!(!text.contains("close every overlay"));
+    }
+}
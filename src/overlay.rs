@@ -1,102 +1,886 @@
-use gtk4::gdk::Display;
+use std::cell::Cell;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::Duration;
+
+use clap::ValueEnum;
+use gtk4::gdk::{self, Display};
+use gtk4::gio;
+use gtk4::glib;
 use gtk4::prelude::*;
-use gtk4::{Application, ApplicationWindow, CssProvider, Image};
+use gtk4::{
+    Application, ApplicationWindow, CssProvider, EventControllerKey, GestureClick, GestureDrag,
+    Image, Label, LevelBar, Orientation, PopoverMenu, Spinner,
+};
 use gtk4_layer_shell::{Edge, KeyboardMode, Layer, LayerShell};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::config::{Config, Position};
+use crate::audit;
+use crate::config::{self, Config};
+use crate::event::SessionState;
+use crate::focus;
+use crate::hyprland;
+use crate::layout::{self, Layout, LayoutTarget, Rect};
+use crate::onboarding;
+use crate::placement::{self, Placement};
+use crate::quirks::{Compositor, Quirks};
 
 /// Error type for overlay window creation
 #[derive(Debug, thiserror::Error)]
 pub enum OverlayError {
-    #[error("layer shell not supported on this compositor")]
-    LayerShellNotSupported,
     #[error("could not get default display")]
     NoDefaultDisplay,
 }
 
 pub type Result<T> = std::result::Result<T, OverlayError>;
 
-pub fn create_overlay_window(app: &Application, config: &Config) -> Result<(ApplicationWindow, Image)> {
-    // Check layer shell support
-    if !gtk4_layer_shell::is_supported() {
-        return Err(OverlayError::LayerShellNotSupported);
+/// How long to wait for the compositor to give the overlay keyboard focus before assuming the
+/// exclusive keyboard grab was rejected
+const KEYBOARD_FOCUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Hint shown when the overlay could not grab the keyboard
+const KEYBOARD_FALLBACK_HINT: &str =
+    "Click here to stop\nor run: pkill -USR1 -x waystt-wrapper";
+
+/// Widgets making up the overlay
+pub struct Overlay {
+    pub window: ApplicationWindow,
+    pub icon: Image,
+    /// Takes the icon's place while processing, unless `--icon-processing` is set
+    pub spinner: Spinner,
+    /// Elapsed recording time (`--show-timer`) and/or countdown to `--max-duration` under the icon
+    pub timer: Label,
+    /// Microphone input level under the icon (`--level-meter` only)
+    pub level: LevelBar,
+    pub hint: Label,
+    /// The configured stop, pause and detach keys (`--show-hints` only)
+    pub keys: Label,
+    /// The transcription, once the command prints it (`--preview` only)
+    pub preview: Label,
+    /// Why the command failed, in the error state
+    pub error: Label,
+    /// Names other applications capturing audio, if any
+    pub warning: Label,
+    /// Warns about sustained silence or clipping (`--level-meter` only)
+    pub gain: Label,
+    pub onboarding: Label,
+    /// The `--label` caption
+    pub caption: Label,
+    /// Describes the session state next to the icon (bar appearance only)
+    pub status: Label,
+}
+
+impl Overlay {
+    /// Fall back to on-demand keyboard interactivity (and show a hint about the other stop paths)
+    /// if the compositor never gives the overlay keyboard focus, rather than leaving it
+    /// un-dismissable
+    pub fn setup_keyboard_fallback(&self) {
+        if !self.window.is_layer_window() || self.window.keyboard_mode() != KeyboardMode::Exclusive
+        {
+            return;
+        }
+
+        let hint = self.hint.clone();
+        self.window.connect_is_active_notify(move |window| {
+            if window.is_active() {
+                hint.set_visible(false);
+            }
+        });
+
+        let window_weak = self.window.downgrade();
+        let hint = self.hint.clone();
+        let keys = self.keys.clone();
+        glib::timeout_add_local_once(KEYBOARD_FOCUS_TIMEOUT, move || {
+            let Some(window) = window_weak.upgrade() else {
+                return;
+            };
+            if window.is_active() {
+                return;
+            }
+
+            warn!("Compositor did not grant keyboard focus, falling back to on-demand keyboard mode");
+            window.set_keyboard_mode(KeyboardMode::OnDemand);
+            hint.set_visible(true);
+            // Without keyboard focus the keys do nothing, so leave it to the hint
+            keys.set_visible(false);
+        });
     }
+}
 
-    info!("Creating overlay window");
+impl Overlay {
+    /// Show `text` until the first key press, then never again
+    pub fn show_onboarding(&self, text: &str) {
+        self.onboarding.set_text(text);
+        self.onboarding.set_visible(true);
 
-    let window = ApplicationWindow::builder()
-        .application(app)
-        .default_width(config.icon_size + 20)
-        .default_height(config.icon_size + 20)
-        .build();
+        // Without keyboard input it could never be dismissed, so only show it this once
+        if self.window.keyboard_mode() == KeyboardMode::None {
+            mark_onboarding_done();
+            return;
+        }
 
-    // Initialize layer shell BEFORE the window is realized
-    window.init_layer_shell();
+        let controller = EventControllerKey::new();
+        let onboarding = self.onboarding.clone();
+        controller.connect_key_pressed(move |_, _, _, _| {
+            if onboarding.is_visible() {
+                onboarding.set_visible(false);
+                mark_onboarding_done();
+            }
+            // Let the key do whatever it normally does as well
+            glib::Propagation::Proceed
+        });
+        track(&controller, audit::Kind::Controller);
+        self.window.add_controller(controller);
+    }
+}
 
-    // Set to overlay layer (on top of everything)
-    window.set_layer(Layer::Overlay);
+fn mark_onboarding_done() {
+    if let Err(e) = onboarding::mark_done() {
+        warn!(error = %e, "Failed to remember that onboarding was shown");
+    }
+}
 
-    // Set keyboard mode to exclusively capture keyboard input
-    window.set_keyboard_mode(KeyboardMode::Exclusive);
+impl From<layout::Edge> for Edge {
+    fn from(edge: layout::Edge) -> Self {
+        match edge {
+            layout::Edge::Top => Edge::Top,
+            layout::Edge::Bottom => Edge::Bottom,
+            layout::Edge::Left => Edge::Left,
+            layout::Edge::Right => Edge::Right,
+        }
+    }
+}
+
+impl From<config::KeyboardMode> for KeyboardMode {
+    fn from(mode: config::KeyboardMode) -> Self {
+        match mode {
+            config::KeyboardMode::Exclusive => KeyboardMode::Exclusive,
+            config::KeyboardMode::OnDemand => KeyboardMode::OnDemand,
+            config::KeyboardMode::None => KeyboardMode::None,
+        }
+    }
+}
 
-    // Set anchors based on position
-    match config.position {
-        Position::TopLeft => {
-            window.set_anchor(Edge::Top, true);
-            window.set_anchor(Edge::Left, true);
+impl From<config::Layer> for Layer {
+    fn from(layer: config::Layer) -> Self {
+        match layer {
+            config::Layer::Background => Layer::Background,
+            config::Layer::Bottom => Layer::Bottom,
+            config::Layer::Top => Layer::Top,
+            config::Layer::Overlay => Layer::Overlay,
         }
-        Position::TopRight => {
-            window.set_anchor(Edge::Top, true);
-            window.set_anchor(Edge::Right, true);
+    }
+}
+
+/// Realizes a [`Layout`] on a layer-shell window
+struct LayerSurface<'a>(&'a ApplicationWindow);
+
+impl LayoutTarget for LayerSurface<'_> {
+    fn set_layer(&self, layer: config::Layer) {
+        LayerShell::set_layer(self.0, layer.into());
+    }
+
+    fn set_keyboard_mode(&self, mode: config::KeyboardMode) {
+        LayerShell::set_keyboard_mode(self.0, mode.into());
+    }
+
+    fn set_anchor(&self, edge: layout::Edge, anchored: bool) {
+        LayerShell::set_anchor(self.0, edge.into(), anchored);
+    }
+
+    fn set_margin(&self, edge: layout::Edge, margin: i32) {
+        LayerShell::set_margin(self.0, edge.into(), margin);
+    }
+
+    fn set_exclusive_zone(&self, zone: config::ExclusiveZone) {
+        match zone {
+            config::ExclusiveZone::Auto => self.0.auto_exclusive_zone_enable(),
+            config::ExclusiveZone::Pixels(pixels) => LayerShell::set_exclusive_zone(self.0, pixels),
+        }
+    }
+}
+
+/// Icon shown while the panic combo waits for confirmation
+const PANIC_WARNING_ICON: &str = "dialog-warning-symbolic";
+
+/// Icon shown when nothing was transcribed (`--skip-empty`)
+const EMPTY_ICON: &str = "microphone-sensitivity-muted-symbolic";
+
+/// Icon shown while asking whether to replace the clipboard (`--confirm-overwrite`)
+const CONFIRM_OVERWRITE_ICON: &str = "edit-paste-symbolic";
+
+/// Icon of `--appearance dot` while recording: a filled circle, colored like the icon
+const DOT_ICON: &str = "media-record-symbolic";
+
+/// Icon to show for each phase of the session, or `None` for the spinner
+pub fn state_icon(config: &Config, state: SessionState) -> Option<&str> {
+    match state {
+        SessionState::Recording if config.appearance == config::Appearance::Dot => Some(DOT_ICON),
+        SessionState::Recording => Some(&config.icon),
+        SessionState::Processing => config.icon_processing.as_deref(),
+        SessionState::Error => Some(&config.icon_error),
+        SessionState::Empty => Some(EMPTY_ICON),
+        SessionState::ConfirmOverwrite => Some(CONFIRM_OVERWRITE_ICON),
+        SessionState::Paused => Some(&config.icon_paused),
+        SessionState::PanicWarning => Some(PANIC_WARNING_ICON),
+    }
+}
+
+/// Status text for each phase of the session
+pub fn state_label(state: SessionState) -> &'static str {
+    match state {
+        SessionState::Recording => "Recording",
+        SessionState::Paused => "Paused",
+        SessionState::Processing => "Transcribing…",
+        SessionState::Error => "Failed",
+        SessionState::Empty => "Nothing transcribed",
+        SessionState::ConfirmOverwrite => {
+            "Replace the clipboard? Enter or click: replace · Esc: keep it"
         }
-        Position::BottomLeft => {
-            window.set_anchor(Edge::Bottom, true);
-            window.set_anchor(Edge::Left, true);
+        SessionState::PanicWarning => "Press again to close every overlay",
+    }
+}
+
+/// With the `adwaita` feature, load libadwaita's stylesheet at startup, for the theme's named
+/// colors to follow the GNOME accent color and dark style
+#[cfg(feature = "adwaita")]
+pub fn init_adwaita(app: &Application) {
+    app.connect_startup(|_| {
+        if let Err(e) = libadwaita::init() {
+            warn!(error = %e, "Failed to initialize libadwaita");
         }
-        Position::BottomRight => {
-            window.set_anchor(Edge::Bottom, true);
-            window.set_anchor(Edge::Right, true);
+    });
+}
+
+#[cfg(not(feature = "adwaita"))]
+pub fn init_adwaita(_app: &Application) {}
+
+/// Pass `--renderer` and `--gdk-backend` on to GTK, which only reads them when it is initialized
+pub fn apply_render_env(config: &Config) {
+    for (name, value) in config.render_env() {
+        info!(name, value, "Setting GTK environment");
+        std::env::set_var(name, value);
+    }
+}
+
+/// Fade `window` out over `duration`, then close it. Without a fade-in to undo, it is closed
+/// right away.
+pub fn fade_out(window: &ApplicationWindow, duration: Duration) {
+    if duration.is_zero() || !window.has_css_class(layout::SHOWN_CLASS) {
+        window.close();
+        return;
+    }
+    window.remove_css_class(layout::SHOWN_CLASS);
+    let window = window.clone();
+    glib::timeout_add_local_once(duration, move || window.close());
+}
+
+/// Count `object` in the `--audit-lifecycle` tallies until it is disposed
+pub fn track(object: &impl IsA<glib::Object>, kind: audit::Kind) {
+    audit::created(kind);
+    object.add_weak_ref_notify(move || audit::destroyed(kind));
+}
+
+/// Call `on_click` when `window` is clicked with the primary button
+pub fn connect_click(window: &ApplicationWindow, on_click: impl Fn() + 'static) {
+    let gesture = GestureClick::new();
+    gesture.set_button(gdk::BUTTON_PRIMARY);
+    gesture.connect_released(move |_, _, _, _| on_click());
+    track(&gesture, audit::Kind::Controller);
+    window.add_controller(gesture);
+}
+
+/// Let `window` be dragged around with the primary button by moving it with top-left margins, and
+/// call `on_moved` with where it was dropped
+pub fn setup_drag(window: &ApplicationWindow, on_moved: impl Fn(Placement) + 'static) {
+    // Without layer shell, moving windows is up to the window manager
+    if !window.is_layer_window() {
+        return;
+    }
+    let gesture = GestureDrag::new();
+    gesture.set_button(gdk::BUTTON_PRIMARY);
+    let moved = Rc::new(Cell::new(false));
+
+    let begin_window = window.clone();
+    let begin_moved = moved.clone();
+    gesture.connect_drag_begin(move |_, _, _| {
+        begin_moved.set(false);
+        // Re-anchor to the top-left corner without moving, so offsets map onto two margins
+        let window = &begin_window;
+        let Some((width, height)) = window_monitor_size(window) else {
+            return;
+        };
+        let margin = |edge| window.is_anchor(edge).then(|| window.margin(edge));
+        let top =
+            layout::start_offset(margin(Edge::Top), margin(Edge::Bottom), height, window.height());
+        let left =
+            layout::start_offset(margin(Edge::Left), margin(Edge::Right), width, window.width());
+        window.set_anchor(Edge::Top, true);
+        window.set_anchor(Edge::Left, true);
+        window.set_anchor(Edge::Bottom, false);
+        window.set_anchor(Edge::Right, false);
+        window.set_margin(Edge::Top, top);
+        window.set_margin(Edge::Left, left);
+    });
+
+    // The window follows the pointer, so each offset is relative to where the last one left it
+    let update_window = window.clone();
+    let update_moved = moved.clone();
+    gesture.connect_drag_update(move |_, x, y| {
+        let (x, y) = (x as i32, y as i32);
+        if (x, y) == (0, 0) {
+            return;
         }
-        Position::Center => {
-            // No anchors = centered
+        update_moved.set(true);
+        let window = &update_window;
+        window.set_margin(Edge::Top, (window.margin(Edge::Top) + y).max(0));
+        window.set_margin(Edge::Left, (window.margin(Edge::Left) + x).max(0));
+    });
+
+    let end_window = window.clone();
+    gesture.connect_drag_end(move |_, _, _| {
+        if !moved.get() {
+            return;
         }
+        let window = &end_window;
+        on_moved(Placement {
+            output: LayerShell::monitor(window)
+                .and_then(|monitor| monitor.connector())
+                .map(String::from),
+            top: window.margin(Edge::Top),
+            left: window.margin(Edge::Left),
+        });
+    });
+    track(&gesture, audit::Kind::Controller);
+    window.add_controller(gesture);
+}
+
+/// Size of the monitor `window` is on, once the compositor has placed it
+fn window_monitor_size(window: &ApplicationWindow) -> Option<(i32, i32)> {
+    let monitor = LayerShell::monitor(window).or_else(|| {
+        let surface = window.surface()?;
+        WidgetExt::display(window).monitor_at_surface(&surface)
+    })?;
+    let geometry = monitor.geometry();
+    Some((geometry.width(), geometry.height()))
+}
+
+/// Pop up a menu of the `overlay.*` actions where `window` is right-clicked
+pub fn setup_context_menu(window: &ApplicationWindow) {
+    let menu = gio::Menu::new();
+    menu.append(Some("Stop"), Some("overlay.stop"));
+    menu.append(Some("Cancel (discard recording)"), Some("overlay.cancel"));
+    menu.append(Some("Restart"), Some("overlay.restart"));
+
+    let popover = PopoverMenu::from_model(Some(&menu));
+    popover.set_has_arrow(false);
+    if let Some(child) = window.child() {
+        popover.set_parent(&child);
     }
 
-    // Set margins from edge
-    window.set_margin(Edge::Top, config.margin);
-    window.set_margin(Edge::Bottom, config.margin);
-    window.set_margin(Edge::Left, config.margin);
-    window.set_margin(Edge::Right, config.margin);
+    let gesture = GestureClick::new();
+    gesture.set_button(gdk::BUTTON_SECONDARY);
+    gesture.connect_pressed(move |_, _, x, y| {
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(x as i32, y as i32, 1, 1)));
+        popover.popup();
+    });
+    track(&gesture, audit::Kind::Controller);
+    window.add_controller(gesture);
+}
 
-    // Create and add the microphone icon
-    let icon = Image::from_icon_name(&config.icon);
-    icon.set_pixel_size(config.icon_size);
-    window.set_child(Some(&icon));
+/// Show `state` on the icon (or the spinner in its place) and the status label
+pub fn show_state(
+    config: &Config,
+    icon: &Image,
+    spinner: &Spinner,
+    status: &Label,
+    state: SessionState,
+) {
+    let icon_name = state_icon(config, state);
+    spinner.set_spinning(icon_name.is_none());
+    spinner.set_visible(icon_name.is_none());
+    icon.set_visible(icon_name.is_some());
+    if let Some(icon_name) = icon_name {
+        set_icon(icon, icon_name);
+    }
+    // Named after the state, for themes to color-code it
+    for other in SessionState::ALL {
+        let class = other.to_string();
+        if other == state {
+            icon.add_css_class(&class);
+            spinner.add_css_class(&class);
+        } else {
+            icon.remove_css_class(&class);
+            spinner.remove_css_class(&class);
+        }
+    }
+    status.set_text(state_label(state));
+    // Otherwise it would look like a successful run or go unanswered, so it is spelled out
+    // on every appearance
+    if matches!(state, SessionState::Empty | SessionState::ConfirmOverwrite) {
+        status.set_visible(true);
+    }
+}
+
+impl Overlay {
+    pub fn show_state(&self, config: &Config, state: SessionState) {
+        show_state(config, &self.icon, &self.spinner, &self.status, state);
+    }
+}
+
+/// Context for an overlay found long after it was started: when, and what the transcription is
+/// for
+fn tooltip_text(config: &Config, started: &str) -> String {
+    let mut lines = vec![format!("Recording since {started}")];
+    match config.preset {
+        Some(preset) => {
+            let name = preset.to_possible_value().map(|value| value.get_name().to_string());
+            lines.push(format!("Preset: {}", name.unwrap_or_default()));
+            lines.push(format!("Sends to: {}", preset.destination()));
+        }
+        None => lines.push(format!("Command: {}", config.command.join(" "))),
+    }
+    lines.join("\n")
+}
+
+/// Show `icon` in `image`, loading it from a file path (PNG, SVG, ...) if it names an existing
+/// file, otherwise from the icon theme
+pub fn set_icon(image: &Image, icon: &str) {
+    let path = Path::new(icon);
+    if path.is_file() {
+        info!(path = %path.display(), "Loading icon from file");
+        image.set_from_file(Some(path));
+    } else {
+        image.set_icon_name(Some(icon));
+    }
+}
+
+/// Show the `--label` caption `text` on `label`, or hide it without one
+pub fn show_caption(label: &Label, text: Option<&str>) {
+    if let Some(text) = text {
+        set_label_markup(label, text);
+    }
+    label.set_visible(text.is_some());
+}
+
+/// Show `text` on `label` as Pango markup, or as it is if it isn't valid markup
+fn set_label_markup(label: &Label, text: &str) {
+    if let Err(e) = gtk4::pango::parse_markup(text, '\0') {
+        warn!(error = %e, "The label is not valid Pango markup, showing it as plain text");
+        label.set_text(text);
+        return;
+    }
+    label.set_markup(text);
+}
+
+/// Find the monitor whose connector name (e.g. `DP-1`) matches `name`
+fn find_monitor(display: &Display, name: &str) -> Option<gdk::Monitor> {
+    let monitors = display.monitors();
+    (0..monitors.n_items())
+        .filter_map(|i| monitors.item(i).and_downcast::<gdk::Monitor>())
+        .find(|monitor| monitor.connector().is_some_and(|connector| connector == name))
+}
+
+pub fn create_overlay_window(app: &Application, config: &Config) -> Result<Overlay> {
+    if !gtk4_layer_shell::is_supported() {
+        warn!("Layer shell not supported, falling back to a regular window");
+    }
+
+    let compositor = Compositor::detect();
+    let quirks = compositor.quirks();
+    info!(?compositor, ?quirks, "Creating overlay window");
+
+    let display = Display::default().ok_or(OverlayError::NoDefaultDisplay)?;
+
+    // A bar spans the screen, so it can't be dragged anywhere
+    let saved = (config.appearance != config::Appearance::Bar)
+        .then(placement::load)
+        .flatten();
+
+    // What to place the overlay above, and the output it is on
+    let target = match config.position {
+        config::Position::AboveWindow => {
+            let window = hyprland::focused_window();
+            if window.is_none() {
+                warn!("No focused Hyprland window to place the overlay above, centering it");
+            }
+            window.map(|window| (window.monitor, window.geometry))
+        }
+        config::Position::Cursor => {
+            let pointer = hyprland::pointer();
+            if pointer.is_none() {
+                warn!("Could not ask Hyprland where the pointer is, centering the overlay");
+            }
+            pointer.map(|pointer| {
+                let spot = Rect {
+                    x: pointer.x,
+                    y: pointer.y,
+                    width: 0,
+                    height: 0,
+                };
+                (pointer.monitor, spot)
+            })
+        }
+        _ => None,
+    };
+
+    // Pin to the requested output, or the one of the window or pointer to go above, otherwise let
+    // the compositor pick
+    let output = match config.output.as_deref() {
+        Some(focus::FOCUSED) => {
+            let focused = focus::focused_output();
+            if focused.is_none() {
+                warn!("Could not ask the compositor for the focused output, using its default");
+            }
+            focused
+        }
+        Some(output) => Some(output.to_string()),
+        None => target
+            .as_ref()
+            .map(|(monitor, _)| monitor.clone())
+            .or_else(|| saved.as_ref().and_then(|placement| placement.output.clone())),
+    };
+    // Its geometry is only meaningful on its own monitor
+    let near = target
+        .filter(|(monitor, _)| output.as_deref() == Some(monitor.as_str()))
+        .map(|(_, rect)| rect);
+    let monitor = output.as_deref().and_then(|name| {
+        let monitor = find_monitor(&display, name);
+        if monitor.is_none() {
+            warn!(output = %name, "Requested output not found, using compositor default");
+        }
+        monitor
+    });
+    // Copies go on every other output, so the main overlay has to be on a known one
+    let monitor = monitor.or_else(|| {
+        config
+            .all_outputs
+            .then(|| display.monitors().item(0).and_downcast::<gdk::Monitor>())
+            .flatten()
+    });
+    // Where the overlay was last dragged to, if that was on this output
+    let connector = monitor.as_ref().and_then(|monitor| monitor.connector()).map(String::from);
+    let saved = saved.filter(|placement| near.is_none() && placement.output == connector);
+
+    if let Some(placement) = &saved {
+        info!(?placement, "Placing the overlay where it was last dragged to");
+    }
+
+    let overlay = build_overlay(app, config, quirks, &display, monitor.as_ref(), near, saved);
 
     // Add CSS styling for visibility
+    let layout = compute_layout(config, quirks, &display, monitor.as_ref());
     let provider = CssProvider::new();
-    provider.load_from_data(
-        "window {
-            background-color: rgba(50, 50, 50, 0.8);
-            border-radius: 10px;
-            padding: 10px;
-        }
-        image {
-            color: #ff5555;
-        }",
-    );
+    provider.load_from_data(&layout.css);
 
-    let display = Display::default().ok_or(OverlayError::NoDefaultDisplay)?;
     gtk4::style_context_add_provider_for_display(
         &display,
         &provider,
         gtk4::STYLE_PROVIDER_PRIORITY_APPLICATION,
     );
 
+    // User stylesheet goes on top of the built-in one
+    if let Some(path) = config.user_css() {
+        if path.is_file() {
+            info!(path = %path.display(), "Loading user stylesheet");
+            let user_provider = CssProvider::new();
+            user_provider.load_from_path(&path);
+            gtk4::style_context_add_provider_for_display(
+                &display,
+                &user_provider,
+                gtk4::STYLE_PROVIDER_PRIORITY_USER,
+            );
+        } else {
+            warn!(path = %path.display(), "User stylesheet not found");
+        }
+    }
+
     info!(position = ?config.position, "Overlay window created");
 
-    Ok((window, icon))
+    Ok(overlay)
+}
+
+/// A copy of the overlay pinned to `monitor`, for `--all-outputs`. It only mirrors the session
+/// state and never takes keyboard input, which stays with the main overlay.
+pub fn create_mirror_window(app: &Application, config: &Config, monitor: &gdk::Monitor) -> Overlay {
+    let quirks = Compositor::detect().quirks();
+    let display = monitor.display();
+    let overlay = build_overlay(app, config, quirks, &display, Some(monitor), None, None);
+    overlay.window.set_keyboard_mode(KeyboardMode::None);
+    overlay.timer.set_visible(false);
+    overlay.level.set_visible(false);
+    overlay.keys.set_visible(false);
+    info!(output = ?monitor.connector(), "Overlay mirror created");
+    overlay
+}
+
+/// A translucent surface covering `monitor` behind the overlay, for `--dim-screen`, or `None` if
+/// the overlay is on the lowest layer and nothing can go behind it
+pub fn create_dim_window(
+    app: &Application,
+    config: &Config,
+    monitor: &gdk::Monitor,
+) -> Option<ApplicationWindow> {
+    let quirks = Compositor::detect().quirks();
+    let layout = Layout::compute(config, quirks, None).dim()?;
+    let window = ApplicationWindow::builder().application(app).build();
+    track(&window, audit::Kind::Window);
+    window.add_css_class(layout::DIM_CLASS);
+    fade_in_on_map(&window, config);
+    window.init_layer_shell();
+    window.set_monitor(Some(monitor));
+    layout.apply(&LayerSurface(&window));
+    info!(output = ?monitor.connector(), "Dimming surface created");
+    Some(window)
+}
+
+/// Re-apply placement and style to `window` after a live change to `config`. The stylesheet goes
+/// into `provider`, which overrides the one generated at startup.
+pub fn restyle(window: &ApplicationWindow, config: &Config, provider: &CssProvider) {
+    let monitor = LayerShell::monitor(window);
+    let quirks = Compositor::detect().quirks();
+    let layout = compute_layout(config, quirks, &WidgetExt::display(window), monitor.as_ref());
+    if window.is_layer_window() {
+        layout.apply(&LayerSurface(window));
+    }
+    provider.load_from_data(&layout.css);
+}
+
+/// Fade `window` in with `--animation-duration` whenever it is shown
+fn fade_in_on_map(window: &ApplicationWindow, config: &Config) {
+    if config.animation_duration > 0 {
+        // Once the transparent style has been drawn, so that the opacity transition runs
+        window.connect_map(|window| {
+            let window = window.clone();
+            glib::idle_add_local_once(move || window.add_css_class(layout::SHOWN_CLASS));
+        });
+    }
+}
+
+/// `monitor`, or else the first one, which is a guess until the compositor has placed the surface
+fn monitor_or_first(display: &Display, monitor: Option<&gdk::Monitor>) -> Option<gdk::Monitor> {
+    monitor
+        .cloned()
+        .or_else(|| display.monitors().item(0).and_downcast::<gdk::Monitor>())
+}
+
+/// Lay the overlay out for `monitor`: percentage sizes relative to it, and `--units physical`
+/// sizes divided by its scale
+fn compute_layout(
+    config: &Config,
+    quirks: Quirks,
+    display: &Display,
+    monitor: Option<&gdk::Monitor>,
+) -> Layout {
+    let monitor = monitor_or_first(display, monitor);
+    let size = monitor
+        .as_ref()
+        .map(|monitor| monitor.geometry())
+        .map(|geometry| (geometry.width(), geometry.height()));
+    let scale = monitor.as_ref().map_or(1, |monitor| monitor.scale_factor());
+    Layout::compute(&config.in_logical_pixels(scale), quirks, size)
+}
+
+/// Id of the root widget of a `--layout` template
+const TEMPLATE_ROOT: &str = "overlay";
+
+/// Load the `--layout` template, or `None` (with a warning) to use the built-in layout if it
+/// can't be loaded or has no root widget
+fn load_template(path: &Path) -> Option<(gtk4::Builder, gtk4::Widget)> {
+    let builder = gtk4::Builder::new();
+    if let Err(e) = builder.add_from_file(path) {
+        warn!(path = %path.display(), error = %e, "Failed to load layout, using the built-in one");
+        return None;
+    }
+    let Some(root) = builder.object::<gtk4::Widget>(TEMPLATE_ROOT) else {
+        warn!(
+            path = %path.display(),
+            id = TEMPLATE_ROOT,
+            "Layout has no root widget, using the built-in one"
+        );
+        return None;
+    };
+    info!(path = %path.display(), "Loaded layout");
+    Some((builder, root))
+}
+
+/// The widget of the template with `id`, if it has one of the right type, otherwise a new one
+/// from `fallback` (which only shows up in the built-in layout)
+fn templated<T: IsA<glib::Object>>(
+    template: Option<&gtk4::Builder>,
+    id: &str,
+    fallback: impl FnOnce() -> T,
+) -> T {
+    template.and_then(|builder| builder.object(id)).unwrap_or_else(fallback)
+}
+
+/// Lay out the overlay's widgets in a new layer-shell window, on `monitor` if given, and just
+/// above `near` on it (the focused window or the pointer) or else at the `saved` placement if
+/// given
+fn build_overlay(
+    app: &Application,
+    config: &Config,
+    quirks: Quirks,
+    display: &Display,
+    monitor: Option<&gdk::Monitor>,
+    near: Option<Rect>,
+    saved: Option<Placement>,
+) -> Overlay {
+    let mut layout = compute_layout(config, quirks, display, monitor);
+    if let Some(window) = near {
+        layout.place_above(window);
+    } else if let Some(placement) = saved {
+        layout.place_at(placement.top, placement.left);
+    }
+
+    let window = ApplicationWindow::builder()
+        .application(app)
+        .default_width(layout.width)
+        .default_height(layout.height)
+        .build();
+    track(&window, audit::Kind::Window);
+    fade_in_on_map(&window, config);
+
+    if gtk4_layer_shell::is_supported() {
+        // Initialize layer shell BEFORE the window is realized
+        window.init_layer_shell();
+
+        if let Some(monitor) = monitor {
+            window.set_monitor(Some(monitor));
+        }
+
+        layout.apply(&LayerSurface(&window));
+    } else {
+        // X11, or a compositor without layer shell (GNOME): a plain borderless window, which
+        // the window manager places and stacks
+        window.set_title(Some("waystt-wrapper"));
+        window.set_decorated(false);
+        window.set_resizable(false);
+    }
+
+    // Each window needs widgets of its own, so mirrors load the template again
+    let template = config.layout.as_deref().and_then(load_template);
+    let builder = template.as_ref().map(|(builder, _)| builder);
+
+    // Create and add the microphone icon, with a hint label below that stays hidden unless needed
+    let icon = templated(builder, "icon", Image::new);
+    set_icon(&icon, state_icon(config, SessionState::Recording).unwrap_or(&config.icon));
+    icon.set_pixel_size(layout.icon_size);
+
+    // Blinks while recording, from the stylesheet, as long as it comes after the icon
+    let rec = templated(builder, "rec", || Label::new(None));
+    rec.set_text("● REC");
+    rec.add_css_class("rec");
+    rec.set_visible(config.rec_dot);
+
+    let spinner = templated(builder, "spinner", Spinner::new);
+    spinner.set_size_request(layout.icon_size, layout.icon_size);
+    spinner.set_visible(false);
+
+    let timer = templated(builder, "timer", || Label::new(None));
+    timer.add_css_class("timer");
+    timer.set_visible(config.show_timer || config.max_duration.is_some());
+
+    let level = templated(builder, "level", || LevelBar::for_interval(0.0, 1.0));
+    level.add_css_class("level");
+    level.set_visible(config.level_meter);
+
+    let hint = templated(builder, "hint", || Label::new(None));
+    hint.set_text(KEYBOARD_FALLBACK_HINT);
+    hint.add_css_class("hint");
+    hint.set_justify(gtk4::Justification::Center);
+    hint.set_visible(false);
+
+    let keys = templated(builder, "keys", || Label::new(None));
+    keys.set_text(&config.key_hints());
+    keys.add_css_class("keys");
+    // Pointless without keyboard input
+    keys.set_visible(config.show_hints && layout.keyboard_mode != config::KeyboardMode::None);
+
+    let preview = templated(builder, "transcript", || Label::new(None));
+    preview.add_css_class("preview");
+    preview.set_wrap(true);
+    preview.set_max_width_chars(40);
+    preview.set_justify(gtk4::Justification::Center);
+    preview.set_visible(false);
+
+    let error = templated(builder, "error", || Label::new(None));
+    error.add_css_class("error");
+    error.set_wrap(true);
+    error.set_max_width_chars(60);
+    error.set_visible(false);
+
+    let warning = templated(builder, "warning", || Label::new(None));
+    warning.add_css_class("warning");
+    warning.set_justify(gtk4::Justification::Center);
+    warning.set_visible(false);
+
+    let gain = templated(builder, "gain", || Label::new(None));
+    gain.add_css_class("warning");
+    gain.add_css_class("gain");
+    gain.set_visible(false);
+
+    let onboarding = templated(builder, "onboarding", || Label::new(None));
+    onboarding.add_css_class("onboarding");
+    onboarding.set_visible(false);
+
+    let label = templated(builder, "caption", || Label::new(None));
+    label.add_css_class("caption");
+    show_caption(&label, config.label.as_deref());
+
+    let status = templated(builder, "status", || Label::new(None));
+    status.add_css_class("status");
+    status.set_visible(layout.horizontal);
+
+    let container = if let Some((_, root)) = template {
+        root
+    } else {
+        let orientation = if layout.horizontal {
+            Orientation::Horizontal
+        } else {
+            Orientation::Vertical
+        };
+        let container = gtk4::Box::new(orientation, layout.spacing);
+        container.append(&icon);
+        container.append(&rec);
+        container.append(&spinner);
+        container.append(&label);
+        container.append(&timer);
+        container.append(&level);
+        container.append(&status);
+        container.append(&preview);
+        container.append(&error);
+        container.append(&hint);
+        container.append(&keys);
+        container.append(&warning);
+        container.append(&gain);
+        container.append(&onboarding);
+        container.upcast()
+    };
+    // In the locale's own time format, 12- or 24-hour
+    let started = glib::DateTime::now_local().and_then(|now| now.format("%X"));
+    container.set_tooltip_text(Some(&tooltip_text(
+        config,
+        started.as_deref().unwrap_or("startup"),
+    )));
+    window.set_child(Some(&container));
+
+    Overlay {
+        window,
+        icon,
+        spinner,
+        timer,
+        level,
+        hint,
+        keys,
+        preview,
+        error,
+        warning,
+        gain,
+        onboarding,
+        caption: label,
+        status,
+    }
 }
 
 #[cfg(test)]
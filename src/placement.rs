@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::onboarding::state_dir;
+
+/// Where the overlay was last dragged to: margins from the top-left corner of `output`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Placement {
+    /// Connector name of the output it was on, if it was pinned to one
+    pub output: Option<String>,
+    pub top: i32,
+    pub left: i32,
+}
+
+fn path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("placement.json"))
+}
+
+/// The saved placement, if the overlay was ever dragged
+pub fn load() -> Option<Placement> {
+    load_from(&path()?)
+}
+
+fn load_from(path: &Path) -> Option<Placement> {
+    let data = fs::read(path).ok()?;
+    match serde_json::from_slice(&data) {
+        Ok(placement) => Some(placement),
+        Err(e) => {
+            warn!(path = %path.display(), error = %e, "Ignoring unreadable saved placement");
+            None
+        }
+    }
+}
+
+/// Remember `placement` for the next overlays
+pub fn save(placement: &Placement) -> io::Result<()> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    save_to(&path, placement)
+}
+
+fn save_to(path: &Path, placement: &Placement) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_vec(placement)?)
+}
+
+/// Go back to placing the overlay by `--position`
+pub fn forget() -> io::Result<()> {
+    match path().map(fs::remove_file) {
+        Some(Err(e)) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = std::env::temp_dir()
+            .join(format!("waystt-wrapper-placement-{}", std::process::id()));
+        let path = dir.join("state").join("placement.json");
+        assert_eq!(load_from(&path), None);
+
+        let placement = Placement {
+            output: Some("DP-1".to_string()),
+            top: 40,
+            left: 1200,
+        };
+        save_to(&path, &placement).unwrap();
+        assert_eq!(load_from(&path), Some(placement));
+
+        fs::write(&path, "{").unwrap();
+        assert_eq!(load_from(&path), None);
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
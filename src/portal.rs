@@ -0,0 +1,79 @@
+use gtk4::gio;
+use gtk4::glib;
+use gtk4::prelude::*;
+use tracing::debug;
+
+use crate::config::{ColorScheme, Config, Theme};
+
+pub const PORTAL_NAME: &str = "org.freedesktop.portal.Desktop";
+pub const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const SETTINGS_INTERFACE: &str = "org.freedesktop.portal.Settings";
+const APPEARANCE: &str = "org.freedesktop.appearance";
+const COLOR_SCHEME: &str = "color-scheme";
+
+/// How long to wait for the portal before drawing with the dark palette, in milliseconds
+const READ_TIMEOUT_MS: i32 = 500;
+
+pub fn session_bus() -> Option<gio::DBusConnection> {
+    gio::bus_get_sync(gio::BusType::Session, gio::Cancellable::NONE)
+        .inspect_err(|e| debug!(error = %e, "No session bus"))
+        .ok()
+}
+
+/// The desktop's dark/light preference, if a settings portal is running
+pub fn color_scheme() -> Option<ColorScheme> {
+    let reply = session_bus()?
+        .call_sync(
+            Some(PORTAL_NAME),
+            PORTAL_PATH,
+            SETTINGS_INTERFACE,
+            "ReadOne",
+            Some(&(APPEARANCE, COLOR_SCHEME).to_variant()),
+            Some(glib::VariantTy::new("(v)").expect("valid variant type")),
+            gio::DBusCallFlags::NONE,
+            READ_TIMEOUT_MS,
+            gio::Cancellable::NONE,
+        )
+        .inspect_err(|e| debug!(error = %e, "Could not read the color scheme from the portal"))
+        .ok()?;
+    let (value,) = reply.get::<(glib::Variant,)>()?;
+    value.get::<u32>().map(ColorScheme::from_portal)
+}
+
+/// Draw `--theme auto` with the desktop's current preference, if it can be read
+pub fn follow_color_scheme(config: &mut Config) {
+    if config.theme != Theme::Auto {
+        return;
+    }
+    if let Some(scheme) = color_scheme() {
+        debug!(?scheme, "Desktop color scheme");
+        config.follow_color_scheme(scheme);
+    }
+}
+
+/// Call `on_change` whenever the desktop's dark/light preference changes, for as long as the
+/// returned subscription is kept
+pub fn watch_color_scheme(
+    on_change: impl Fn(ColorScheme) + 'static,
+) -> Option<gio::SignalSubscription> {
+    let subscription = session_bus()?.subscribe_to_signal(
+        Some(PORTAL_NAME),
+        Some(SETTINGS_INTERFACE),
+        Some("SettingChanged"),
+        Some(PORTAL_PATH),
+        Some(APPEARANCE),
+        gio::DBusSignalFlags::NONE,
+        move |signal| {
+            let Some((_, key, value)) = signal.parameters.get::<(String, String, glib::Variant)>()
+            else {
+                return;
+            };
+            if key == COLOR_SCHEME {
+                if let Some(value) = value.get::<u32>() {
+                    on_change(ColorScheme::from_portal(value));
+                }
+            }
+        },
+    );
+    Some(subscription)
+}
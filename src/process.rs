@@ -1,12 +1,22 @@
-use std::ffi::OsStr;
-use std::io;
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Read};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
 use std::process::{Child, Command, ExitStatus, Stdio};
-use std::result::Result as StdResult;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use nix::sys::prctl;
 use nix::sys::signal::{kill, Signal};
-use nix::unistd::Pid;
+use nix::unistd::{getppid, Pid};
 use tracing::{error, info, warn};
 
+use crate::audit;
+use crate::config::{OutputMode, EMPTY_MARKER_VAR, PENDING_VAR};
+
 /// Error type for process spawning and management operations
 #[derive(Debug, thiserror::Error)]
 pub enum ProcessError {
@@ -16,39 +26,283 @@ pub enum ProcessError {
     SignalFailed(nix::errno::Errno),
     #[error("no command specified")]
     EmptyCommand,
+    #[error("`{0}` was not found in PATH")]
+    ProgramNotFound(String),
 }
 
 pub type Result<T> = std::result::Result<T, ProcessError>;
 
+/// Signals sent, in order, when a stop is requested again for a child that is already stopping
+pub const ESCALATION_SIGNALS: [Signal; 2] = [Signal::SIGTERM, Signal::SIGKILL];
+
+/// How long to wait after each escalation signal before sending the next one
+pub const ESCALATION_GRACE: Duration = Duration::from_secs(2);
+
+/// Variables the command keeps with `--clean-env`: enough to find programs, reach the session
+/// and its audio, and hand the transcription over
+const CLEAN_ENV: &[&str] = &[
+    "PATH",
+    "HOME",
+    "USER",
+    "LANG",
+    "LC_ALL",
+    "WAYLAND_DISPLAY",
+    "DISPLAY",
+    "XDG_RUNTIME_DIR",
+    "DBUS_SESSION_BUS_ADDRESS",
+    EMPTY_MARKER_VAR,
+    PENDING_VAR,
+];
+
+/// The environment to run the command in with `--clean-env`: the wrapper's own, down to
+/// [`CLEAN_ENV`] and the variables in `keep`
+pub fn clean_env(keep: &[String]) -> Vec<(OsString, OsString)> {
+    kept_vars(std::env::vars_os(), keep)
+}
+
+fn kept_vars(
+    vars: impl IntoIterator<Item = (OsString, OsString)>,
+    keep: &[String],
+) -> Vec<(OsString, OsString)> {
+    vars.into_iter()
+        .filter(|(name, _)| {
+            name.to_str().is_some_and(|name| {
+                CLEAN_ENV.contains(&name) || keep.iter().any(|kept| kept == name)
+            })
+        })
+        .collect()
+}
+
+/// Send `signal` to the process `pid`
+pub fn send_signal(pid: Pid, signal: Signal) -> Result<()> {
+    info!(pid = ?pid, signal = ?signal, "Sending signal to child");
+    kill(pid, signal).map_err(ProcessError::SignalFailed)
+}
+
+impl OutputMode {
+    fn stdio(self) -> Stdio {
+        match self {
+            OutputMode::Inherit => Stdio::inherit(),
+            OutputMode::Null => Stdio::null(),
+            OutputMode::Log | OutputMode::Capture => Stdio::piped(),
+        }
+    }
+}
+
+/// The child's piped output streams, drained on background threads
+pub struct ChildOutput {
+    stdout: Arc<Mutex<Vec<u8>>>,
+    stderr: Arc<Mutex<Vec<u8>>>,
+    readers: usize,
+    done: Receiver<()>,
+}
+
+/// Output captured with [`OutputMode::Capture`]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct CapturedOutput {
+    pub stdout: Vec<u8>,
+    pub stderr: Vec<u8>,
+}
+
+impl ChildOutput {
+    /// The last `lines` non-empty lines of captured stderr read so far
+    pub fn stderr_tail(&self, lines: usize) -> Vec<String> {
+        let stderr = self.stderr.lock().unwrap();
+        let text = String::from_utf8_lossy(&stderr);
+        let mut tail: Vec<String> = text
+            .lines()
+            .rev()
+            .filter(|line| !line.trim().is_empty())
+            .take(lines)
+            .map(str::to_string)
+            .collect();
+        tail.reverse();
+        tail
+    }
+
+    /// Wait (at most `timeout`) for the readers to reach the end of the pipes, and return what
+    /// was captured. Bounded, since a grandchild may still hold a pipe open after the child exits.
+    pub fn finish(self, timeout: Duration) -> CapturedOutput {
+        let deadline = Instant::now() + timeout;
+        for _ in 0..self.readers {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if self.done.recv_timeout(remaining).is_err() {
+                warn!("Child output still open, returning what was read so far");
+                break;
+            }
+        }
+        let take = |buffer: &Mutex<Vec<u8>>| std::mem::take(&mut *buffer.lock().unwrap());
+        CapturedOutput {
+            stdout: take(&self.stdout),
+            stderr: take(&self.stderr),
+        }
+    }
+}
+
+/// Receives each line of the child's stdout as it is read, from a background thread
+pub type LineSink = Box<dyn Fn(String) + Send>;
+
+/// Drain `stream` on a background thread, logging or capturing it per `mode`, and passing each
+/// line to `on_line`
+fn spawn_reader(
+    stream: impl Read + Send + 'static,
+    name: &'static str,
+    mode: OutputMode,
+    buffer: Arc<Mutex<Vec<u8>>>,
+    on_line: Option<LineSink>,
+    done: Sender<()>,
+) {
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stream);
+        let on_line = |line: &[u8]| {
+            if let Some(on_line) = &on_line {
+                on_line(String::from_utf8_lossy(line).into_owned());
+            }
+        };
+        match mode {
+            OutputMode::Log => {
+                for line in reader.lines().map_while(|line| line.ok()) {
+                    info!(stream = name, "child: {line}");
+                    on_line(line.as_bytes());
+                }
+            }
+            _ => {
+                // Append as it arrives, so that a bounded `finish` still sees partial output
+                let mut chunk = [0; 4096];
+                let mut pending = Vec::new();
+                while let Ok(n @ 1..) = reader.read(&mut chunk) {
+                    buffer.lock().unwrap().extend_from_slice(&chunk[..n]);
+                    pending.extend_from_slice(&chunk[..n]);
+                    while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+                        on_line(&pending[..end]);
+                        pending.drain(..=end);
+                    }
+                }
+                if !pending.is_empty() {
+                    on_line(&pending);
+                }
+            }
+        }
+        let _ = done.send(());
+    });
+}
+
+/// Whether `program` can be run: an executable path, or found in `PATH`
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let is_executable = |path: &Path| {
+        path.metadata()
+            .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+    };
+    if program.contains('/') {
+        let path = PathBuf::from(program);
+        return is_executable(&path).then_some(path);
+    }
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|path| is_executable(path))
+}
+
+/// Fail with [`ProcessError::ProgramNotFound`] for the first of `programs` that can't be run
+pub fn require_programs(programs: &[&str]) -> Result<()> {
+    match programs.iter().find(|program| find_program(program).is_none()) {
+        Some(program) => Err(ProcessError::ProgramNotFound(program.to_string())),
+        None => Ok(()),
+    }
+}
+
 pub struct ChildProcess {
     child: Child,
+    output: Option<ChildOutput>,
 }
 
 impl ChildProcess {
-    pub fn spawn(command: &[String]) -> Result<Self> {
+    /// Spawn `command`, passing each line it prints to stdout to `on_line` (in the `log` and
+    /// `capture` output modes), with `env` as its whole environment if given instead of the
+    /// wrapper's. With `die_with_parent`, the command is terminated if the wrapper dies without
+    /// stopping it.
+    pub fn spawn(
+        command: &[String],
+        stdout: OutputMode,
+        stderr: OutputMode,
+        on_line: Option<LineSink>,
+        env: Option<Vec<(OsString, OsString)>>,
+        die_with_parent: bool,
+    ) -> Result<Self> {
         if command.is_empty() {
             return Err(ProcessError::EmptyCommand);
         }
 
-        info!(command = ?command, "Spawning child process");
+        info!(command = ?command, ?stdout, ?stderr, "Spawning child process");
 
-        let child = Command::new(&command[0])
+        let mut process = Command::new(&command[0]);
+        process
             .args(&command[1..])
             .stdin(Stdio::null())
-            .stdout(Stdio::inherit())
-            .stderr(Stdio::inherit())
-            .spawn()?;
+            .stdout(stdout.stdio())
+            .stderr(stderr.stdio());
+        if let Some(env) = env {
+            process.env_clear().envs(env);
+        }
+        if die_with_parent {
+            let parent = Pid::this();
+            // SAFETY: prctl and getppid are async-signal-safe, and nothing is allocated
+            unsafe {
+                process.pre_exec(move || {
+                    prctl::set_pdeathsig(Signal::SIGTERM)?;
+                    // The wrapper died before the signal was set up
+                    if getppid() != parent {
+                        return Err(io::ErrorKind::BrokenPipe.into());
+                    }
+                    Ok(())
+                });
+            }
+        }
+        let mut child = process.spawn()?;
 
         info!(pid = child.id(), "Child process spawned");
+        audit::created(audit::Kind::Child);
+
+        let (sender, done) = mpsc::channel();
+        let mut output = ChildOutput {
+            stdout: Arc::default(),
+            stderr: Arc::default(),
+            readers: 0,
+            done,
+        };
+        if let Some(pipe) = child.stdout.take() {
+            spawn_reader(
+                pipe,
+                "stdout",
+                stdout,
+                output.stdout.clone(),
+                on_line,
+                sender.clone(),
+            );
+            output.readers += 1;
+        }
+        if let Some(pipe) = child.stderr.take() {
+            spawn_reader(pipe, "stderr", stderr, output.stderr.clone(), None, sender);
+            output.readers += 1;
+        }
+
+        Ok(Self {
+            child,
+            output: Some(output),
+        })
+    }
+
+    /// Take over the child's piped output, if any
+    pub fn take_output(&mut self) -> Option<ChildOutput> {
+        self.output.take()
+    }
 
-        Ok(Self { child })
+    pub fn pid(&self) -> Pid {
+        Pid::from_raw(self.child.id().try_into().expect("child had no valid pid"))
     }
 
     pub fn send_sigusr1(&self) -> Result<()> {
-        let pid = Pid::from_raw(self.child.id().try_into().expect("child had no valid pid"));
-        info!(pid = ?pid, "Sending SIGUSR1 to child");
-        kill(pid, Signal::SIGUSR1).map_err(ProcessError::SignalFailed)?;
-        Ok(())
+        send_signal(self.pid(), Signal::SIGUSR1)
     }
 
     pub fn wait(mut self) -> std::io::Result<ExitStatus> {
@@ -70,39 +324,143 @@ impl ChildProcess {
     }
 }
 
-/// Error type for holding possibilities when running a child process to termination
-#[derive(Debug, thiserror::Error)]
-pub enum CommandError {
-    #[error(transparent)]
-    Io(#[from] io::Error),
-    #[error("child process exited with failure exit status: {0:?}")]
-    Status(std::process::ExitStatus),
+impl Drop for ChildProcess {
+    fn drop(&mut self) {
+        audit::destroyed(audit::Kind::Child);
+    }
 }
 
-/// Convenience trait giving a 1-liner for the execution and checking phase of running a
-/// [`Command`]
-pub trait CommandExt {
-    /// Check the status of the command and return an error if it failed, but in one step
-    fn status_checked(&mut self) -> StdResult<(), CommandError>;
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-impl CommandExt for Command {
-    #[inline]
-    fn status_checked(&mut self) -> StdResult<(), CommandError> {
-        self.status()
-            .map_err(From::from)
-            .and_then(|status| if status.success() {
-                Ok(())
-            } else {
-                Err(CommandError::Status(status))
-            })
+    fn sh(script: &str, stdout: OutputMode, stderr: OutputMode) -> ChildProcess {
+        let command = ["sh", "-c", script].map(String::from);
+        ChildProcess::spawn(&command, stdout, stderr, None, None, false).unwrap()
     }
-}
 
-/// Simply shells out to `killall`
-pub fn killall<S: AsRef<OsStr>>(process_name: S, signal_type: Option<&str>) -> StdResult<(), CommandError> {
-    Command::new("killall")
-        .args(signal_type)
-        .arg(process_name)
-        .status_checked()
+    #[test]
+    fn test_clean_env_keeps_the_session_and_requested_vars() {
+        let vars = ["PATH", "WAYLAND_DISPLAY", "GSK_RENDERER", "OPENAI_API_KEY", EMPTY_MARKER_VAR]
+            .map(|name| (OsString::from(name), OsString::from("x")));
+        let kept = kept_vars(vars.clone(), &[]);
+        let names: Vec<_> = kept.iter().map(|(name, _)| name.to_str().unwrap()).collect();
+        assert_eq!(names, ["PATH", "WAYLAND_DISPLAY", EMPTY_MARKER_VAR]);
+
+        let kept = kept_vars(vars, &["OPENAI_API_KEY".to_string()]);
+        assert!(kept.iter().any(|(name, _)| name == "OPENAI_API_KEY"));
+        assert!(!kept.iter().any(|(name, _)| name == "GSK_RENDERER"));
+    }
+
+    #[test]
+    fn test_clean_env_replaces_the_environment() {
+        let command = ["sh", "-c", "printf '%s' \"${HOME-unset}\""].map(String::from);
+        let env = Some(vec![(OsString::from("PATH"), std::env::var_os("PATH").unwrap())]);
+        let mut child =
+            ChildProcess::spawn(&command, OutputMode::Capture, OutputMode::Null, None, env, false)
+                .unwrap();
+        let output = child.take_output().unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(output.finish(Duration::from_secs(5)).stdout, b"unset");
+    }
+
+    #[test]
+    fn test_find_program() {
+        assert!(find_program("sh").is_some());
+        assert!(find_program("/bin/sh").is_some());
+        assert!(find_program("waystt-wrapper-no-such-program").is_none());
+        assert!(find_program("/nonexistent/sh").is_none());
+    }
+
+    #[test]
+    fn test_require_programs_names_the_missing_one() {
+        assert!(require_programs(&["sh"]).is_ok());
+        let err = require_programs(&["sh", "waystt-wrapper-no-such-program"]).unwrap_err();
+        assert!(matches!(
+            err,
+            ProcessError::ProgramNotFound(program) if program == "waystt-wrapper-no-such-program"
+        ));
+    }
+
+    #[test]
+    fn test_capture_both_streams() {
+        let mut child = sh("echo out; echo err >&2", OutputMode::Capture, OutputMode::Capture);
+        let output = child.take_output().unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(
+            output.finish(Duration::from_secs(5)),
+            CapturedOutput {
+                stdout: b"out\n".to_vec(),
+                stderr: b"err\n".to_vec(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_stdout_lines_are_forwarded() {
+        for mode in [OutputMode::Capture, OutputMode::Log] {
+            let (sender, lines) = mpsc::channel();
+            let command = ["sh", "-c", "echo one; printf 'two\\nthree'"].map(String::from);
+            let on_line: LineSink = Box::new(move |line| sender.send(line).unwrap());
+            let mut child =
+                ChildProcess::spawn(&command, mode, OutputMode::Null, Some(on_line), None, false)
+                    .unwrap();
+            let output = child.take_output().unwrap();
+            assert!(child.wait().unwrap().success());
+            output.finish(Duration::from_secs(5));
+            assert_eq!(lines.iter().collect::<Vec<_>>(), ["one", "two", "three"]);
+        }
+    }
+
+    #[test]
+    fn test_die_with_parent() {
+        use std::os::unix::process::ExitStatusExt;
+
+        let command = ["sleep", "30"].map(String::from);
+        // The signal follows the thread that spawned the command, so its exit stands in for the
+        // wrapper's
+        let child = thread::spawn(move || {
+            ChildProcess::spawn(&command, OutputMode::Null, OutputMode::Null, None, None, true)
+        })
+        .join()
+        .unwrap()
+        .unwrap();
+        assert_eq!(child.wait().unwrap().signal(), Some(Signal::SIGTERM as i32));
+    }
+
+    #[test]
+    fn test_stderr_tail() {
+        let script = "echo out; printf 'one\\ntwo\\n\\nthree\\n' >&2";
+        let mut child = sh(script, OutputMode::Null, OutputMode::Capture);
+        let output = child.take_output().unwrap();
+        assert!(child.wait().unwrap().success());
+        // Wait for the reader to reach the end of the pipe
+        let start = Instant::now();
+        while output.stderr_tail(5).len() < 3 && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(output.stderr_tail(2), ["two", "three"]);
+        assert_eq!(output.stderr_tail(5), ["one", "two", "three"]);
+    }
+
+    #[test]
+    fn test_null_and_log_capture_nothing() {
+        let mut child = sh("echo out; echo err >&2", OutputMode::Null, OutputMode::Log);
+        let output = child.take_output().unwrap();
+        assert!(child.wait().unwrap().success());
+        assert_eq!(output.finish(Duration::from_secs(5)), CapturedOutput::default());
+    }
+
+    #[test]
+    fn test_finish_is_bounded_by_timeout() {
+        // The background sleep keeps the pipe open after the shell exits
+        let mut child = sh("echo early; sleep 5 &", OutputMode::Capture, OutputMode::Null);
+        let output = child.take_output().unwrap();
+        assert!(child.wait().unwrap().success());
+
+        let start = Instant::now();
+        let captured = output.finish(Duration::from_millis(200));
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert_eq!(captured.stdout, b"early\n");
+    }
 }
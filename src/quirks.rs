@@ -0,0 +1,102 @@
+use crate::config::{KeyboardMode, Layer};
+
+/// Compositors that need non-default layer-shell settings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compositor {
+    Niri,
+    Cosmic,
+    Other,
+}
+
+/// Layer-shell defaults adjusted per compositor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    pub keyboard_mode: KeyboardMode,
+    pub layer: Layer,
+    /// Only apply margins on anchored edges. The protocol says margins on unanchored edges are
+    /// ignored, but some compositors offset the surface by them anyway.
+    pub anchored_margins_only: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            keyboard_mode: KeyboardMode::Exclusive,
+            layer: Layer::Overlay,
+            anchored_margins_only: false,
+        }
+    }
+}
+
+impl Compositor {
+    /// Detect the running compositor from the session environment
+    pub fn detect() -> Self {
+        let desktop = std::env::var("XDG_CURRENT_DESKTOP").ok();
+        let niri_socket = std::env::var_os("NIRI_SOCKET").is_some();
+        Self::from_env(desktop.as_deref(), niri_socket)
+    }
+
+    fn from_env(desktop: Option<&str>, niri_socket: bool) -> Self {
+        if niri_socket {
+            return Compositor::Niri;
+        }
+
+        // XDG_CURRENT_DESKTOP is a colon-separated list
+        let desktops = desktop.into_iter().flat_map(|d| d.split(':'));
+        for name in desktops {
+            if name.eq_ignore_ascii_case("niri") {
+                return Compositor::Niri;
+            }
+            if name.eq_ignore_ascii_case("cosmic") {
+                return Compositor::Cosmic;
+            }
+        }
+
+        Compositor::Other
+    }
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Compositor::Niri => Quirks {
+                anchored_margins_only: true,
+                ..Quirks::default()
+            },
+            Compositor::Cosmic => Quirks {
+                keyboard_mode: KeyboardMode::OnDemand,
+                layer: Layer::Top,
+                anchored_margins_only: true,
+            },
+            Compositor::Other => Quirks::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_niri_socket() {
+        assert_eq!(Compositor::from_env(None, true), Compositor::Niri);
+        assert_eq!(Compositor::from_env(Some("sway"), true), Compositor::Niri);
+    }
+
+    #[test]
+    fn test_detect_from_desktop() {
+        assert_eq!(Compositor::from_env(Some("niri"), false), Compositor::Niri);
+        assert_eq!(Compositor::from_env(Some("COSMIC"), false), Compositor::Cosmic);
+        assert_eq!(Compositor::from_env(Some("pop:COSMIC"), false), Compositor::Cosmic);
+    }
+
+    #[test]
+    fn test_detect_other() {
+        assert_eq!(Compositor::from_env(None, false), Compositor::Other);
+        assert_eq!(Compositor::from_env(Some("sway"), false), Compositor::Other);
+        assert_eq!(Compositor::from_env(Some("Hyprland"), false), Compositor::Other);
+    }
+
+    #[test]
+    fn test_other_uses_defaults() {
+        assert_eq!(Compositor::Other.quirks(), Quirks::default());
+    }
+}
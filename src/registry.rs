@@ -0,0 +1,461 @@
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::event::SessionState;
+
+/// Error type for the runtime instance registry
+#[derive(Debug, thiserror::Error)]
+pub enum RegistryError {
+    #[error("XDG_RUNTIME_DIR is not set")]
+    NoRuntimeDir,
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+pub type Result<T> = std::result::Result<T, RegistryError>;
+
+/// A running wrapper, as recorded in the registry
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Instance {
+    pub pid: u32,
+    /// Wayland display the instance runs on, so that actions stay within one graphical session
+    pub session: Option<String>,
+    pub state: SessionState,
+    /// When the recording started, in milliseconds since the Unix epoch
+    #[serde(default)]
+    pub started: u64,
+    /// The overlay was closed and the transcription is finishing in the background
+    #[serde(default)]
+    pub detached: bool,
+}
+
+impl Instance {
+    /// This process, just started
+    pub fn current() -> Self {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        Self {
+            pid: std::process::id(),
+            session: current_session(),
+            state: SessionState::Recording,
+            started: u64::try_from(now.as_millis()).unwrap_or(u64::MAX),
+            detached: false,
+        }
+    }
+
+    /// How long ago the recording started
+    pub fn elapsed(&self) -> Duration {
+        let started = UNIX_EPOCH + Duration::from_millis(self.started);
+        SystemTime::now().duration_since(started).unwrap_or_default()
+    }
+}
+
+fn current_session() -> Option<String> {
+    std::env::var("WAYLAND_DISPLAY").ok()
+}
+
+/// Per-user directory of running instances, `$XDG_RUNTIME_DIR/waystt-wrapper/instances`.
+///
+/// The runtime dir is private to the user, so other users' instances are never visible here.
+pub struct Registry {
+    dir: PathBuf,
+}
+
+impl Registry {
+    pub fn open() -> Result<Self> {
+        let runtime_dir = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|path| path.is_absolute())
+            .ok_or(RegistryError::NoRuntimeDir)?;
+        Self::open_in(&runtime_dir.join(env!("CARGO_PKG_NAME")).join("instances"))
+    }
+
+    fn open_in(dir: &Path) -> Result<Self> {
+        DirBuilder::new().mode(0o700).recursive(true).create(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{pid}.json"))
+    }
+
+    /// Where the instance with `pid` listens for settings
+    pub fn socket_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{pid}.sock"))
+    }
+
+    /// The file a `--skip-empty` command of the instance with `pid` creates when it transcribed
+    /// nothing
+    pub fn empty_marker_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{pid}.empty"))
+    }
+
+    /// The file the transcription of the instance with `pid` is held back in by
+    /// `--confirm-overwrite`
+    pub fn pending_path(&self, pid: u32) -> PathBuf {
+        self.dir.join(format!("{pid}.pending"))
+    }
+
+    /// Record `instance`, until the returned [`Registration`] is dropped
+    pub fn register(&self, instance: Instance) -> Result<Registration> {
+        let registration = Registration {
+            path: self.path(instance.pid),
+            instance,
+        };
+        registration.write()?;
+        track_artifact(registration.path.clone());
+        debug!(path = %registration.path.display(), "Registered instance");
+        Ok(registration)
+    }
+
+    /// Every running instance. Entries left behind by instances that died without unregistering
+    /// are removed.
+    pub fn instances(&self) -> Vec<Instance> {
+        self.prune(is_running)
+    }
+
+    /// Instances in the same graphical session as this process
+    pub fn peers(&self) -> Vec<Instance> {
+        let session = current_session();
+        self.instances()
+            .into_iter()
+            .filter(|instance| instance.session == session)
+            .collect()
+    }
+
+    /// Remove what an instance with `pid` that died without cleaning up may have left next to its
+    /// entry
+    fn remove_leftovers(&self, pid: u32) {
+        let temp = self.path(pid).with_extension("tmp");
+        for path in [
+            self.socket_path(pid),
+            self.empty_marker_path(pid),
+            self.pending_path(pid),
+            temp,
+        ] {
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// Read every entry, removing those for which `is_live` is false and skipping unreadable ones
+    fn prune(&self, is_live: impl Fn(u32) -> bool) -> Vec<Instance> {
+        let Ok(entries) = fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .filter_map(|path| match read_instance(&path) {
+                Ok(instance) if is_live(instance.pid) => Some(instance),
+                Ok(instance) => {
+                    info!(pid = instance.pid, "Removing stale instance");
+                    let _ = fs::remove_file(&path);
+                    self.remove_leftovers(instance.pid);
+                    None
+                }
+                Err(e) => {
+                    warn!(path = %path.display(), error = %e, "Skipping unreadable instance");
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Whether one of `instances` started less than `window` ago, which makes a new launch a
+/// repeated keybind rather than a new recording
+pub fn is_bounce(instances: &[Instance], window: Duration) -> bool {
+    instances.iter().any(|instance| instance.elapsed() < window)
+}
+
+/// The detached session among `instances` to attach to: `pid` if given, otherwise the most
+/// recently started one
+pub fn find_detached(instances: Vec<Instance>, pid: Option<u32>) -> Option<Instance> {
+    instances
+        .into_iter()
+        .filter(|instance| instance.detached)
+        .filter(|instance| pid.is_none_or(|pid| instance.pid == pid))
+        .max_by_key(|instance| instance.started)
+}
+
+fn read_instance(path: &Path) -> Result<Instance> {
+    Ok(serde_json::from_slice(&fs::read(path)?)?)
+}
+
+/// Whether `pid` is still a wrapper process, rather than exited (or reused by something else)
+fn is_running(pid: u32) -> bool {
+    fs::read_to_string(format!("/proc/{pid}/comm"))
+        .is_ok_and(|comm| comm.trim_end() == env!("CARGO_PKG_NAME"))
+}
+
+/// Registry entries of this process, for the exit paths that skip [`Registration`]'s destructor
+static ARTIFACTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+fn artifacts() -> MutexGuard<'static, Vec<PathBuf>> {
+    // A panic while holding the lock must not keep the panic hook from cleaning up
+    ARTIFACTS.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Remove `path` along with the registry entries on the exit paths that skip destructors
+pub fn track_artifact(path: PathBuf) {
+    artifacts().push(path);
+}
+
+/// Stop tracking `path`, before removing it. False if it was no longer tracked, i.e.
+/// [`remove_artifacts`] already removed it.
+pub fn untrack_artifact(path: &Path) -> bool {
+    let mut artifacts = artifacts();
+    let tracked = artifacts.len();
+    artifacts.retain(|artifact| artifact != path);
+    artifacts.len() != tracked
+}
+
+/// Remove every registry entry of this process, so that a crash, a termination signal or a model
+/// still held at exit doesn't leave one behind for `status` and the panic combo to trip over
+pub fn remove_artifacts() {
+    for path in artifacts().drain(..) {
+        let _ = fs::remove_file(path.with_extension("tmp"));
+        if fs::remove_file(&path).is_ok() {
+            debug!(path = %path.display(), "Removed instance");
+        }
+    }
+}
+
+/// Keeps the tests that track artifacts from running while another one removes them all
+#[cfg(test)]
+pub fn serialize_tracking() -> MutexGuard<'static, ()> {
+    static TRACKING: Mutex<()> = Mutex::new(());
+    TRACKING.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Remove this process's registry entries before reporting a panic
+pub fn install_panic_hook() {
+    let report = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        remove_artifacts();
+        report(info);
+    }));
+}
+
+/// An entry in the registry, removed when dropped
+pub struct Registration {
+    path: PathBuf,
+    instance: Instance,
+}
+
+impl Registration {
+    /// Publish a new session state for this instance
+    pub fn set_state(&mut self, state: SessionState) {
+        self.instance.state = state;
+        self.update();
+    }
+
+    /// Publish that the overlay is gone, so that `attach` can bring it back
+    pub fn set_detached(&mut self) {
+        self.instance.detached = true;
+        self.update();
+    }
+
+    fn update(&self) {
+        if let Err(e) = self.write() {
+            warn!(path = %self.path.display(), error = %e, "Failed to update instance");
+        }
+    }
+
+    /// Write the entry atomically, so that readers never see a partial file
+    fn write(&self) -> Result<()> {
+        let temp = self.path.with_extension("tmp");
+        fs::write(&temp, serde_json::to_vec(&self.instance)?)?;
+        fs::rename(&temp, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for Registration {
+    fn drop(&mut self) {
+        if !untrack_artifact(&self.path) {
+            return;
+        }
+        if let Err(e) = fs::remove_file(&self.path) {
+            warn!(path = %self.path.display(), error = %e, "Failed to unregister instance");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_registry(name: &str) -> Registry {
+        let dir = std::env::temp_dir().join(format!(
+            "waystt-wrapper-registry-{name}-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        Registry::open_in(&dir).unwrap()
+    }
+
+    fn instance(pid: u32) -> Instance {
+        Instance {
+            pid,
+            session: Some("wayland-1".to_string()),
+            state: SessionState::Recording,
+            started: 1_700_000_000_000,
+            detached: false,
+        }
+    }
+
+    #[test]
+    fn test_register_and_unregister() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("register");
+
+        let registration = registry.register(instance(42)).unwrap();
+        assert_eq!(registry.prune(|_| true), vec![instance(42)]);
+
+        drop(registration);
+        assert!(registry.prune(|_| true).is_empty());
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_registrations_are_tracked_for_cleanup() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("tracked");
+        let registration = registry.register(instance(42)).unwrap();
+        assert!(artifacts().contains(&registry.path(42)));
+
+        drop(registration);
+        assert!(!artifacts().contains(&registry.path(42)));
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_nothing_is_left_behind_at_exit() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("exit");
+        // Still held when the wrapper exits, as the model's is
+        let registration = registry.register(instance(42)).unwrap();
+        let socket = registry.socket_path(42);
+        fs::write(&socket, "").unwrap();
+        track_artifact(socket.clone());
+
+        remove_artifacts();
+        assert!(artifacts().is_empty());
+        assert!(!registry.path(42).exists());
+        assert!(!socket.exists());
+        // Dropping it afterwards finds nothing left to do
+        drop(registration);
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_set_state() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("state");
+        let mut registration = registry.register(instance(42)).unwrap();
+        registration.set_state(SessionState::Processing);
+        assert_eq!(registry.prune(|_| true)[0].state, SessionState::Processing);
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_set_detached() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("detached");
+        let mut registration = registry.register(instance(42)).unwrap();
+        registration.set_detached();
+        assert!(registry.prune(|_| true)[0].detached);
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_find_detached() {
+        let detached = |pid, started| Instance {
+            started,
+            detached: true,
+            ..instance(pid)
+        };
+        let instances = vec![detached(1, 100), instance(2), detached(3, 200)];
+        assert_eq!(find_detached(instances.clone(), None).map(|i| i.pid), Some(3));
+        assert_eq!(find_detached(instances.clone(), Some(1)).map(|i| i.pid), Some(1));
+        // Only detached sessions can be attached to
+        assert_eq!(find_detached(instances, Some(2)), None);
+    }
+
+    #[test]
+    fn test_is_bounce() {
+        let window = Duration::from_millis(200);
+        assert!(!is_bounce(&[], window));
+        assert!(!is_bounce(&[instance(1)], window));
+        assert!(is_bounce(&[instance(1), Instance::current()], window));
+        assert!(!is_bounce(&[Instance::current()], Duration::ZERO));
+    }
+
+    #[test]
+    fn test_entries_without_newer_fields_are_read() {
+        let registry = temp_registry("compat");
+        fs::write(
+            registry.dir.join("7.json"),
+            r#"{"pid":7,"session":null,"state":"processing"}"#,
+        )
+        .unwrap();
+        let instances = registry.prune(|_| true);
+        assert_eq!(instances[0].started, 0);
+        assert!(!instances[0].detached);
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_stale_entries_are_removed() {
+        let _tracking = serialize_tracking();
+        let registry = temp_registry("stale");
+        let live = registry.register(instance(1)).unwrap();
+        let stale = registry.register(instance(2)).unwrap();
+        // Simulate an instance that died without unregistering
+        std::mem::forget(stale);
+
+        let leftovers = [
+            registry.socket_path(2),
+            registry.empty_marker_path(2),
+            registry.pending_path(2),
+            registry.path(2).with_extension("tmp"),
+        ];
+        for path in &leftovers {
+            fs::write(path, "").unwrap();
+        }
+
+        assert_eq!(registry.prune(|pid| pid == 1), vec![instance(1)]);
+        assert!(!registry.path(2).exists());
+        assert!(leftovers.iter().all(|path| !path.exists()));
+        assert!(registry.path(1).exists());
+        drop(live);
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_unreadable_entries_are_skipped() {
+        let registry = temp_registry("unreadable");
+        fs::write(registry.dir.join("1.json"), "not json").unwrap();
+        fs::write(registry.dir.join("notes.txt"), "ignored").unwrap();
+        assert!(registry.prune(|_| true).is_empty());
+        let _ = fs::remove_dir_all(&registry.dir);
+    }
+
+    #[test]
+    fn test_other_processes_are_not_running() {
+        // The test binary is not called waystt-wrapper
+        assert!(!is_running(std::process::id()));
+    }
+}
@@ -0,0 +1,300 @@
+//! Development tasks for waystt-wrapper.
+//!
+//! `cargo xtask e2e` builds the wrapper, starts a nested headless sway, and drives the wrapper
+//! end-to-end: spawning it, injecting keys through the virtual keyboard protocol (`wtype`),
+//! signalling it, and asserting on its exit code.
+//!
+//! Requires `sway` and `wtype` in `PATH`.
+
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitCode, ExitStatus, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to wait for the compositor socket and for the wrapper to exit
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long to give the wrapper to map its overlay and grab the keyboard
+const SETTLE: Duration = Duration::from_millis(1500);
+
+const SWAY_CONFIG: &str = "output HEADLESS-1 resolution 1280x720\n";
+
+type Result<T> = std::result::Result<T, String>;
+
+/// Something to do to the running wrapper
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Wait(Duration),
+    /// Press a key by its xkb keysym name, via `wtype -k`
+    Key(&'static str),
+    /// Send a signal (by name, e.g. `USR1`) to the wrapper itself
+    Signal(&'static str),
+}
+
+/// One end-to-end scenario
+struct Case {
+    name: &'static str,
+    /// Wrapper options, before the `--` separating the child command
+    args: &'static [&'static str],
+    /// Options for the child, which is the wrapper's own hidden `fake-child` subcommand
+    child: &'static [&'static str],
+    steps: &'static [Step],
+    expected_code: i32,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        name: "escape stops the child",
+        args: &[],
+        child: &[],
+        steps: &[Step::Wait(SETTLE), Step::Key("Escape")],
+        expected_code: 0,
+    },
+    Case {
+        name: "SIGUSR1 to the wrapper stops the child",
+        args: &["--keyboard-mode", "none"],
+        child: &[],
+        steps: &[Step::Wait(SETTLE), Step::Signal("USR1")],
+        expected_code: 0,
+    },
+    Case {
+        name: "child exit code propagates",
+        args: &[],
+        child: &["--exit-code", "3", "--exit-after-ms", "1000"],
+        steps: &[],
+        expected_code: 3,
+    },
+    Case {
+        name: "repeated escape escalates to SIGKILL",
+        args: &[],
+        child: &["--ignore-stop"],
+        steps: &[
+            Step::Wait(SETTLE),
+            Step::Key("Escape"),
+            Step::Wait(Duration::from_millis(300)),
+            Step::Key("Escape"),
+            Step::Wait(Duration::from_millis(300)),
+            Step::Key("Escape"),
+        ],
+        // Killed by a signal, so there is no exit code to propagate
+        expected_code: 1,
+    },
+    Case {
+        name: "hung child is killed after the kill timeout",
+        args: &["--kill-timeout", "1"],
+        child: &["--ignore-stop"],
+        steps: &[Step::Wait(SETTLE), Step::Key("Escape")],
+        expected_code: 1,
+    },
+];
+
+/// A nested headless sway, torn down (with its runtime dir) on drop
+struct Compositor {
+    process: Child,
+    runtime_dir: PathBuf,
+    wayland_display: String,
+}
+
+impl Compositor {
+    fn start() -> Result<Self> {
+        let runtime_dir =
+            std::env::temp_dir().join(format!("waystt-wrapper-e2e-{}", std::process::id()));
+        DirBuilder::new()
+            .mode(0o700)
+            .recursive(true)
+            .create(&runtime_dir)
+            .map_err(|e| format!("creating {}: {e}", runtime_dir.display()))?;
+
+        let config = runtime_dir.join("sway.conf");
+        fs::write(&config, SWAY_CONFIG).map_err(|e| format!("writing sway config: {e}"))?;
+
+        let process = Command::new("sway")
+            .arg("--config")
+            .arg(&config)
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env("WLR_BACKENDS", "headless")
+            .env("WLR_HEADLESS_OUTPUTS", "1")
+            .env("WLR_LIBINPUT_NO_DEVICES", "1")
+            .env("WLR_RENDERER", "pixman")
+            .env_remove("WAYLAND_DISPLAY")
+            .env_remove("DISPLAY")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("starting sway: {e}"))?;
+
+        let mut compositor = Self {
+            process,
+            runtime_dir,
+            wayland_display: String::new(),
+        };
+        compositor.wayland_display = compositor.wait_for_socket()?;
+        Ok(compositor)
+    }
+
+    fn wait_for_socket(&mut self) -> Result<String> {
+        let deadline = Instant::now() + TIMEOUT;
+        while Instant::now() < deadline {
+            if let Ok(Some(status)) = self.process.try_wait() {
+                return Err(format!("sway exited early: {status}"));
+            }
+            if let Some(name) = find_wayland_socket(&self.runtime_dir) {
+                return Ok(name);
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        Err("timed out waiting for the sway socket".to_string())
+    }
+
+    /// A command running as a client of this compositor
+    fn command(&self, program: impl AsRef<std::ffi::OsStr>) -> Command {
+        let mut command = Command::new(program);
+        command
+            .env("XDG_RUNTIME_DIR", &self.runtime_dir)
+            .env("WAYLAND_DISPLAY", &self.wayland_display)
+            .env("GDK_BACKEND", "wayland")
+            // Keep first-run state (e.g. the onboarding panel) out of the real home directory
+            .env("XDG_STATE_HOME", &self.runtime_dir)
+            .env_remove("DISPLAY");
+        command
+    }
+}
+
+impl Drop for Compositor {
+    fn drop(&mut self) {
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+        let _ = fs::remove_dir_all(&self.runtime_dir);
+    }
+}
+
+fn find_wayland_socket(runtime_dir: &Path) -> Option<String> {
+    fs::read_dir(runtime_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .find(|name| name.starts_with("wayland-") && !name.ends_with(".lock"))
+}
+
+fn status_checked(command: &mut Command) -> Result<()> {
+    let status = command.status().map_err(|e| format!("{command:?}: {e}"))?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(format!("{command:?} failed: {status}"))
+    }
+}
+
+fn wait_with_timeout(child: &mut Child, timeout: Duration) -> io::Result<Option<ExitStatus>> {
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    Ok(None)
+}
+
+fn run_case(compositor: &Compositor, binary: &Path, case: &Case) -> Result<()> {
+    let mut wrapper = compositor
+        .command(binary)
+        .args(case.args)
+        .arg("--")
+        .arg(binary)
+        .arg("fake-child")
+        .args(case.child)
+        .stdout(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("spawning wrapper: {e}"))?;
+
+    let result = (|| {
+        for step in case.steps {
+            match *step {
+                Step::Wait(duration) => thread::sleep(duration),
+                Step::Key(key) => status_checked(compositor.command("wtype").arg("-k").arg(key))?,
+                Step::Signal(signal) => status_checked(
+                    Command::new("kill")
+                        .arg(format!("-{signal}"))
+                        .arg(wrapper.id().to_string()),
+                )?,
+            }
+        }
+
+        let status = wait_with_timeout(&mut wrapper, TIMEOUT)
+            .map_err(|e| format!("waiting for wrapper: {e}"))?
+            .ok_or_else(|| "wrapper did not exit".to_string())?;
+        match status.code() {
+            Some(code) if code == case.expected_code => Ok(()),
+            _ => Err(format!(
+                "expected exit code {}, got {status}",
+                case.expected_code
+            )),
+        }
+    })();
+
+    if result.is_err() {
+        let _ = wrapper.kill();
+        let _ = wrapper.wait();
+    }
+    result
+}
+
+fn build_wrapper() -> Result<PathBuf> {
+    let cargo = std::env::var("CARGO").unwrap_or_else(|_| "cargo".to_string());
+    status_checked(Command::new(cargo).args(["build", "--package", "waystt-wrapper"]))?;
+
+    let workspace = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask lives inside the workspace");
+    let target_dir = std::env::var_os("CARGO_TARGET_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| workspace.join("target"));
+    Ok(target_dir.join("debug").join("waystt-wrapper"))
+}
+
+fn e2e() -> Result<()> {
+    let binary = build_wrapper()?;
+    let compositor = Compositor::start()?;
+    eprintln!("headless sway running on {}", compositor.wayland_display);
+
+    let mut failures = 0;
+    for case in CASES {
+        match run_case(&compositor, &binary, case) {
+            Ok(()) => eprintln!("ok      {}", case.name),
+            Err(e) => {
+                failures += 1;
+                eprintln!("FAILED  {}: {e}", case.name);
+            }
+        }
+    }
+
+    eprintln!("{} passed, {failures} failed", CASES.len() - failures);
+    if failures == 0 {
+        Ok(())
+    } else {
+        Err(format!("{failures} end-to-end case(s) failed"))
+    }
+}
+
+fn main() -> ExitCode {
+    let task = std::env::args().nth(1);
+    let result = match task.as_deref() {
+        Some("e2e") => e2e(),
+        _ => {
+            eprintln!("usage: cargo xtask e2e");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}